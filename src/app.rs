@@ -1,12 +1,26 @@
-use std::sync::mpsc::{channel, Receiver};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 
 use winit::application::ApplicationHandler;
 use winit::event::{ElementState, MouseScrollDelta, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{Window, WindowId};
 
-use crate::wgpu_ctx::InstanceData;
+use crate::automaton::{Automaton, AutomatonLayer, Rule};
+use crate::bindings::{MouseAction, MouseBindings};
+use crate::debug_draw::DebugOverlays;
+use crate::fluid::FluidLayer;
+use crate::input;
+use crate::obstacle::ObstacleLayer;
+use crate::query::ParticleIndex;
+use crate::rigid::BodyVertex;
+use crate::render_graph::RenderGraph;
+use crate::screenshot::{self, ScreenshotSettings};
+use crate::sim::{Particle, SimCommand, SimConfig, Snapshot, Timeline, TimeStep};
+use crate::stats::StatsHistory;
+use crate::tools::{Tool, Tools};
+use crate::ui;
 use crate::wgpu_ctx::WgpuCtx;
 
 pub struct ImguiState {
@@ -20,8 +34,41 @@ pub struct ImguiState {
 pub struct App<'window> {
     pub window: Option<Arc<Window>>,
     pub wgpu_ctx: Option<WgpuCtx<'window>>,
-    pub instance_receiver: Receiver<Vec<InstanceData>>,
+    pub snapshot_receiver: Receiver<Snapshot>,
+    pub command_sender: Sender<SimCommand>,
+    pub timeline: Timeline,
+    pub time_step: TimeStep,
+    pub physics: ui::PhysicsSettings,
+    pub sim_config: SimConfig,
+    pub obstacle_loader: ui::ObstacleLoader,
+    pub obstacles: Vec<ObstacleLayer>,
+    pub body_spawner: ui::BodySpawner,
+    pub fluid: Option<FluidLayer>,
+    pub fluid_settings: ui::FluidSettings,
+    // Last world position and time while stirring the fluid
+    pub fluid_stirring: Option<([f32; 2], std::time::Instant)>,
+    pub automaton: Option<AutomatonLayer>,
+    pub automaton_settings: ui::AutomatonSettings,
+    // Cell state being painted while dragging over the automaton
+    pub painting_cells: Option<bool>,
+    pub stats: StatsHistory,
+    // Latest snapshot contents, re-uploaded every frame relative to the camera
+    pub shown_particles: Vec<Particle>,
+    pub shown_bodies: Vec<BodyVertex>,
+    pub shown_bounds: [f32; 2],
+    // Spatial index of the latest snapshot, for picking and area tools
+    pub particles: ParticleIndex,
     pub mouse_position: Option<[f32; 2]>,
+    pub tools: Tools,
+    pub bindings: MouseBindings,
+    // Space turns left-drag into panning whatever the tool
+    pub space_held: bool,
+    pub screenshot: ScreenshotSettings,
+    pub recorder: ui::Recorder,
+    pub colors: ui::ColorSettings,
+    // Colormap positions of the shown particles, empty when tinted by species
+    pub color_values: Vec<f32>,
+    pub debug_overlays: DebugOverlays,
     pub imgui: Option<ImguiState>,
     pub input: input_actions::System,
 }
@@ -99,25 +146,101 @@ impl<'window> App<'window> {
                 .prepare_frame(imgui_state.context.io_mut(), window)
                 .expect("Failed to prepare frame");
 
+            // A supersampled capture with the overlay lays the UI out at the capture's scale,
+            // so that frame goes only to the capture and the window keeps the previous one
+            let capture = self.screenshot.take_request();
+            let scaled_ui = capture.filter(|capture| capture.include_ui && capture.scale > 1);
+            // The platform only sets the framebuffer scale on attach and on scale factor
+            // changes, so it's put back once the frame is rendered
+            let window_scale = imgui_state.context.io().display_framebuffer_scale;
+            if let Some(capture) = scaled_ui {
+                let config = &wgpu_ctx.surface_config;
+                let [width, height] = screenshot::capture_size(&wgpu_ctx.device, config, capture.scale);
+                imgui_state.context.io_mut().display_framebuffer_scale = [
+                    window_scale[0] * width as f32 / config.width as f32,
+                    window_scale[1] * height as f32 / config.height as f32,
+                ];
+            }
+
             // Build your ImGui UI
             let ui = imgui_state.context.frame();
             ui.window("Debug")
                 .size([300.0, 200.0], imgui::Condition::FirstUseEver)
                 .build(|| {
-                    ui.text("Hello from ImGui!");
                     ui.text(format!("FPS: {:.1}", ui.io().framerate));
+                    let hovered = self.mouse_position.and_then(|position| {
+                        let world = wgpu_ctx.camera.screen_to_world(position).map(|v| v as f32);
+                        self.particles.nearest(world, self.particles.radius)
+                    });
+                    if let Some(hit) = hovered {
+                        ui.text(format!(
+                            "Hovered: #{} at ({:.1}, {:.1})",
+                            hit.index, hit.position[0], hit.position[1]
+                        ));
+                    }
+                    ui.separator();
+                    let overlays = &mut self.debug_overlays;
+                    ui.checkbox("Sim bounds", &mut overlays.bounds);
+                    ui.checkbox("Grid cells", &mut overlays.grid);
+                    ui.checkbox("Velocity vectors", &mut overlays.velocities);
+                    if overlays.velocities {
+                        ui.slider("Vector scale", 0.01, 5.0, &mut overlays.velocity_scale);
+                    }
+                    ui.checkbox("Contacts", &mut overlays.contacts);
+                    ui.checkbox("Ray from view centre to cursor", &mut overlays.ray);
                 });
+            ui::stats_window(ui, &mut self.stats);
+            ui::timeline_window(ui, &self.timeline, &self.command_sender);
+            ui::time_step_window(ui, &mut self.time_step, &self.command_sender);
+            ui::physics_window(ui, &mut self.physics, &self.command_sender);
+            if ui::initial_conditions_window(ui, &mut self.sim_config, &self.command_sender) {
+                self.stats.clear();
+            }
+            ui::obstacle_window(ui, &mut self.obstacle_loader, &self.command_sender);
+            ui::rigid_body_window(ui, &mut self.body_spawner, &self.command_sender);
+            ui::tool_window(ui, &mut self.tools, &mut self.bindings);
+            ui::inspector_window(ui, &self.shown_particles, &mut self.tools.selection, &self.command_sender);
+            ui::screenshot_window(ui, &mut self.screenshot);
+            ui::record_window(
+                ui,
+                &mut self.recorder,
+                |settings| {
+                    settings.camera_position = wgpu_ctx.camera.position;
+                    settings.zoom = wgpu_ctx.camera.zoom;
+                    settings.particle_style = wgpu_ctx.particle_style;
+                    settings.trails = wgpu_ctx.trails;
+                    settings.post = wgpu_ctx.post.clone();
+                    settings.coloring = self.colors.coloring.clone();
+                },
+                &self.command_sender,
+            );
+            let heatmap_active = wgpu_ctx.heatmap_active();
+            ui::render_window(
+                ui,
+                &mut wgpu_ctx.particle_style,
+                &mut wgpu_ctx.lod,
+                &mut wgpu_ctx.trails,
+                &mut wgpu_ctx.grid,
+                heatmap_active,
+                &wgpu_ctx.graph_cache.timings,
+            );
+            ui::post_window(ui, &mut wgpu_ctx.post);
+            ui::color_window(ui, &mut self.colors);
+            self.tools.frame(&self.command_sender);
+            if let Some((start, end)) = self.tools.selection.dragging {
+                let camera = &wgpu_ctx.camera;
+                let [a, b] = [start, end].map(|corner| camera.world_to_screen(corner.map(|v| v as f64)));
+                let draw_list = ui.get_background_draw_list();
+                draw_list
+                    .add_rect(a, b, [1.0, 0.5, 0.2, 0.15])
+                    .filled(true)
+                    .build();
+                draw_list.add_rect(a, b, [1.0, 0.5, 0.2, 1.0]).build();
+            }
+            ui::fluid_window(ui, &mut self.fluid_settings, &self.bindings);
+            ui::automaton_window(ui, &mut self.automaton_settings, self.automaton.as_mut(), &self.bindings);
 
-            // Acquire the swap chain texture only once
-            let output = wgpu_ctx
-                .surface
-                .get_current_texture()
-                .expect("Failed to get current texture");
-            let view = output
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor::default());
-
-            // Create one command encoder for both passes
+            // One command encoder for the window and any capture
             let mut encoder =
                 wgpu_ctx
                     .device
@@ -125,8 +248,84 @@ impl<'window> App<'window> {
                         label: Some("Main Command Encoder"),
                     });
 
-            if let Ok(new_instances) = self.instance_receiver.try_recv() {
-                wgpu_ctx.update_instances(&new_instances);
+            // Record stats from every tick, but only keep the newest particles
+            let mut latest = None;
+            while let Ok(snapshot) = self.snapshot_receiver.try_recv() {
+                // Scrubbed ticks are already in the plots
+                if !snapshot.timeline.paused {
+                    self.stats.push(snapshot.stats);
+                }
+                self.timeline = snapshot.timeline;
+                latest = Some(snapshot);
+            }
+            if let Some(snapshot) = latest {
+                self.stats.speed_histogram = snapshot.speed_histogram;
+                self.shown_particles = snapshot.particles;
+                self.shown_bodies = snapshot.bodies;
+                self.shown_bounds = snapshot.bounds;
+                self.particles = snapshot.index;
+            }
+            let coloring = &mut self.colors.coloring;
+            coloring.evaluate(&self.shown_particles, &self.particles, &mut self.color_values);
+            // The heatmap uses the colormap too, whatever the particles are coloured by
+            wgpu_ctx.set_colormap(&coloring.table());
+            wgpu_ctx.update_instances(&self.shown_particles, &self.tools.selection.indices, &self.color_values);
+            wgpu_ctx.update_polygons(&self.shown_bodies);
+
+            // Step the grid fluid at the frame rate, capped to keep it stable after stalls
+            if !self.fluid_settings.enabled {
+                self.fluid = None;
+            } else if self.fluid.is_none() || self.fluid_settings.rebuild {
+                self.fluid = Some(FluidLayer::new(wgpu_ctx, self.fluid_settings.config));
+                self.fluid_settings.rebuild = false;
+            }
+            if let Some(layer) = self.fluid.as_mut() {
+                let config = &mut layer.fluid.config;
+                config.pressure_iterations = self.fluid_settings.config.pressure_iterations;
+                config.vorticity = self.fluid_settings.config.vorticity;
+                config.velocity_dissipation = self.fluid_settings.config.velocity_dissipation;
+                config.dye_dissipation = self.fluid_settings.config.dye_dissipation;
+                if !self.fluid_settings.paused {
+                    let dt = ui.io().delta_time.min(1.0 / 30.0);
+                    layer.fluid.step(dt);
+                }
+                layer.upload(&wgpu_ctx.queue, &wgpu_ctx.camera, self.fluid_settings.view);
+            }
+
+            if !self.automaton_settings.enabled {
+                self.automaton = None;
+            } else if self.automaton.is_none() || self.automaton_settings.rebuild {
+                let settings = &self.automaton_settings;
+                let rule = settings.rule_text.parse().unwrap_or(Rule::LIFE);
+                let automaton = Automaton::new(settings.size[0] as usize, settings.size[1] as usize, rule);
+                self.automaton = Some(AutomatonLayer::new(
+                    wgpu_ctx,
+                    automaton,
+                    settings.origin,
+                    settings.cell_size,
+                ));
+                self.automaton_settings.rebuild = false;
+            }
+            if let Some(layer) = self.automaton.as_mut() {
+                if self.automaton_settings.running {
+                    for _ in 0..self.automaton_settings.steps_per_frame {
+                        layer.automaton.step();
+                    }
+                }
+                layer.upload(&wgpu_ctx.queue, &wgpu_ctx.camera);
+            }
+
+            if self.obstacle_loader.rebuild {
+                self.obstacles = self
+                    .obstacle_loader
+                    .added
+                    .iter()
+                    .map(|obstacle| ObstacleLayer::new(wgpu_ctx, obstacle))
+                    .collect();
+                self.obstacle_loader.rebuild = false;
+            }
+            for layer in &self.obstacles {
+                layer.set_view(&wgpu_ctx.queue, &wgpu_ctx.camera);
             }
 
             let view_matrix = wgpu_ctx.camera.get_view_matrix();
@@ -136,78 +335,184 @@ impl<'window> App<'window> {
                 bytemuck::cast_slice(&view_matrix),
             );
 
-            // --- Scene Render Pass ---
+            // Gizmos from anywhere in the frame are in the debug batch by now
+            let [width, height] = [wgpu_ctx.surface_config.width, wgpu_ctx.surface_config.height].map(|v| v as f32);
+            let view_min = wgpu_ctx.camera.screen_to_world([0.0, height]);
+            let view_max = wgpu_ctx.camera.screen_to_world([width, 0.0]);
+            let cursor = self.mouse_position.map(|position| wgpu_ctx.camera.screen_to_world(position));
+            self.debug_overlays.draw(
+                &mut wgpu_ctx.debug,
+                &self.shown_particles,
+                &self.particles,
+                self.shown_bounds,
+                [view_min, view_max],
+                cursor,
+            );
+            // The renderer has no fonts, so labels go through the UI
             {
-                let mut scene_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: Some("Scene Render Pass"),
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                            store: wgpu::StoreOp::Store,
-                        },
-                    })],
-                    depth_stencil_attachment: None,
-                    timestamp_writes: None,
-                    occlusion_query_set: None,
-                });
-
-                // Render your scene
-                scene_pass.set_pipeline(&wgpu_ctx.render_pipeline);
-                scene_pass.set_vertex_buffer(0, wgpu_ctx.vertex_buffer.slice(..));
-                scene_pass.set_vertex_buffer(1, wgpu_ctx.instance_buffer.slice(..));
-                scene_pass.set_bind_group(0, &wgpu_ctx.uniform_bind_group, &[]);
-                scene_pass.draw(0..3, 0..wgpu_ctx.num_instances);
-            } // End scene pass
+                let draw_list = ui.get_background_draw_list();
+                for label in wgpu_ctx.debug.labels() {
+                    draw_list.add_text(wgpu_ctx.camera.world_to_screen(label.position), label.color, &label.text);
+                }
+            }
+            wgpu_ctx.upload_debug();
+            ui::grid_labels(ui, &wgpu_ctx.camera, &wgpu_ctx.grid, [width, height], self.mouse_position);
 
-            // --- ImGui Render Pass ---
-            {
-                // Use Load so that the scene remains intact
-                let mut imgui_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: Some("ImGui Render Pass"),
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: wgpu::StoreOp::Store,
-                        },
-                    })],
-                    depth_stencil_attachment: None,
-                    timestamp_writes: None,
-                    occlusion_query_set: None,
+            let output = scaled_ui.is_none().then(|| wgpu_ctx.acquire_frame());
+            let draw_data = imgui_state.context.render();
+            let renderer = &mut imgui_state.renderer;
+            // Taken out while the graphs borrow the context, and put back after submitting
+            let mut cache = std::mem::take(&mut wgpu_ctx.graph_cache);
+            let ctx: &WgpuCtx = wgpu_ctx;
+            if let Some(output) = output.as_ref() {
+                let view = output
+                    .texture()
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                let mut graph = RenderGraph::new(ctx.surface_config.width, ctx.surface_config.height);
+                let target = graph.import_texture(&view);
+                let layers = scene_layers(ctx, self.fluid.as_ref(), self.automaton.as_ref(), &self.obstacles);
+                let scene = ctx.add_scene_nodes(&mut graph, true, layers);
+                ctx.add_output_nodes(&mut graph, scene, target);
+                // Loads the composited scene and draws over it
+                graph.add_node("ImGui", &[target], &[target], |encoder, resources| {
+                    let mut imgui_pass = begin_overlay_pass(encoder, resources.view(target));
+                    renderer
+                        .render(draw_data, &ctx.queue, &ctx.device, &mut imgui_pass)
+                        .expect("Failed to render ImGui");
                 });
+                graph.execute(&ctx.device, &mut encoder, &mut cache);
+            }
 
-                // Render ImGui draw data on top of the scene
-                let draw_data = imgui_state.context.render();
-                imgui_state
-                    .renderer
-                    .render(
-                        draw_data,
-                        &wgpu_ctx.queue,
-                        &wgpu_ctx.device,
-                        &mut imgui_pass,
-                    )
-                    .expect("Failed to render ImGui");
-            } // End ImGui pass
+            // Captures redraw the frame into a texture of their own, since the
+            // swapchain image can't be read back on every platform
+            let captured = capture.map(|capture| {
+                let texture = screenshot::capture_texture(&ctx.device, &ctx.surface_config, capture.scale);
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let mut graph = RenderGraph::new(texture.width(), texture.height());
+                let target = graph.import_texture(&view);
+                let scene = if output.is_some() && capture.scale == 1 {
+                    // Same size as the window, so reuse this frame's scene, trails included
+                    graph.import_texture(ctx.scene_history())
+                } else {
+                    let layers = scene_layers(ctx, self.fluid.as_ref(), self.automaton.as_ref(), &self.obstacles);
+                    ctx.add_scene_nodes(&mut graph, false, layers)
+                };
+                ctx.add_output_nodes(&mut graph, scene, target);
+                if capture.include_ui {
+                    let renderer = &mut *renderer;
+                    graph.add_node("ImGui", &[target], &[target], |encoder, resources| {
+                        // Fresh render data so the scissor rects follow the capture's size
+                        let render_data = renderer.prepare(draw_data, None, &ctx.queue, &ctx.device);
+                        let mut imgui_pass = begin_overlay_pass(encoder, resources.view(target));
+                        renderer
+                            .split_render(draw_data, &render_data, &mut imgui_pass)
+                            .expect("Failed to render ImGui");
+                    });
+                }
+                graph.execute_untimed(&ctx.device, &mut encoder, &mut cache);
+                texture
+            });
+            // Done with this frame's draw data
+            imgui_state.context.io_mut().display_framebuffer_scale = window_scale;
 
             // Submit all commands and present
             wgpu_ctx.queue.submit(Some(encoder.finish()));
-            output.present();
+            wgpu_ctx.graph_cache = cache;
+            wgpu_ctx.graph_cache.after_submit(&wgpu_ctx.device, &wgpu_ctx.queue);
+            if let Some(output) = output {
+                output.present();
+            }
+            if let Some(texture) = captured {
+                let image = wgpu_ctx.read_texture(&texture);
+                screenshot::save_in_background(image, &self.screenshot.directory);
+            }
+        }
+    }
+}
+
+/// Draws the world layers under the bodies and particles of the scene pass.
+fn scene_layers<'a>(
+    wgpu_ctx: &'a WgpuCtx,
+    fluid: Option<&'a FluidLayer>,
+    automaton: Option<&'a AutomatonLayer>,
+    obstacles: &'a [ObstacleLayer],
+) -> impl FnOnce(&mut wgpu::RenderPass<'_>) + 'a {
+    move |pass| {
+        // The fluid is drawn under the particles
+        if let Some(layer) = fluid {
+            layer.quad.draw(pass, &wgpu_ctx.uniform_bind_group);
+        }
+        if let Some(layer) = automaton {
+            layer.quad.draw(pass, &wgpu_ctx.uniform_bind_group);
+        }
+        for layer in obstacles {
+            layer.quad.draw(pass, &wgpu_ctx.uniform_bind_group);
         }
     }
 }
 
+/// A pass that draws over what is already in `view`.
+fn begin_overlay_pass<'encoder>(
+    encoder: &'encoder mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+) -> wgpu::RenderPass<'encoder> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("ImGui Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
 impl App<'_> {
-    pub fn new(instance_receiver: Receiver<Vec<InstanceData>>) -> Self {
+    pub fn new(
+        sim_config: SimConfig,
+        time_step: TimeStep,
+        snapshot_receiver: Receiver<Snapshot>,
+        command_sender: Sender<SimCommand>,
+    ) -> Self {
         Self {
-            instance_receiver,
+            snapshot_receiver,
+            command_sender,
+            timeline: Timeline::default(),
+            time_step,
+            physics: ui::PhysicsSettings::default(),
+            sim_config,
+            obstacle_loader: ui::ObstacleLoader::default(),
+            obstacles: Vec::new(),
+            body_spawner: ui::BodySpawner::default(),
+            fluid: None,
+            fluid_settings: ui::FluidSettings::default(),
+            fluid_stirring: None,
+            automaton: None,
+            automaton_settings: ui::AutomatonSettings::default(),
+            painting_cells: None,
+            stats: StatsHistory::new(600),
+            shown_particles: Vec::new(),
+            shown_bodies: Vec::new(),
+            shown_bounds: [0.0; 2],
+            particles: ParticleIndex::default(),
             window: None,
             mouse_position: None,
+            tools: Tools::default(),
+            bindings: MouseBindings::default(),
+            space_held: false,
+            screenshot: ScreenshotSettings::default(),
+            recorder: ui::Recorder::default(),
+            colors: ui::ColorSettings::default(),
+            color_values: Vec::new(),
+            debug_overlays: DebugOverlays::default(),
             wgpu_ctx: None,
             imgui: None,
-            input: input_actions::System::new(),
+            input: input::actions(),
         }
     }
 }
@@ -231,6 +536,11 @@ impl<'window> ApplicationHandler for App<'window> {
     }
 
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        let screenshot = self.input.get_user_action(0, input::SCREENSHOT);
+        if screenshot.is_some_and(|action| action.on_button_pressed()) {
+            self.screenshot.requested = true;
+        }
+        self.input.update();
         if let Some(window) = self.window.as_ref() {
             window.request_redraw();
         }
@@ -293,21 +603,80 @@ impl<'window> ApplicationHandler for App<'window> {
                 }
             }
             WindowEvent::MouseInput { state, button, .. } => {
-                if !imgui_captures_mouse {
+                // Releases always go through so drags can't get stuck over a window
+                if !imgui_captures_mouse || state == ElementState::Released {
                     if let Some(wgpu_ctx) = self.wgpu_ctx.as_mut() {
                         match state {
                             ElementState::Pressed => {
                                 if let Some(position) = self.mouse_position {
-                                    wgpu_ctx.camera.handle_mouse_press(button, position);
+                                    let world =
+                                        wgpu_ctx.camera.screen_to_world(position).map(|v| v as f32);
+                                    // The first action on this button that applies at the cursor takes the drag
+                                    let bindings = self.bindings;
+                                    for action in bindings.actions(button) {
+                                        let started = match action {
+                                            MouseAction::Pan => {
+                                                wgpu_ctx.camera.begin_pan(position);
+                                                true
+                                            }
+                                            MouseAction::UseTool => {
+                                                // The pan tool and a held space pan instead
+                                                if self.tools.tool == Tool::Pan || self.space_held {
+                                                    wgpu_ctx.camera.begin_pan(position);
+                                                } else {
+                                                    self.tools.press(world, &self.particles, &self.command_sender);
+                                                }
+                                                true
+                                            }
+                                            MouseAction::PaintCells => {
+                                                // Toggle the clicked cell and keep painting that state
+                                                let cell = self.automaton.as_mut().and_then(|layer| {
+                                                    layer.world_to_cell(world).map(|cell| (layer, cell))
+                                                });
+                                                if let Some((layer, (x, y))) = cell {
+                                                    let alive = !layer.automaton.get(x, y);
+                                                    layer.automaton.set(x, y, alive);
+                                                    self.painting_cells = Some(alive);
+                                                }
+                                                self.painting_cells.is_some()
+                                            }
+                                            MouseAction::Stir => {
+                                                if self.fluid.is_some() {
+                                                    self.fluid_stirring =
+                                                        Some((world, std::time::Instant::now()));
+                                                }
+                                                self.fluid_stirring.is_some()
+                                            }
+                                        };
+                                        if started {
+                                            break;
+                                        }
+                                    }
                                 }
                             }
                             ElementState::Released => {
-                                wgpu_ctx.camera.handle_mouse_release(button);
+                                let bindings = self.bindings;
+                                for action in bindings.actions(button) {
+                                    match action {
+                                        MouseAction::Pan => wgpu_ctx.camera.end_pan(),
+                                        MouseAction::UseTool => {
+                                            wgpu_ctx.camera.end_pan();
+                                            self.tools.release(&self.command_sender);
+                                        }
+                                        MouseAction::PaintCells => self.painting_cells = None,
+                                        MouseAction::Stir => self.fluid_stirring = None,
+                                    }
+                                }
                             }
                         }
                     }
                 }
             }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.physical_key == PhysicalKey::Code(KeyCode::Space) {
+                    self.space_held = event.state == ElementState::Pressed;
+                }
+            }
             WindowEvent::CursorMoved { position, .. } => {
                 // Update the cursor position regardless of ImGui capture
                 self.mouse_position = Some([position.x as f32, position.y as f32]);
@@ -317,6 +686,40 @@ impl<'window> ApplicationHandler for App<'window> {
                         wgpu_ctx
                             .camera
                             .handle_mouse_move([position.x as f32, position.y as f32]);
+
+                        let world = wgpu_ctx
+                            .camera
+                            .screen_to_world([position.x as f32, position.y as f32])
+                            .map(|v| v as f32);
+                        self.tools.drag(world, &self.particles, &self.command_sender);
+
+                        if let (Some(alive), Some(layer)) =
+                            (self.painting_cells, self.automaton.as_mut())
+                        {
+                            if let Some((x, y)) = layer.world_to_cell(world) {
+                                layer.automaton.set(x, y, alive);
+                            }
+                        }
+
+                        // Stirring pushes velocity and dye into the fluid
+                        if let (Some((last_world, last_time)), Some(layer)) =
+                            (self.fluid_stirring, self.fluid.as_mut())
+                        {
+                            let now = std::time::Instant::now();
+                            let elapsed = (now - last_time).as_secs_f32().max(1e-3);
+                            let velocity = [
+                                (world[0] - last_world[0]) / elapsed,
+                                (world[1] - last_world[1]) / elapsed,
+                            ];
+                            let settings = &self.fluid_settings;
+                            layer.fluid.splat(
+                                world,
+                                [velocity[0] * settings.force, velocity[1] * settings.force],
+                                settings.dye_color,
+                                settings.splat_radius,
+                            );
+                            self.fluid_stirring = Some((world, now));
+                        }
                     }
                 }
             }
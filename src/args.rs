@@ -0,0 +1,115 @@
+use crate::presets::Preset;
+use crate::record::RecordSettings;
+use crate::sim::{SimConfig, TimeStep, MIN_SPACING};
+
+/// Options read from the command line.
+#[derive(Clone, Debug, Default)]
+pub struct Args {
+    pub config: SimConfig,
+    pub time_step: TimeStep,
+    // Set by `--record`, which renders offline instead of opening a window
+    pub record: Option<RecordSettings>,
+}
+
+impl Args {
+    /// Reads `[--preset NAME] [--seed N] [--count N] [--spacing X] [--speed X]` for the run,
+    /// `[--dt X] [--adaptive CFL] [--max-substeps N]` for its time step and,
+    /// with `--record <file>`, `[--frames N] [--size WxH] [--fps N] [--ticks-per-frame N]
+    /// [--zoom X] [--center X,Y]` for the offline render.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| format!("invalid value for {flag}: {value}"))
+        }
+        fn finite(flag: &str, value: &str) -> Result<f32, String> {
+            let number: f32 = parse(flag, value)?;
+            if number.is_finite() {
+                Ok(number)
+            } else {
+                Err(format!("{flag} must be finite, got {value}"))
+            }
+        }
+        fn pair<T: std::str::FromStr>(flag: &str, value: &str, separator: char) -> Result<[T; 2], String> {
+            let (a, b) = value
+                .split_once(separator)
+                .ok_or_else(|| format!("{flag} expects two values separated by '{separator}'"))?;
+            Ok([parse(flag, a)?, parse(flag, b)?])
+        }
+
+        let mut config = SimConfig::default();
+        let mut time_step = TimeStep::default();
+        let mut settings = RecordSettings::default();
+        let mut recording = false;
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{flag} expects a value"))?;
+            match flag.as_str() {
+                "--preset" => {
+                    config.preset = Preset::from_name(&value).ok_or_else(|| {
+                        let names: Vec<String> = Preset::ALL.iter().map(Preset::flag_name).collect();
+                        format!("unknown preset {value}, expected one of {}", names.join(", "))
+                    })?
+                }
+                "--seed" => config.seed = parse(&flag, &value)?,
+                "--count" => config.count = parse(&flag, &value)?,
+                "--spacing" => config.spacing = finite(&flag, &value)?.max(MIN_SPACING),
+                "--speed" => config.speed = finite(&flag, &value)?,
+                "--dt" => time_step.dt = parse(&flag, &value)?,
+                "--adaptive" => {
+                    time_step.cfl = parse(&flag, &value)?;
+                    time_step.adaptive = true;
+                }
+                "--max-substeps" => time_step.max_substeps = parse(&flag, &value)?,
+                "--record" => {
+                    settings.path = value;
+                    recording = true;
+                }
+                "--frames" => settings.frames = parse(&flag, &value)?,
+                "--size" => [settings.width, settings.height] = pair(&flag, &value, 'x')?,
+                "--fps" => settings.fps = parse(&flag, &value)?,
+                "--ticks-per-frame" => settings.ticks_per_frame = parse(&flag, &value)?,
+                "--zoom" => settings.zoom = parse(&flag, &value)?,
+                "--center" => settings.camera_position = pair(&flag, &value, ',')?,
+                _ => return Err(format!("unknown argument {flag}")),
+            }
+        }
+        Ok(Self {
+            config,
+            time_step,
+            record: recording.then_some(settings),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        Args::parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_run_and_record_flags() {
+        let parsed =
+            args("--preset galaxy-disk --seed 7 --count 500 --dt 0.02 --adaptive 0.3 --record out.y4m --size 64x48")
+                .unwrap();
+        assert_eq!(parsed.config.preset, Preset::GalaxyDisk);
+        assert_eq!(parsed.config.seed, 7);
+        assert_eq!(parsed.config.count, 500);
+        assert_eq!(parsed.time_step.dt, 0.02);
+        assert!(parsed.time_step.adaptive);
+        assert_eq!(parsed.time_step.cfl, 0.3);
+        let record = parsed.record.unwrap();
+        assert_eq!(record.path, "out.y4m");
+        assert_eq!([record.width, record.height], [64, 48]);
+
+        assert!(args("").unwrap().record.is_none());
+        assert!(args("--preset nope").is_err());
+        assert!(args("--seed").is_err());
+        assert!(args("--bogus 1").is_err());
+
+        assert_eq!(args("--spacing -3").unwrap().config.spacing, MIN_SPACING);
+        assert!(args("--spacing NaN").is_err());
+        assert!(args("--spacing inf").is_err());
+    }
+}
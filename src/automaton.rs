@@ -0,0 +1,523 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::texture_quad::TextureQuad;
+use crate::trails::SCENE_FORMAT;
+use crate::wgpu_ctx::WgpuCtx;
+use crate::Camera;
+
+/// Life-like birth/survival rule, one bit per live neighbour count 0..=8.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub birth: u16,
+    pub survival: u16,
+}
+
+impl Rule {
+    pub const LIFE: Rule = Rule {
+        birth: 1 << 3,
+        survival: (1 << 2) | (1 << 3),
+    };
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parses `B3/S23` notation, case-insensitive, in either order.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut rule = Rule {
+            birth: 0,
+            survival: 0,
+        };
+        let mut seen = (false, false);
+        for part in text.trim().split('/') {
+            let mut chars = part.trim().chars();
+            let target = match chars.next().map(|c| c.to_ascii_uppercase()) {
+                Some('B') => {
+                    seen.0 = true;
+                    &mut rule.birth
+                }
+                Some('S') => {
+                    seen.1 = true;
+                    &mut rule.survival
+                }
+                _ => return Err(format!("expected B or S in rule part '{}'", part)),
+            };
+            for c in chars {
+                let count = c
+                    .to_digit(10)
+                    .filter(|&count| count <= 8)
+                    .ok_or_else(|| format!("invalid neighbour count '{}' in rule", c))?;
+                *target |= 1 << count;
+            }
+        }
+        if !(seen.0 && seen.1) {
+            return Err(format!("rule '{}' needs both B and S parts", text));
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        for count in 0..=8 {
+            if self.birth & (1 << count) != 0 {
+                write!(f, "{}", count)?;
+            }
+        }
+        write!(f, "/S")?;
+        for count in 0..=8 {
+            if self.survival & (1 << count) != 0 {
+                write!(f, "{}", count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A cell pattern as live cell coordinates, row 0 at the top as in RLE files.
+pub struct Pattern {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<(usize, usize)>,
+    pub rule: Option<Rule>,
+}
+
+impl Pattern {
+    /// Parses the run-length encoded format used by Golly and LifeWiki.
+    pub fn from_rle(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.starts_with('#') && !line.is_empty());
+        let header = lines.next().ok_or("empty RLE file")?;
+
+        let mut width = 0;
+        let mut height = 0;
+        let mut rule = None;
+        for field in header.split(',') {
+            let (key, value) = field.split_once('=').ok_or_else(|| format!("bad RLE header field '{}'", field))?;
+            let value = value.trim();
+            match key.trim() {
+                "x" => width = value.parse().map_err(|_| format!("bad width '{}'", value))?,
+                "y" => height = value.parse().map_err(|_| format!("bad height '{}'", value))?,
+                "rule" => rule = Some(value.parse()?),
+                _ => {}
+            }
+        }
+
+        let mut cells = Vec::new();
+        let (mut x, mut y) = (0usize, 0usize);
+        let mut run = 0usize;
+        'body: for line in lines {
+            for c in line.chars() {
+                match c {
+                    '0'..='9' => run = run * 10 + c.to_digit(10).unwrap() as usize,
+                    'b' | '.' => {
+                        x += run.max(1);
+                        run = 0;
+                    }
+                    '$' => {
+                        y += run.max(1);
+                        x = 0;
+                        run = 0;
+                    }
+                    '!' => break 'body,
+                    c if c.is_ascii_alphabetic() => {
+                        // Any other state letter counts as alive
+                        for _ in 0..run.max(1) {
+                            cells.push((x, y));
+                            x += 1;
+                        }
+                        run = 0;
+                    }
+                    c if c.is_whitespace() => {}
+                    c => return Err(format!("unexpected '{}' in RLE body", c)),
+                }
+            }
+        }
+
+        // Trust the cells over a header that undersizes the pattern
+        for &(x, y) in &cells {
+            width = width.max(x + 1);
+            height = height.max(y + 1);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            cells,
+            rule,
+        })
+    }
+
+    pub fn to_rle(&self) -> String {
+        let mut grid = vec![false; self.width * self.height];
+        for &(x, y) in &self.cells {
+            grid[y * self.width + x] = true;
+        }
+
+        let mut body = String::new();
+        let mut line_len = 0;
+        let mut push = |body: &mut String, count: usize, tag: char| {
+            let token = if count > 1 { format!("{}{}", count, tag) } else { tag.to_string() };
+            if line_len + token.len() > 70 {
+                body.push('\n');
+                line_len = 0;
+            }
+            line_len += token.len();
+            body.push_str(&token);
+        };
+
+        let mut pending_rows = 0;
+        for y in 0..self.height {
+            let row = &grid[y * self.width..(y + 1) * self.width];
+            // Trailing dead cells are implied
+            let Some(last) = row.iter().rposition(|&alive| alive) else {
+                pending_rows += 1;
+                continue;
+            };
+            if y > 0 {
+                push(&mut body, pending_rows + 1, '$');
+            }
+            pending_rows = 0;
+
+            let mut x = 0;
+            while x <= last {
+                let alive = row[x];
+                let run = row[x..=last].iter().take_while(|&&cell| cell == alive).count();
+                push(&mut body, run, if alive { 'o' } else { 'b' });
+                x += run;
+            }
+        }
+        body.push('!');
+
+        let rule = self.rule.unwrap_or(Rule::LIFE);
+        format!("x = {}, y = {}, rule = {}\n{}\n", self.width, self.height, rule, body)
+    }
+}
+
+/// Life-like cellular automaton on a bit-packed grid. Cells outside the grid are dead.
+///
+/// Row 0 is the bottom of the grid in world space.
+pub struct Automaton {
+    pub width: usize,
+    pub height: usize,
+    pub rule: Rule,
+    pub generation: u64,
+    words_per_row: usize,
+    cells: Vec<u64>,
+    next: Vec<u64>,
+}
+
+impl Automaton {
+    pub fn new(width: usize, height: usize, rule: Rule) -> Self {
+        let words_per_row = width.div_ceil(64);
+        Self {
+            width,
+            height,
+            rule,
+            generation: 0,
+            words_per_row,
+            cells: vec![0; words_per_row * height],
+            next: vec![0; words_per_row * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width
+            && y < self.height
+            && self.cells[y * self.words_per_row + x / 64] & (1 << (x % 64)) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, alive: bool) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let word = &mut self.cells[y * self.words_per_row + x / 64];
+        if alive {
+            *word |= 1 << (x % 64);
+        } else {
+            *word &= !(1 << (x % 64));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(0);
+        self.generation = 0;
+    }
+
+    pub fn randomize(&mut self, density: f32, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.set(x, y, rng.random::<f32>() < density);
+            }
+        }
+        self.generation = 0;
+    }
+
+    pub fn population(&self) -> u64 {
+        self.cells.iter().map(|word| word.count_ones() as u64).sum()
+    }
+
+    /// Advances one generation, 64 cells at a time with bit-sliced neighbour counts.
+    pub fn step(&mut self) {
+        let words = self.words_per_row;
+        let height = self.height;
+        let rule = self.rule;
+        let cells = &self.cells;
+        let next = &mut self.next;
+        let empty = vec![0u64; words];
+        let row = |y: isize| row_or_empty(cells, &empty, words, height, y);
+        // Masks off bits past the right edge in the last word of each row
+        let tail_mask = match self.width % 64 {
+            0 => u64::MAX,
+            bits => (1u64 << bits) - 1,
+        };
+
+        for y in 0..height {
+            let above = row(y as isize + 1);
+            let center = row(y as isize);
+            let below = row(y as isize - 1);
+
+            for w in 0..words {
+                let mut count = [0u64; 4];
+                for (neighbours, include_center) in [(above, true), (center, false), (below, true)] {
+                    let word = neighbours[w];
+                    let prev = if w > 0 { neighbours[w - 1] } else { 0 };
+                    let following = if w + 1 < words { neighbours[w + 1] } else { 0 };
+                    // Neighbour at x - 1 lands on bit x, and x + 1 likewise
+                    add_bit(&mut count, (word << 1) | (prev >> 63));
+                    add_bit(&mut count, (word >> 1) | (following << 63));
+                    if include_center {
+                        add_bit(&mut count, word);
+                    }
+                }
+
+                let alive = center[w];
+                let mut born = 0;
+                let mut survives = 0;
+                for n in 0..=8 {
+                    let equal = count_equals(&count, n);
+                    if rule.birth & (1 << n) != 0 {
+                        born |= equal;
+                    }
+                    if rule.survival & (1 << n) != 0 {
+                        survives |= equal;
+                    }
+                }
+
+                let mut result = (alive & survives) | (!alive & born);
+                if w + 1 == words {
+                    result &= tail_mask;
+                }
+                next[y * words + w] = result;
+            }
+        }
+
+        std::mem::swap(&mut self.cells, &mut self.next);
+        self.generation += 1;
+    }
+
+    /// Stamps a pattern with its top-left cell at `(x, y_top)`.
+    pub fn place(&mut self, pattern: &Pattern, x: usize, y_top: usize) {
+        for &(px, py) in &pattern.cells {
+            if let Some(y) = y_top.checked_sub(py) {
+                self.set(x + px, y, true);
+            }
+        }
+    }
+
+    /// The bounding box of all live cells as a pattern.
+    pub fn to_pattern(&self) -> Pattern {
+        let mut live = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) {
+                    live.push((x, y));
+                }
+            }
+        }
+        let min_x = live.iter().map(|&(x, _)| x).min().unwrap_or(0);
+        let max_x = live.iter().map(|&(x, _)| x).max().unwrap_or(0);
+        let min_y = live.iter().map(|&(_, y)| y).min().unwrap_or(0);
+        let max_y = live.iter().map(|&(_, y)| y).max().unwrap_or(0);
+
+        Pattern {
+            width: if live.is_empty() { 0 } else { max_x - min_x + 1 },
+            height: if live.is_empty() { 0 } else { max_y - min_y + 1 },
+            // Flip to RLE's top-down rows
+            cells: live.iter().map(|&(x, y)| (x - min_x, max_y - y)).collect(),
+            rule: Some(self.rule),
+        }
+    }
+
+    /// Writes live cells as white RGBA8 pixels, top row first, for texture upload.
+    pub fn write_rgba(&self, out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(self.width * self.height * 4);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let value = if self.get(x, y) { 255 } else { 0 };
+                out.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+    }
+}
+
+fn row_or_empty<'a>(cells: &'a [u64], empty: &'a [u64], words: usize, height: usize, y: isize) -> &'a [u64] {
+    if y >= 0 && (y as usize) < height {
+        &cells[y as usize * words..(y as usize + 1) * words]
+    } else {
+        empty
+    }
+}
+
+/// Adds a one-bit value to each lane of a 4-bit bit-sliced counter.
+fn add_bit(count: &mut [u64; 4], mut carry: u64) {
+    for bit in count.iter_mut() {
+        let next_carry = *bit & carry;
+        *bit ^= carry;
+        carry = next_carry;
+    }
+}
+
+fn count_equals(count: &[u64; 4], n: u32) -> u64 {
+    let mut mask = u64::MAX;
+    for (i, bit) in count.iter().enumerate() {
+        mask &= if n & (1 << i) != 0 { *bit } else { !*bit };
+    }
+    mask
+}
+
+/// An automaton placed in world space together with the texture quad it is drawn through.
+pub struct AutomatonLayer {
+    pub automaton: Automaton,
+    pub quad: TextureQuad,
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    pixels: Vec<u8>,
+}
+
+impl AutomatonLayer {
+    pub fn new(wgpu_ctx: &WgpuCtx, automaton: Automaton, origin: [f32; 2], cell_size: f32) -> Self {
+        let quad = TextureQuad::new(
+            &wgpu_ctx.device,
+            SCENE_FORMAT,
+            &wgpu_ctx.uniform_bind_group_layout,
+            (automaton.width as u32, automaton.height as u32),
+            wgpu::FilterMode::Nearest,
+            "Automaton Texture",
+        );
+        Self {
+            automaton,
+            quad,
+            origin,
+            cell_size,
+            pixels: Vec::new(),
+        }
+    }
+
+    /// The cell under a world position, if it is inside the grid.
+    pub fn world_to_cell(&self, position: [f32; 2]) -> Option<(usize, usize)> {
+        let x = ((position[0] - self.origin[0]) / self.cell_size).floor();
+        let y = ((position[1] - self.origin[1]) / self.cell_size).floor();
+        (x >= 0.0 && y >= 0.0 && (x as usize) < self.automaton.width && (y as usize) < self.automaton.height)
+            .then_some((x as usize, y as usize))
+    }
+
+    pub fn upload(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        self.automaton.write_rgba(&mut self.pixels);
+        self.quad.upload(queue, &self.pixels);
+        let max = [
+            self.origin[0] + self.automaton.width as f32 * self.cell_size,
+            self.origin[1] + self.automaton.height as f32 * self.cell_size,
+        ];
+        self.quad.set_rect(queue, camera, self.origin, max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+
+    fn live_cells(automaton: &Automaton) -> Vec<(usize, usize)> {
+        (0..automaton.height)
+            .flat_map(|y| (0..automaton.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| automaton.get(x, y))
+            .collect()
+    }
+
+    /// One generation computed cell by cell through `get`.
+    fn naive_step(automaton: &Automaton) -> Vec<(usize, usize)> {
+        let mut live = Vec::new();
+        for y in 0..automaton.height {
+            for x in 0..automaton.width {
+                let mut neighbours = 0;
+                for dy in -1..=1isize {
+                    for dx in -1..=1isize {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        if (dx, dy) != (0, 0) && nx >= 0 && ny >= 0 && automaton.get(nx as usize, ny as usize) {
+                            neighbours += 1;
+                        }
+                    }
+                }
+                let rule = if automaton.get(x, y) { automaton.rule.survival } else { automaton.rule.birth };
+                if rule & (1 << neighbours) != 0 {
+                    live.push((x, y));
+                }
+            }
+        }
+        live
+    }
+
+    #[test]
+    fn blinker_oscillates() {
+        let mut automaton = Automaton::new(5, 5, Rule::LIFE);
+        for x in 1..4 {
+            automaton.set(x, 2, true);
+        }
+        automaton.step();
+        assert_eq!(live_cells(&automaton), vec![(2, 1), (2, 2), (2, 3)]);
+        automaton.step();
+        assert_eq!(live_cells(&automaton), vec![(1, 2), (2, 2), (3, 2)]);
+        assert_eq!(automaton.generation, 2);
+    }
+
+    #[test]
+    fn glider_crosses_word_boundary() {
+        // Two words per row with a partial second word, so the tail mask is exercised too
+        let mut automaton = Automaton::new(100, 64, Rule::LIFE);
+        automaton.place(&Pattern::from_rle(GLIDER).unwrap(), 56, 60);
+        for generation in 0..48 {
+            let expected = naive_step(&automaton);
+            automaton.step();
+            assert_eq!(live_cells(&automaton), expected, "generation {generation}");
+        }
+        assert_eq!(automaton.population(), 5);
+        // A glider moves one cell diagonally every four generations
+        assert!(live_cells(&automaton).iter().all(|&(x, _)| x > 64));
+    }
+
+    #[test]
+    fn rle_round_trips() {
+        let glider = Pattern::from_rle(GLIDER).unwrap();
+        assert_eq!((glider.width, glider.height), (3, 3));
+        assert_eq!(glider.cells, vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(glider.rule, Some(Rule::LIFE));
+        assert_eq!(glider.to_rle(), GLIDER);
+
+        // Blank rows collapse into a counted '$'
+        let gapped = "x = 2, y = 4, rule = B36/S23\n2o3$bo!\n";
+        assert_eq!(Pattern::from_rle(gapped).unwrap().to_rle(), gapped);
+
+        // Stamping into a grid and reading it back keeps the pattern
+        let mut automaton = Automaton::new(70, 10, Rule::LIFE);
+        automaton.place(&glider, 62, 5);
+        assert_eq!(automaton.to_pattern().to_rle(), GLIDER);
+    }
+}
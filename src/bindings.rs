@@ -0,0 +1,89 @@
+use winit::event::MouseButton;
+
+/// What a mouse drag over the world can do.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MouseAction {
+    Pan,
+    UseTool,
+    PaintCells,
+    Stir,
+}
+
+impl MouseAction {
+    // Also the order actions sharing a button are tried in
+    pub const ALL: [MouseAction; 4] = [
+        MouseAction::Pan,
+        MouseAction::UseTool,
+        MouseAction::PaintCells,
+        MouseAction::Stir,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MouseAction::Pan => "Pan",
+            MouseAction::UseTool => "Use tool",
+            MouseAction::PaintCells => "Paint cells",
+            MouseAction::Stir => "Stir fluid",
+        }
+    }
+}
+
+pub const BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Middle, MouseButton::Right];
+
+pub fn button_name(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "Left",
+        MouseButton::Middle => "Middle",
+        MouseButton::Right => "Right",
+        _ => "Other",
+    }
+}
+
+/// The mouse button bound to each action. Several actions may share a
+/// button; a press then starts the first of them that applies at the cursor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MouseBindings {
+    pub pan: MouseButton,
+    pub use_tool: MouseButton,
+    pub paint_cells: MouseButton,
+    pub stir: MouseButton,
+}
+
+impl Default for MouseBindings {
+    fn default() -> Self {
+        Self {
+            pan: MouseButton::Middle,
+            use_tool: MouseButton::Left,
+            // Paints over automaton cells and stirs everywhere else
+            paint_cells: MouseButton::Right,
+            stir: MouseButton::Right,
+        }
+    }
+}
+
+impl MouseBindings {
+    pub fn button(&self, action: MouseAction) -> MouseButton {
+        match action {
+            MouseAction::Pan => self.pan,
+            MouseAction::UseTool => self.use_tool,
+            MouseAction::PaintCells => self.paint_cells,
+            MouseAction::Stir => self.stir,
+        }
+    }
+
+    pub fn button_mut(&mut self, action: MouseAction) -> &mut MouseButton {
+        match action {
+            MouseAction::Pan => &mut self.pan,
+            MouseAction::UseTool => &mut self.use_tool,
+            MouseAction::PaintCells => &mut self.paint_cells,
+            MouseAction::Stir => &mut self.stir,
+        }
+    }
+
+    /// Actions bound to `button`, in the order they are tried.
+    pub fn actions(&self, button: MouseButton) -> impl Iterator<Item = MouseAction> + '_ {
+        MouseAction::ALL
+            .into_iter()
+            .filter(move |&action| self.button(action) == button)
+    }
+}
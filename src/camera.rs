@@ -1,7 +1,6 @@
-use winit::event::MouseButton;
-
 pub struct Camera {
-    pub position: [f32; 2],
+    // f64 so panning stays smooth far from the origin; see `to_view`
+    pub position: [f64; 2],
     pub zoom: f32,
     pub dragging: bool,
     pub last_mouse_pos: Option<[f32; 2]>,
@@ -23,6 +22,11 @@ impl Camera {
         self.window_size = [width, height];
     }
 
+    /// Projects camera-relative positions, as produced by `to_view`.
+    ///
+    /// The translation is applied on the CPU in f64 instead of here, so
+    /// positions far from the origin don't lose precision in f32 before the
+    /// zoom magnifies the error.
     pub fn get_view_matrix(&self) -> [[f32; 4]; 4] {
         let aspect_ratio = self.window_size[0] / self.window_size[1];
         let scale_x = self.zoom / aspect_ratio;
@@ -32,11 +36,30 @@ impl Camera {
             [scale_x, 0.0, 0.0, 0.0],
             [0.0, scale_y, 0.0, 0.0],
             [0.0, 0.0, 1.0, 0.0],
-            [-self.position[0] * scale_x, -self.position[1] * scale_y, 0.0, 1.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+
+    /// Size of one window pixel in world units.
+    pub fn world_per_pixel(&self) -> f32 {
+        2.0 / (self.zoom * self.window_size[1])
+    }
+
+    /// Half the visible area in world units.
+    pub fn view_extent(&self) -> [f32; 2] {
+        let aspect_ratio = self.window_size[0] / self.window_size[1];
+        [aspect_ratio / self.zoom, 1.0 / self.zoom]
+    }
+
+    /// Rebases a world position onto the camera for upload.
+    pub fn to_view(&self, world: [f64; 2]) -> [f32; 2] {
+        [
+            (world[0] - self.position[0]) as f32,
+            (world[1] - self.position[1]) as f32,
         ]
     }
 
-    fn screen_to_world(&self, screen_pos: [f32; 2]) -> [f32; 2] {
+    pub fn screen_to_world(&self, screen_pos: [f32; 2]) -> [f64; 2] {
         let aspect_ratio = self.window_size[0] / self.window_size[1];
         
         // Convert screen coordinates to normalized device coordinates (-1 to 1)
@@ -45,23 +68,32 @@ impl Camera {
         
         // Convert to world space
         [
-            ndc_x * (1.0 / self.zoom) * aspect_ratio + self.position[0],
-            ndc_y * (1.0 / self.zoom) + self.position[1],
+            (ndc_x * (1.0 / self.zoom) * aspect_ratio) as f64 + self.position[0],
+            (ndc_y * (1.0 / self.zoom)) as f64 + self.position[1],
         ]
     }
 
-    pub fn handle_mouse_press(&mut self, button: MouseButton, position: [f32; 2]) {
-        if button == MouseButton::Left {
-            self.dragging = true;
-            self.last_mouse_pos = Some(position);
-        }
+    /// Inverse of `screen_to_world`, in window pixels.
+    pub fn world_to_screen(&self, world: [f64; 2]) -> [f32; 2] {
+        let aspect_ratio = self.window_size[0] / self.window_size[1];
+        let view = self.to_view(world);
+        let ndc_x = view[0] * self.zoom / aspect_ratio;
+        let ndc_y = view[1] * self.zoom;
+        [
+            (ndc_x + 1.0) * 0.5 * self.window_size[0],
+            (1.0 - ndc_y) * 0.5 * self.window_size[1],
+        ]
     }
 
-    pub fn handle_mouse_release(&mut self, button: MouseButton) {
-        if button == MouseButton::Left {
-            self.dragging = false;
-            self.last_mouse_pos = None;
-        }
+    /// Starts dragging the view; which button does this is up to the caller.
+    pub fn begin_pan(&mut self, position: [f32; 2]) {
+        self.dragging = true;
+        self.last_mouse_pos = Some(position);
+    }
+
+    pub fn end_pan(&mut self) {
+        self.dragging = false;
+        self.last_mouse_pos = None;
     }
 
     pub fn handle_mouse_move(&mut self, position: [f32; 2]) {
@@ -74,8 +106,8 @@ impl Camera {
                 let delta_y = (position[1] - last_pos[1]) / self.window_size[1];
                 
                 // Convert to world space movement
-                self.position[0] -= 2.0 * delta_x * (1.0 / self.zoom) * aspect_ratio;
-                self.position[1] += 2.0 * delta_y * (1.0 / self.zoom); // Note the += because Y is flipped
+                self.position[0] -= (2.0 * delta_x * (1.0 / self.zoom) * aspect_ratio) as f64;
+                self.position[1] += (2.0 * delta_y * (1.0 / self.zoom)) as f64; // Note the += because Y is flipped
             }
             self.last_mouse_pos = Some(position);
         }
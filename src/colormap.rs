@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use crate::query::ParticleIndex;
+use crate::sim::Particle;
+
+/// Entries in a colormap lookup table.
+pub const TABLE_SIZE: usize = 256;
+
+// Neighbours are counted within this many particle radii for the density
+const DENSITY_REACH: f32 = 4.0;
+
+/// Per-particle quantity mapped to colour.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorAttribute {
+    Species,
+    Speed,
+    Density,
+    Age,
+    Pressure,
+}
+
+impl ColorAttribute {
+    pub const ALL: [ColorAttribute; 5] = [
+        ColorAttribute::Species,
+        ColorAttribute::Speed,
+        ColorAttribute::Density,
+        ColorAttribute::Age,
+        ColorAttribute::Pressure,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorAttribute::Species => "Species",
+            ColorAttribute::Speed => "Speed",
+            ColorAttribute::Density => "Density",
+            ColorAttribute::Age => "Age (ticks)",
+            ColorAttribute::Pressure => "Pressure",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Turbo,
+    // Loaded from a PNG into `Coloring::gradient`
+    Custom,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [Colormap::Viridis, Colormap::Magma, Colormap::Turbo, Colormap::Custom];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Viridis => "Viridis",
+            Colormap::Magma => "Magma",
+            Colormap::Turbo => "Turbo",
+            Colormap::Custom => "Custom",
+        }
+    }
+
+    /// sRGB colour at `t` in 0..1, from polynomial fits to the published maps.
+    /// Custom gradients are sampled by `Coloring::table` instead.
+    pub fn sample(self, t: f32) -> [f32; 3] {
+        // Viridis and magma fits by Matt Zucker, turbo by Anton Mikhailov
+        const VIRIDIS: [[f32; 3]; 7] = [
+            [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+            [0.105_093_04, 1.404_613_5, 1.384_590_1],
+            [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+            [-4.634_230_6, -5.799_101, -19.332_441],
+            [6.228_27, 14.179_933, 56.690_55],
+            [4.776_385, -13.745_145, -65.353_035],
+            [-5.435_456, 4.645_852_6, 26.312_435],
+        ];
+        const MAGMA: [[f32; 3]; 7] = [
+            [-0.002_136_485, -0.000_749_655_05, -0.005_386_128],
+            [0.251_660_54, 0.677_523_24, 2.494_026_7],
+            [8.353_717, -3.577_719_5, 0.314_467_9],
+            [-27.668_733, 14.264_731, -13.649_213],
+            [52.176_14, -27.943_607, 12.944_169],
+            [-50.768_524, 29.046_583, 4.234_153],
+            [18.655_705, -11.489_774, -5.601_961_5],
+        ];
+        const TURBO: [[f32; 3]; 6] = [
+            [0.135_721_38, 0.091_402_61, 0.106_673_3],
+            [4.615_392_6, 2.194_188_4, 12.641_946],
+            [-42.660_324, 4.842_966_6, -60.582_047],
+            [132.131_08, -14.185_033, 110.362_77],
+            [-152.942_39, 4.277_298_5, -89.903_11],
+            [59.286_38, 2.829_566, 27.348_25],
+        ];
+        fn polynomial(coefficients: &[[f32; 3]], t: f32) -> [f32; 3] {
+            // Horner's rule, highest power first
+            let mut color = [0.0; 3];
+            for coefficient in coefficients.iter().rev() {
+                for c in 0..3 {
+                    color[c] = color[c] * t + coefficient[c];
+                }
+            }
+            color.map(|c| c.clamp(0.0, 1.0))
+        }
+
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Colormap::Viridis => polynomial(&VIRIDIS, t),
+            Colormap::Magma => polynomial(&MAGMA, t),
+            Colormap::Turbo => polynomial(&TURBO, t),
+            Colormap::Custom => [t; 3],
+        }
+    }
+}
+
+/// Loads a gradient from a PNG, read left to right along the middle row, or
+/// bottom to top along the middle column if the image is taller than wide.
+pub fn load_gradient(path: impl AsRef<Path>) -> Result<Vec<[u8; 4]>, image::ImageError> {
+    let image = image::open(path)?.to_rgba8();
+    let (width, height) = image.dimensions();
+    let vertical = height > width;
+    let length = if vertical { height } else { width };
+    let pixel = |i: u32| {
+        let pixel = if vertical {
+            image.get_pixel(width / 2, height - 1 - i)
+        } else {
+            image.get_pixel(i, height / 2)
+        };
+        pixel.0.map(|c| c as f32)
+    };
+
+    // Resample linearly to the table size
+    Ok((0..TABLE_SIZE)
+        .map(|entry| {
+            let x = entry as f32 / (TABLE_SIZE - 1) as f32 * (length - 1) as f32;
+            let (i, fraction) = (x.floor() as u32, x.fract());
+            let (a, b) = (pixel(i), pixel((i + 1).min(length - 1)));
+            [0, 1, 2, 3].map(|c| (a[c] + (b[c] - a[c]) * fraction).round() as u8)
+        })
+        .collect())
+}
+
+/// How particles are coloured, as chosen in the UI.
+#[derive(Clone, Debug)]
+pub struct Coloring {
+    // None keeps the fixed per-species tints
+    pub attribute: Option<ColorAttribute>,
+    pub colormap: Colormap,
+    pub gradient: Vec<[u8; 4]>,
+    // Follow the shown values' extent each frame instead of `range`
+    pub auto_range: bool,
+    pub range: [f32; 2],
+}
+
+impl Default for Coloring {
+    fn default() -> Self {
+        Self {
+            attribute: None,
+            colormap: Colormap::Viridis,
+            gradient: Vec::new(),
+            auto_range: true,
+            range: [0.0, 1.0],
+        }
+    }
+}
+
+impl Coloring {
+    /// Colour at `t` in 0..1, as sRGB bytes.
+    pub fn color(&self, t: f32) -> [u8; 4] {
+        if self.colormap == Colormap::Custom && !self.gradient.is_empty() {
+            let last = self.gradient.len() - 1;
+            return self.gradient[(t.clamp(0.0, 1.0) * last as f32).round() as usize];
+        }
+        let [r, g, b] = self.colormap.sample(t).map(|c| (c * 255.0).round() as u8);
+        [r, g, b, 255]
+    }
+
+    /// The lookup table uploaded for the particle shader.
+    pub fn table(&self) -> Vec<[u8; 4]> {
+        (0..TABLE_SIZE)
+            .map(|entry| self.color(entry as f32 / (TABLE_SIZE - 1) as f32))
+            .collect()
+    }
+
+    /// Fills `values` with the chosen attribute of each particle, scaled into
+    /// 0..1 by the range. In auto mode the range first moves to the values'
+    /// extent. Leaves `values` empty when colouring by species tint.
+    pub fn evaluate(&mut self, particles: &[Particle], index: &ParticleIndex, values: &mut Vec<f32>) {
+        values.clear();
+        let Some(attribute) = self.attribute else {
+            return;
+        };
+        let reach = DENSITY_REACH * index.radius;
+        let area = std::f32::consts::PI * reach * reach;
+        values.extend(particles.iter().enumerate().map(|(i, particle)| match attribute {
+            ColorAttribute::Species => particle.species as f32,
+            ColorAttribute::Speed => particle.velocity[0].hypot(particle.velocity[1]),
+            ColorAttribute::Density => match index.positions.get(i) {
+                Some(&position) => index.count_within(position, reach) as f32 / area,
+                None => 0.0,
+            },
+            ColorAttribute::Age => particle.age as f32,
+            ColorAttribute::Pressure => particle.pressure,
+        }));
+
+        if self.auto_range && !values.is_empty() {
+            let (min, max) = values
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+            self.range = [min, max];
+        }
+        let [min, max] = self.range;
+        // A flat range maps everything to the middle of the colormap
+        let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+        for value in values.iter_mut() {
+            *value = if scale > 0.0 { (*value - min) * scale } else { 0.5 };
+        }
+    }
+}
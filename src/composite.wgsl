@@ -0,0 +1,15 @@
+@group(0) @binding(0) var scene: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the screen
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The scene and the target are the same size, so pixels are copied one to one
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(scene, vec2<i32>(position.xy), 0);
+    return vec4<f32>(color.rgb, 1.0);
+}
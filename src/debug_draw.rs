@@ -0,0 +1,327 @@
+use std::borrow::Cow;
+use std::f64::consts::TAU;
+use wgpu::ShaderSource;
+
+use crate::query::ParticleIndex;
+use crate::render_graph::{RenderGraph, ResourceId};
+use crate::sim::Particle;
+use crate::Camera;
+
+const CIRCLE_SEGMENTS: usize = 48;
+// Arrow heads as a fraction of the shaft, swept back this far from it
+const ARROW_HEAD: f64 = 0.25;
+const ARROW_ANGLE: f64 = 0.45;
+// Keeps the contact overlay affordable when zoomed out over a dense pile
+const MAX_CONTACTS: usize = 20_000;
+
+/// Camera-relative vertex of the debug line list.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub struct DebugVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// World-space text, drawn through the UI since the renderer has no fonts.
+#[derive(Clone, Debug)]
+pub struct DebugLabel {
+    pub position: [f64; 2],
+    pub text: String,
+    pub color: [f32; 4],
+}
+
+/// Immediate-mode gizmos: anything added during a frame is drawn over that
+/// frame's output and then dropped. Positions are in world coordinates.
+#[derive(Default)]
+pub struct DebugDraw {
+    // Pairs of line endpoints
+    vertices: Vec<([f64; 2], [f32; 4])>,
+    labels: Vec<DebugLabel>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, a: [f64; 2], b: [f64; 2], color: [f32; 4]) {
+        self.vertices.push((a, color));
+        self.vertices.push((b, color));
+    }
+
+    /// An axis-aligned outline between two opposite corners.
+    pub fn rect(&mut self, min: [f64; 2], max: [f64; 2], color: [f32; 4]) {
+        let corners = [min, [max[0], min[1]], max, [min[0], max[1]]];
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], color);
+        }
+    }
+
+    pub fn circle(&mut self, center: [f64; 2], radius: f64, color: [f32; 4]) {
+        let point = |i: usize| {
+            let angle = TAU * i as f64 / CIRCLE_SEGMENTS as f64;
+            [center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// A line from `from` to `to` with a head at `to`.
+    pub fn arrow(&mut self, from: [f64; 2], to: [f64; 2], color: [f32; 4]) {
+        self.line(from, to, color);
+        let back = [from[0] - to[0], from[1] - to[1]];
+        for angle in [ARROW_ANGLE, -ARROW_ANGLE] {
+            let (sin, cos) = angle.sin_cos();
+            let head = [
+                ARROW_HEAD * (back[0] * cos - back[1] * sin),
+                ARROW_HEAD * (back[0] * sin + back[1] * cos),
+            ];
+            self.line(to, [to[0] + head[0], to[1] + head[1]], color);
+        }
+    }
+
+    pub fn text(&mut self, position: [f64; 2], text: impl Into<String>, color: [f32; 4]) {
+        self.labels.push(DebugLabel {
+            position,
+            text: text.into(),
+            color,
+        });
+    }
+
+    pub fn labels(&self) -> &[DebugLabel] {
+        &self.labels
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.labels.clear();
+    }
+}
+
+/// Built-in overlays drawn through `DebugDraw`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DebugOverlays {
+    pub bounds: bool,
+    pub grid: bool,
+    pub velocities: bool,
+    // World units of arrow per unit of speed
+    pub velocity_scale: f32,
+    pub contacts: bool,
+    // Cast from the centre of the view to the cursor
+    pub ray: bool,
+}
+
+impl Default for DebugOverlays {
+    fn default() -> Self {
+        Self {
+            bounds: false,
+            grid: false,
+            velocities: false,
+            velocity_scale: 0.5,
+            contacts: false,
+            ray: false,
+        }
+    }
+}
+
+impl DebugOverlays {
+    /// Adds the enabled overlays for the particles within the visible `view_min..view_max`.
+    pub fn draw(
+        &self,
+        debug: &mut DebugDraw,
+        particles: &[Particle],
+        index: &ParticleIndex,
+        bounds: [f32; 2],
+        [view_min, view_max]: [[f64; 2]; 2],
+        cursor: Option<[f64; 2]>,
+    ) {
+        if self.bounds {
+            let [x, y] = bounds.map(f64::from);
+            let color = [1.0, 0.9, 0.2, 1.0];
+            debug.rect([-x, -y], [x, y], color);
+            debug.text([-x, y], format!("Bounds {:.0} x {:.0}", 2.0 * x, 2.0 * y), color);
+        }
+        if self.grid {
+            if let Some((min, max)) = index.grid().bounds() {
+                let color = [0.4, 0.6, 1.0, 0.35];
+                let cell = index.grid().cell_size as f64;
+                let (min, max) = (min.map(f64::from), max.map(f64::from));
+                let columns = ((max[0] - min[0]) / cell).round() as usize;
+                let rows = ((max[1] - min[1]) / cell).round() as usize;
+                for i in 0..=columns {
+                    let x = min[0] + i as f64 * cell;
+                    debug.line([x, min[1]], [x, max[1]], color);
+                }
+                for i in 0..=rows {
+                    let y = min[1] + i as f64 * cell;
+                    debug.line([min[0], y], [max[0], y], color);
+                }
+            }
+        }
+        if let Some(cursor) = cursor.filter(|_| self.ray) {
+            let from = [(view_min[0] + view_max[0]) / 2.0, (view_min[1] + view_max[1]) / 2.0];
+            let color = [0.9, 0.5, 1.0, 0.9];
+            match index.raycast(from.map(|v| v as f32), cursor.map(|v| v as f32)) {
+                Some(hit) => {
+                    let point = hit.point.map(f64::from);
+                    let reach = 2.0 * index.radius as f64;
+                    let normal = [point[0] + hit.normal[0] as f64 * reach, point[1] + hit.normal[1] as f64 * reach];
+                    debug.line(from, point, color);
+                    debug.arrow(point, normal, color);
+                    debug.text(point, format!("#{} at {:.2}", hit.index, hit.distance), color);
+                }
+                None => debug.line(from, cursor, color),
+            }
+        }
+        if !self.velocities && !self.contacts {
+            return;
+        }
+
+        let visible = index.in_aabb(view_min.map(|v| v as f32), view_max.map(|v| v as f32));
+        if self.velocities {
+            let scale = self.velocity_scale as f64;
+            for &i in &visible {
+                let Some(particle) = particles.get(i) else {
+                    continue;
+                };
+                let [x, y] = particle.position;
+                let [vx, vy] = particle.velocity.map(f64::from);
+                debug.arrow([x, y], [x + vx * scale, y + vy * scale], [0.3, 1.0, 0.5, 0.9]);
+            }
+        }
+        if self.contacts {
+            // Touching pairs as seen in the snapshot, each marked at its midpoint
+            let mut count = 0;
+            'particles: for &i in &visible {
+                let a = index.positions[i];
+                for hit in index.within_radius(a, 2.0 * index.radius) {
+                    if hit.index <= i {
+                        continue;
+                    }
+                    let b = hit.position;
+                    let (a, b) = (a.map(f64::from), b.map(f64::from));
+                    let middle = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+                    debug.line(a, b, [1.0, 0.3, 0.3, 0.8]);
+                    debug.circle(middle, index.radius as f64 * 0.25, [1.0, 0.3, 0.3, 0.8]);
+                    count += 1;
+                    if count == MAX_CONTACTS {
+                        break 'particles;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws a `DebugDraw` batch as one line list over the output.
+pub struct DebugRenderer {
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    count: u32,
+    // Reused between uploads to rebase vertices onto the camera
+    staging: Vec<DebugVertex>,
+}
+
+impl DebugRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, camera_layout: &wgpu::BindGroupLayout) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug Shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("debug.wgsl"))),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let buffer = create_buffer(device, std::mem::size_of::<DebugVertex>() as u64 * 2);
+
+        Self {
+            pipeline,
+            buffer,
+            count: 0,
+            staging: Vec::new(),
+        }
+    }
+
+    /// Uploads the lines in `batch` relative to the camera, then empties it.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera, batch: &mut DebugDraw) {
+        self.staging.clear();
+        self.staging.extend(batch.vertices.iter().map(|&(position, color)| DebugVertex {
+            position: camera.to_view(position),
+            color,
+        }));
+        batch.clear();
+
+        let size = std::mem::size_of_val(self.staging.as_slice()) as u64;
+        if size > self.buffer.size() {
+            self.buffer = create_buffer(device, size);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.staging));
+        self.count = self.staging.len() as u32;
+    }
+
+    /// Adds a node drawing the uploaded lines over `output`, when there are any.
+    pub fn add_node<'a>(&'a self, graph: &mut RenderGraph<'a>, camera_bind_group: &'a wgpu::BindGroup, output: ResourceId) {
+        if self.count == 0 {
+            return;
+        }
+        graph.add_node("Debug draw", &[output], &[output], move |encoder, resources| {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Debug Draw Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: resources.view(output),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, camera_bind_group, &[]);
+            pass.set_vertex_buffer(0, self.buffer.slice(..));
+            pass.draw(0..self.count, 0..1);
+        });
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug Vertex Buffer"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,334 @@
+use crate::texture_quad::TextureQuad;
+use crate::trails::SCENE_FORMAT;
+use crate::wgpu_ctx::WgpuCtx;
+use crate::Camera;
+
+/// Grid-based incompressible fluid (Stam's stable fluids) with RGB dye.
+///
+/// Velocities are stored at cell centres in cells per second. Row 0 is the
+/// bottom of the grid in world space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FluidConfig {
+    pub width: usize,
+    pub height: usize,
+    // World position of the bottom-left corner and size of one cell
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    pub pressure_iterations: u32,
+    // Strength of the force that re-injects small-scale swirls lost to advection
+    pub vorticity: f32,
+    // Fraction of velocity and dye kept per second
+    pub velocity_dissipation: f32,
+    pub dye_dissipation: f32,
+}
+
+impl Default for FluidConfig {
+    fn default() -> Self {
+        Self {
+            width: 128,
+            height: 128,
+            origin: [-1_000.0, -1_000.0],
+            cell_size: 2_000.0 / 128.0,
+            pressure_iterations: 30,
+            vorticity: 10.0,
+            velocity_dissipation: 0.99,
+            dye_dissipation: 0.97,
+        }
+    }
+}
+
+/// Which field is drawn to the texture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FluidView {
+    Dye,
+    Velocity,
+}
+
+pub struct Fluid {
+    pub config: FluidConfig,
+    pub u: Vec<f32>,
+    pub v: Vec<f32>,
+    pub dye: [Vec<f32>; 3],
+    scratch: Vec<f32>,
+    pressure: Vec<f32>,
+    divergence: Vec<f32>,
+}
+
+impl Fluid {
+    pub fn new(config: FluidConfig) -> Self {
+        let cells = config.width * config.height;
+        Self {
+            config,
+            u: vec![0.0; cells],
+            v: vec![0.0; cells],
+            dye: [vec![0.0; cells], vec![0.0; cells], vec![0.0; cells]],
+            scratch: vec![0.0; cells],
+            pressure: vec![0.0; cells],
+            divergence: vec![0.0; cells],
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.config.width + x
+    }
+
+    /// World position to fractional cell coordinates.
+    pub fn world_to_grid(&self, position: [f32; 2]) -> [f32; 2] {
+        [
+            (position[0] - self.config.origin[0]) / self.config.cell_size - 0.5,
+            (position[1] - self.config.origin[1]) / self.config.cell_size - 0.5,
+        ]
+    }
+
+    pub fn world_rect(&self) -> ([f32; 2], [f32; 2]) {
+        let origin = self.config.origin;
+        (
+            origin,
+            [
+                origin[0] + self.config.width as f32 * self.config.cell_size,
+                origin[1] + self.config.height as f32 * self.config.cell_size,
+            ],
+        )
+    }
+
+    /// Adds velocity (world units per second) and dye in a Gaussian splat around a world position.
+    pub fn splat(&mut self, position: [f32; 2], velocity: [f32; 2], color: [f32; 3], radius: f32) {
+        let [cx, cy] = self.world_to_grid(position);
+        let r = (radius / self.config.cell_size).max(1.0);
+        let (w, h) = (self.config.width, self.config.height);
+        let x0 = (cx - 3.0 * r).floor().max(0.0) as usize;
+        let y0 = (cy - 3.0 * r).floor().max(0.0) as usize;
+        let x1 = ((cx + 3.0 * r).ceil().max(0.0) as usize).min(w);
+        let y1 = ((cy + 3.0 * r).ceil().max(0.0) as usize).min(h);
+        let cell_velocity = [velocity[0] / self.config.cell_size, velocity[1] / self.config.cell_size];
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let dx = x as f32 - cx;
+                let dy = y as f32 - cy;
+                let weight = (-(dx * dx + dy * dy) / (r * r)).exp();
+                let i = self.index(x, y);
+                self.u[i] += cell_velocity[0] * weight;
+                self.v[i] += cell_velocity[1] * weight;
+                for (channel, amount) in self.dye.iter_mut().zip(color) {
+                    channel[i] += amount * weight;
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        if self.config.vorticity > 0.0 {
+            self.confine_vorticity(dt);
+        }
+
+        // Advect velocity by itself, reading from copies of the previous field
+        let u0 = self.u.clone();
+        let v0 = self.v.clone();
+        advect(&self.config, &mut self.scratch, &u0, &u0, &v0, dt);
+        std::mem::swap(&mut self.u, &mut self.scratch);
+        advect(&self.config, &mut self.scratch, &v0, &u0, &v0, dt);
+        std::mem::swap(&mut self.v, &mut self.scratch);
+
+        let velocity_keep = self.config.velocity_dissipation.powf(dt);
+        for (u, v) in self.u.iter_mut().zip(self.v.iter_mut()) {
+            *u *= velocity_keep;
+            *v *= velocity_keep;
+        }
+
+        self.set_velocity_boundaries();
+        self.project();
+
+        let dye_keep = self.config.dye_dissipation.powf(dt);
+        for channel in 0..3 {
+            advect(&self.config, &mut self.scratch, &self.dye[channel], &self.u, &self.v, dt);
+            std::mem::swap(&mut self.dye[channel], &mut self.scratch);
+            for value in self.dye[channel].iter_mut() {
+                *value *= dye_keep;
+            }
+        }
+    }
+
+    /// Solid walls: no flow through the edges of the grid.
+    fn set_velocity_boundaries(&mut self) {
+        let (w, h) = (self.config.width, self.config.height);
+        for y in 0..h {
+            let left = self.index(0, y);
+            let right = self.index(w - 1, y);
+            self.u[left] = 0.0;
+            self.u[right] = 0.0;
+        }
+        for x in 0..w {
+            let bottom = self.index(x, 0);
+            let top = self.index(x, h - 1);
+            self.v[bottom] = 0.0;
+            self.v[top] = 0.0;
+        }
+    }
+
+    /// Makes the velocity field divergence-free with a Gauss-Seidel pressure solve.
+    fn project(&mut self) {
+        let (w, h) = (self.config.width, self.config.height);
+        if w < 3 || h < 3 {
+            return;
+        }
+
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let i = self.index(x, y);
+                self.divergence[i] = -0.5
+                    * (self.u[i + 1] - self.u[i - 1] + self.v[i + w] - self.v[i - w]);
+            }
+        }
+
+        self.pressure.fill(0.0);
+        for _ in 0..self.config.pressure_iterations {
+            for y in 1..h - 1 {
+                for x in 1..w - 1 {
+                    let i = self.index(x, y);
+                    self.pressure[i] = (self.divergence[i]
+                        + self.pressure[i - 1]
+                        + self.pressure[i + 1]
+                        + self.pressure[i - w]
+                        + self.pressure[i + w])
+                        / 4.0;
+                }
+            }
+        }
+
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let i = self.index(x, y);
+                self.u[i] -= 0.5 * (self.pressure[i + 1] - self.pressure[i - 1]);
+                self.v[i] -= 0.5 * (self.pressure[i + w] - self.pressure[i - w]);
+            }
+        }
+        self.set_velocity_boundaries();
+    }
+
+    fn confine_vorticity(&mut self, dt: f32) {
+        let (w, h) = (self.config.width, self.config.height);
+        if w < 5 || h < 5 {
+            return;
+        }
+
+        let curl = &mut self.scratch;
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let i = y * w + x;
+                curl[i] = 0.5 * ((self.v[i + 1] - self.v[i - 1]) - (self.u[i + w] - self.u[i - w]));
+            }
+        }
+
+        for y in 2..h - 2 {
+            for x in 2..w - 2 {
+                let i = y * w + x;
+                // Push towards higher curl magnitude, perpendicular to its gradient
+                let grad_x = 0.5 * (curl[i + 1].abs() - curl[i - 1].abs());
+                let grad_y = 0.5 * (curl[i + w].abs() - curl[i - w].abs());
+                let length = (grad_x * grad_x + grad_y * grad_y).sqrt() + 1e-5;
+                let (nx, ny) = (grad_x / length, grad_y / length);
+                self.u[i] += self.config.vorticity * ny * curl[i] * dt;
+                self.v[i] -= self.config.vorticity * nx * curl[i] * dt;
+            }
+        }
+    }
+
+    /// Writes the chosen field as RGBA8, top row first, for texture upload.
+    pub fn write_rgba(&self, view: FluidView, out: &mut Vec<u8>) {
+        let (w, h) = (self.config.width, self.config.height);
+        out.clear();
+        out.reserve(w * h * 4);
+        let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u8;
+
+        for y in (0..h).rev() {
+            for x in 0..w {
+                let i = self.index(x, y);
+                let rgb = match view {
+                    FluidView::Dye => [self.dye[0][i], self.dye[1][i], self.dye[2][i]],
+                    FluidView::Velocity => {
+                        // Direction as hue, speed as brightness
+                        let speed = (self.u[i] * self.u[i] + self.v[i] * self.v[i]).sqrt();
+                        let hue = self.v[i].atan2(self.u[i]) / std::f32::consts::TAU + 0.5;
+                        hsv_to_rgb(hue, 1.0, (speed * 0.1).min(1.0))
+                    }
+                };
+                out.extend_from_slice(&[to_byte(rgb[0]), to_byte(rgb[1]), to_byte(rgb[2]), 255]);
+            }
+        }
+    }
+}
+
+/// Semi-Lagrangian advection: trace each cell back along the velocity and sample `field` there.
+fn advect(config: &FluidConfig, out: &mut [f32], field: &[f32], u: &[f32], v: &[f32], dt: f32) {
+    let (w, h) = (config.width, config.height);
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            let px = (x as f32 - dt * u[i]).clamp(0.0, (w - 1) as f32);
+            let py = (y as f32 - dt * v[i]).clamp(0.0, (h - 1) as f32);
+            out[i] = bilinear(field, w, h, px, py);
+        }
+    }
+}
+
+fn bilinear(field: &[f32], w: usize, h: usize, x: f32, y: f32) -> f32 {
+    let x0 = (x as usize).min(w.saturating_sub(2));
+    let y0 = (y as usize).min(h.saturating_sub(2));
+    let x1 = (x0 + 1).min(w - 1);
+    let y1 = (y0 + 1).min(h - 1);
+    let tx = x - x0 as f32;
+    let ty = y - y0 as f32;
+    let top = field[y0 * w + x0] * (1.0 - tx) + field[y0 * w + x1] * tx;
+    let bottom = field[y1 * w + x0] * (1.0 - tx) + field[y1 * w + x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+    let m = value - c;
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r + m, g + m, b + m]
+}
+
+/// A fluid together with the texture quad it is drawn through.
+pub struct FluidLayer {
+    pub fluid: Fluid,
+    pub quad: TextureQuad,
+    pixels: Vec<u8>,
+}
+
+impl FluidLayer {
+    pub fn new(wgpu_ctx: &WgpuCtx, config: FluidConfig) -> Self {
+        let fluid = Fluid::new(config);
+        let quad = TextureQuad::new(
+            &wgpu_ctx.device,
+            SCENE_FORMAT,
+            &wgpu_ctx.uniform_bind_group_layout,
+            (config.width as u32, config.height as u32),
+            wgpu::FilterMode::Linear,
+            "Fluid Texture",
+        );
+        Self {
+            fluid,
+            quad,
+            pixels: Vec::new(),
+        }
+    }
+
+    pub fn upload(&mut self, queue: &wgpu::Queue, camera: &Camera, view: FluidView) {
+        self.fluid.write_rgba(view, &mut self.pixels);
+        self.quad.upload(queue, &self.pixels);
+        let (min, max) = self.fluid.world_rect();
+        self.quad.set_rect(queue, camera, min, max);
+    }
+}
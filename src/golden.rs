@@ -0,0 +1,332 @@
+// Golden-image tests: known scenes are rendered offscreen on the software
+// adapter and compared against the reference PNGs in `tests/golden`.
+// Run with `UPDATE_GOLDEN=1 cargo test golden` to accept new output after an
+// intended change; mismatches leave actual and diff images in `target/golden`.
+// Without a software adapter the tests fail, unless `GOLDEN_SKIP_NO_ADAPTER=1`
+// is set on machines that knowingly can't run them.
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use crate::colormap::Coloring;
+use crate::heatmap::LodMode;
+use crate::post::PostEffect;
+use crate::rigid::BodyVertex;
+use crate::sim::Particle;
+use crate::wgpu_ctx::{ParticleShape, WgpuCtx};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+// Channels may differ by this much before a pixel counts as changed
+const CHANNEL_TOLERANCE: u8 = 3;
+// Fraction of changed pixels allowed, for rasterizer differences along edges
+const PIXEL_TOLERANCE: f64 = 0.002;
+
+fn reference_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+fn software_ctx(test: &str) -> Option<WgpuCtx<'static>> {
+    let ctx = WgpuCtx::new_software(WIDTH, HEIGHT);
+    if ctx.is_none() {
+        assert!(
+            std::env::var_os("GOLDEN_SKIP_NO_ADAPTER").is_some(),
+            "{test}: no software adapter; set GOLDEN_SKIP_NO_ADAPTER=1 to skip golden tests"
+        );
+        eprintln!("Skipping {test}: no software adapter");
+    }
+    ctx
+}
+
+/// Counts pixels that differ beyond the tolerance and marks them red over a
+/// dimmed copy of the reference.
+fn diff(reference: &RgbaImage, actual: &RgbaImage) -> (usize, RgbaImage) {
+    let mut changed = 0;
+    let mut image = RgbaImage::new(reference.width(), reference.height());
+    for ((expected, got), out) in reference.pixels().zip(actual.pixels()).zip(image.pixels_mut()) {
+        let differs = expected
+            .0
+            .iter()
+            .zip(got.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE);
+        *out = if differs {
+            changed += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0;
+            let luma = ((r as u32 + g as u32 + b as u32) / 12) as u8;
+            Rgba([luma, luma, luma, 255])
+        };
+    }
+    (changed, image)
+}
+
+fn check(name: &str, actual: &RgbaImage) {
+    let reference_path = reference_dir().join(format!("{name}.png"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(reference_dir()).unwrap();
+        actual.save(&reference_path).unwrap();
+        println!("Updated {}", reference_path.display());
+        return;
+    }
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.to_rgba8(),
+        Err(e) => panic!(
+            "No reference image at {} ({e}); run with UPDATE_GOLDEN=1 to create it",
+            reference_path.display()
+        ),
+    };
+    let output = output_dir();
+    std::fs::create_dir_all(&output).unwrap();
+    let actual_path = output.join(format!("{name}-actual.png"));
+    if reference.dimensions() != actual.dimensions() {
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "{name}: rendered {:?}, reference is {:?}; output written to {}",
+            actual.dimensions(),
+            reference.dimensions(),
+            actual_path.display()
+        );
+    }
+
+    let (changed, diff_image) = diff(&reference, actual);
+    let allowed = (PIXEL_TOLERANCE * (actual.width() * actual.height()) as f64) as usize;
+    if changed > allowed {
+        let diff_path = output.join(format!("{name}-diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff_image.save(&diff_path).unwrap();
+        panic!(
+            "{name}: {changed} pixels differ from the reference (at most {allowed} allowed); see {} and {}",
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn particle_pipeline() {
+    let Some(mut ctx) = software_ctx("particle_pipeline") else {
+        return;
+    };
+    ctx.camera.zoom = 0.2;
+
+    // Every species tint, a few marker angles and one selected particle
+    let particles: Vec<Particle> = (0..6)
+        .map(|i| Particle {
+            position: [-4.0 + 4.0 * (i % 3) as f64, if i < 3 { 2.5 } else { -2.5 }],
+            angle: i as f32 * 0.9,
+            mass: 1.0,
+            species: (i % 4) as u8,
+            ..Default::default()
+        })
+        .collect();
+    ctx.update_instances(&particles, &[4], &[]);
+
+    // A quad behind the middle column, for the polygon pipeline
+    let color = [0.2, 0.4, 0.7];
+    let quad = [[-1.5, -4.0], [1.5, -4.0], [1.5, 4.0], [-1.5, -4.0], [1.5, 4.0], [-1.5, 4.0]]
+        .map(|position| BodyVertex { position, color });
+    ctx.update_polygons(&quad);
+
+    check("particles", &ctx.render_to_image());
+}
+
+#[test]
+fn density_heatmap() {
+    let Some(mut ctx) = software_ctx("density_heatmap") else {
+        return;
+    };
+    ctx.lod.mode = LodMode::Heatmap;
+    ctx.lod.saturation = 16.0;
+    ctx.camera.zoom = 0.01;
+
+    // Columns of particles getting denser from left to right
+    let mut particles = Vec::new();
+    for column in 0..16 {
+        let per_cell = column + 1;
+        for row in 0..60 {
+            for k in 0..per_cell {
+                particles.push(Particle {
+                    position: [-120.0 + 15.0 * column as f64 + k as f64 * 0.01, -90.0 + 3.0 * row as f64],
+                    mass: 1.0,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    ctx.set_colormap(&Coloring::default().table());
+    ctx.update_instances(&particles, &[], &[]);
+
+    check("heatmap", &ctx.render_to_image());
+}
+
+#[test]
+fn motion_trails() {
+    let Some(mut ctx) = software_ctx("motion_trails") else {
+        return;
+    };
+    ctx.camera.zoom = 0.2;
+    ctx.trails.enabled = true;
+    ctx.trails.length = 8.0;
+    ctx.trails.fade_color = [0.1, 0.0, 0.2];
+
+    // One particle stepping right leaves a fading streak behind it
+    let mut image = None;
+    for frame in 0..12 {
+        let particle = Particle {
+            position: [-6.0 + frame as f64, 0.0],
+            mass: 1.0,
+            ..Default::default()
+        };
+        ctx.update_instances(&[particle], &[], &[]);
+        image = Some(ctx.render_to_image());
+    }
+
+    check("trails", &image.unwrap());
+}
+
+#[test]
+fn bloom_and_tonemap() {
+    let Some(mut ctx) = software_ctx("bloom_and_tonemap") else {
+        return;
+    };
+    ctx.camera.zoom = 0.2;
+    ctx.particle_style.shape = ParticleShape::Glow;
+    for pass in &mut ctx.post.passes {
+        pass.enabled = true;
+        if let PostEffect::Bloom(bloom) = &mut pass.effect {
+            bloom.intensity = 2.0;
+        }
+    }
+
+    // A bright row that blooms, next to a dim one that stays under the threshold
+    let particles: Vec<Particle> = (0..8)
+        .map(|i| Particle {
+            position: [-7.0 + 2.0 * (i % 4) as f64 + if i < 4 { 0.0 } else { 8.0 }, if i < 4 { 2.5 } else { -2.5 }],
+            mass: 1.0,
+            species: if i < 4 { 0 } else { 1 },
+            ..Default::default()
+        })
+        .collect();
+    ctx.update_instances(&particles, &[], &[]);
+
+    check("bloom", &ctx.render_to_image());
+}
+
+#[test]
+fn debug_gizmos() {
+    let Some(mut ctx) = software_ctx("debug_gizmos") else {
+        return;
+    };
+    ctx.camera.zoom = 0.2;
+
+    // One of each shape, some over a particle to check they draw on top
+    ctx.update_instances(
+        &[Particle {
+            position: [3.0, 0.0],
+            mass: 1.0,
+            ..Default::default()
+        }],
+        &[],
+        &[],
+    );
+    ctx.debug.line([-6.0, -4.0], [6.0, -4.0], [1.0, 1.0, 1.0, 1.0]);
+    ctx.debug.rect([-6.5, -3.0], [-1.5, 3.0], [1.0, 0.9, 0.2, 1.0]);
+    ctx.debug.circle([3.0, 0.0], 2.0, [1.0, 0.3, 0.3, 1.0]);
+    ctx.debug.arrow([-4.0, -2.0], [-2.5, 2.0], [0.3, 1.0, 0.5, 0.5]);
+
+    check("debug", &ctx.render_to_image());
+}
+
+#[test]
+fn world_grid() {
+    let Some(mut ctx) = software_ctx("world_grid") else {
+        return;
+    };
+    ctx.grid.enabled = true;
+    // Both axes and a major line in view, with minor lines just fading in
+    ctx.camera.position = [4.0, -2.0];
+    ctx.camera.zoom = 0.2;
+    ctx.update_instances(&[], &[], &[]);
+
+    check("grid", &ctx.render_to_image());
+}
+
+#[test]
+fn imgui_renderer() {
+    let Some(ctx) = software_ctx("imgui_renderer") else {
+        return;
+    };
+    let mut context = imgui::Context::create();
+    context.set_ini_filename(None);
+    context.io_mut().display_size = [WIDTH as f32, HEIGHT as f32];
+    context.io_mut().delta_time = 1.0 / 60.0;
+    let mut renderer = imgui_wgpu::Renderer::new(
+        &mut context,
+        &ctx.device,
+        &ctx.queue,
+        imgui_wgpu::RendererConfig {
+            texture_format: ctx.surface_config.format,
+            ..Default::default()
+        },
+    );
+
+    // Windows settle their layout over the first few frames
+    let mut image = None;
+    for _ in 0..3 {
+        let ui = context.frame();
+        ui.window("Golden")
+            .position([8.0, 8.0], imgui::Condition::Always)
+            .size([144.0, 104.0], imgui::Condition::Always)
+            .build(|| {
+                ui.text("Particles: 1234");
+                ui.button("Capture");
+                let mut checked = true;
+                ui.checkbox("Overlay", &mut checked);
+                let mut value = 0.25;
+                ui.slider("Zoom", 0.0, 1.0, &mut value);
+            });
+        let draw_data = context.render();
+
+        let frame = ctx.acquire_frame();
+        let view = frame.texture().create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.1,
+                            g: 0.1,
+                            b: 0.1,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            renderer
+                .render(draw_data, &ctx.queue, &ctx.device, &mut pass)
+                .expect("Failed to render ImGui");
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+        image = Some(ctx.read_texture(frame.texture()));
+    }
+
+    check("imgui", &image.unwrap());
+}
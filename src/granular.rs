@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+
+use crate::grid::SpatialGrid;
+use crate::sim::{Sim, SIMD_LEVEL};
+
+/// Contact model for sand and grain piles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GranularSettings {
+    pub enabled: bool,
+    // Coulomb coefficient: tangential impulse is limited to `friction` times the normal impulse
+    pub friction: f32,
+    // Opposes relative rolling, limited to `rolling_resistance * radius` times the normal impulse
+    pub rolling_resistance: f32,
+    pub restitution: f32,
+    pub iterations: u32,
+}
+
+impl Default for GranularSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            friction: 0.5,
+            rolling_resistance: 0.1,
+            restitution: 0.1,
+            iterations: 10,
+        }
+    }
+}
+
+// Marks a contact with the bounds instead of another particle
+const WALL: u32 = u32::MAX;
+// Penetration left uncorrected, and the gap within which contacts are kept,
+// so resting contacts persist between substeps
+const SLOP: f32 = 0.01;
+// Approach speed below which contacts do not bounce
+const BOUNCE_THRESHOLD: f32 = 1.0;
+
+#[derive(Copy, Clone)]
+struct Contact {
+    a: u32,
+    b: u32,
+    // Unit vector from `a` towards `b` (or into the wall)
+    normal: [f32; 2],
+    // Separating speed the normal impulse aims for
+    bounce: f32,
+    normal_impulse: f32,
+    tangent_impulse: f32,
+    rolling_impulse: f32,
+}
+
+/// Scratch state for the contact solver, reused across substeps.
+#[derive(Clone, Default)]
+pub struct Granular {
+    pub settings: GranularSettings,
+    positions: Vec<[f32; 2]>,
+    velocities: Vec<[f32; 2]>,
+    angular_velocities: Vec<f32>,
+    inv_masses: Vec<f32>,
+    contacts: Vec<Contact>,
+    // Accumulated impulses of the previous substep, for warm starting
+    previous: HashMap<(u32, u32), [f32; 3]>,
+    grid: SpatialGrid,
+}
+
+struct Body {
+    inv_mass: f32,
+    inv_inertia: f32,
+}
+
+const STATIC: Body = Body {
+    inv_mass: 0.0,
+    inv_inertia: 0.0,
+};
+
+fn cross(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+impl Granular {
+    /// Drops the warm-start impulses, which are keyed by particle index and go
+    /// stale once particles are removed or the state is replaced.
+    pub fn forget_contacts(&mut self) {
+        self.previous.clear();
+    }
+
+    fn gather(&mut self, sim: &Sim) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.angular_velocities.clear();
+        self.inv_masses.clear();
+        for index in 0..sim.count {
+            let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+            self.positions.push([sim.x[i][j], sim.y[i][j]]);
+            self.velocities.push([sim.x_vel[i][j], sim.y_vel[i][j]]);
+            self.angular_velocities.push(sim.angular_velocity[i][j]);
+            self.inv_masses.push(1.0 / sim.mass[i][j]);
+        }
+    }
+
+    fn scatter(&self, sim: &mut Sim, dt: f32) {
+        for index in 0..sim.count {
+            let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+            sim.x[i][j] = self.positions[index][0];
+            sim.y[i][j] = self.positions[index][1];
+            sim.x_vel[i][j] = self.velocities[index][0];
+            sim.y_vel[i][j] = self.velocities[index][1];
+            sim.angular_velocity[i][j] = self.angular_velocities[index];
+            sim.angle[i][j] += self.angular_velocities[index] * dt;
+        }
+    }
+
+    fn find_contacts(&mut self, radius: f32, bounds: [f32; 2]) {
+        self.contacts.clear();
+        let diameter = 2.0 * radius;
+        let reach = diameter + SLOP;
+        self.grid.build(&self.positions, reach);
+
+        for (a, &pa) in self.positions.iter().enumerate() {
+            let min = [pa[0] - reach, pa[1] - reach];
+            let max = [pa[0] + reach, pa[1] + reach];
+            let positions = &self.positions;
+            let contacts = &mut self.contacts;
+            self.grid.for_each_in_box(min, max, |b| {
+                if b as usize <= a {
+                    return;
+                }
+                let pb = positions[b as usize];
+                let d = [pb[0] - pa[0], pb[1] - pa[1]];
+                let distance_squared = d[0] * d[0] + d[1] * d[1];
+                if distance_squared >= reach * reach || distance_squared == 0.0 {
+                    return;
+                }
+                let distance = distance_squared.sqrt();
+                contacts.push(Contact {
+                    a: a as u32,
+                    b,
+                    normal: [d[0] / distance, d[1] / distance],
+                    bounce: 0.0,
+                    normal_impulse: 0.0,
+                    tangent_impulse: 0.0,
+                    rolling_impulse: 0.0,
+                });
+            });
+
+            // Walls, with the normal pointing out of the box
+            let walls = [
+                ([1.0, 0.0], bounds[0] - pa[0]),
+                ([-1.0, 0.0], pa[0] + bounds[0]),
+                ([0.0, 1.0], bounds[1] - pa[1]),
+                ([0.0, -1.0], pa[1] + bounds[1]),
+            ];
+            for (normal, gap) in walls {
+                if gap < radius + SLOP {
+                    self.contacts.push(Contact {
+                        a: a as u32,
+                        b: WALL,
+                        normal,
+                        bounce: 0.0,
+                        normal_impulse: 0.0,
+                        tangent_impulse: 0.0,
+                        rolling_impulse: 0.0,
+                    });
+                }
+            }
+        }
+    }
+
+    fn key(contact: &Contact) -> (u32, u32) {
+        // Walls are told apart by their normal
+        if contact.b == WALL {
+            let side = if contact.normal[0] > 0.5 {
+                0
+            } else if contact.normal[0] < -0.5 {
+                1
+            } else if contact.normal[1] > 0.5 {
+                2
+            } else {
+                3
+            };
+            (contact.a, WALL - side)
+        } else {
+            (contact.a, contact.b)
+        }
+    }
+
+    fn bodies(&self, contact: &Contact, radius: f32) -> (Body, Body) {
+        // Solid disks: I = m r² / 2
+        let particle = |index: u32| {
+            let inv_mass = self.inv_masses[index as usize];
+            Body {
+                inv_mass,
+                inv_inertia: 2.0 * inv_mass / (radius * radius),
+            }
+        };
+        let other = if contact.b == WALL { STATIC } else { particle(contact.b) };
+        (particle(contact.a), other)
+    }
+
+    fn state(&self, index: u32) -> ([f32; 2], f32) {
+        if index == WALL {
+            ([0.0, 0.0], 0.0)
+        } else {
+            (
+                self.velocities[index as usize],
+                self.angular_velocities[index as usize],
+            )
+        }
+    }
+
+    /// Velocity of `b`'s surface relative to `a`'s at the contact point.
+    fn relative_velocity(&self, contact: &Contact, radius: f32) -> [f32; 2] {
+        let n = contact.normal;
+        let (va, wa) = self.state(contact.a);
+        let (vb, wb) = self.state(contact.b);
+        // Contact point offsets are +n * r from a and -n * r from b, so w x r = w * r * perp(n)
+        let perp = [-n[1], n[0]];
+        [
+            vb[0] - wb * radius * perp[0] - (va[0] + wa * radius * perp[0]),
+            vb[1] - wb * radius * perp[1] - (va[1] + wa * radius * perp[1]),
+        ]
+    }
+
+    /// Applies a linear impulse `p` to `b` (and `-p` to `a`) at the contact point, plus a rolling torque impulse.
+    fn apply(&mut self, contact: &Contact, radius: f32, p: [f32; 2], torque: f32) {
+        let (body_a, body_b) = self.bodies(contact, radius);
+        let n = contact.normal;
+        let ra = [n[0] * radius, n[1] * radius];
+        let rb = [-ra[0], -ra[1]];
+
+        let a = contact.a as usize;
+        self.velocities[a][0] -= p[0] * body_a.inv_mass;
+        self.velocities[a][1] -= p[1] * body_a.inv_mass;
+        self.angular_velocities[a] -= (cross(ra, p) - torque) * body_a.inv_inertia;
+
+        if contact.b != WALL {
+            let b = contact.b as usize;
+            self.velocities[b][0] += p[0] * body_b.inv_mass;
+            self.velocities[b][1] += p[1] * body_b.inv_mass;
+            self.angular_velocities[b] += (cross(rb, p) - torque) * body_b.inv_inertia;
+        }
+    }
+
+    fn solve_velocities(&mut self, radius: f32) {
+        let settings = self.settings;
+        for c in 0..self.contacts.len() {
+            let mut contact = self.contacts[c];
+            let (body_a, body_b) = self.bodies(&contact, radius);
+            let n = contact.normal;
+            let t = [-n[1], n[0]];
+
+            // Normal: no approach, bounce only on fast impacts
+            let v = self.relative_velocity(&contact, radius);
+            let vn = v[0] * n[0] + v[1] * n[1];
+            let k_normal = body_a.inv_mass + body_b.inv_mass;
+            let lambda = (contact.bounce - vn) / k_normal;
+            let accumulated = (contact.normal_impulse + lambda).max(0.0);
+            let lambda = accumulated - contact.normal_impulse;
+            contact.normal_impulse = accumulated;
+            self.apply(&contact, radius, [n[0] * lambda, n[1] * lambda], 0.0);
+
+            // Tangent: Coulomb friction cone
+            let v = self.relative_velocity(&contact, radius);
+            let vt = v[0] * t[0] + v[1] * t[1];
+            let k_tangent = body_a.inv_mass
+                + body_b.inv_mass
+                + radius * radius * (body_a.inv_inertia + body_b.inv_inertia);
+            let limit = settings.friction * contact.normal_impulse;
+            let accumulated = (contact.tangent_impulse - vt / k_tangent).clamp(-limit, limit);
+            let lambda = accumulated - contact.tangent_impulse;
+            contact.tangent_impulse = accumulated;
+            self.apply(&contact, radius, [t[0] * lambda, t[1] * lambda], 0.0);
+
+            // Rolling resistance on the relative spin
+            let k_rolling = body_a.inv_inertia + body_b.inv_inertia;
+            if k_rolling > 0.0 {
+                let (_, wa) = self.state(contact.a);
+                let (_, wb) = self.state(contact.b);
+                let limit = settings.rolling_resistance * radius * contact.normal_impulse;
+                let accumulated =
+                    (contact.rolling_impulse - (wa - wb) / k_rolling).clamp(-limit, limit);
+                let torque = accumulated - contact.rolling_impulse;
+                contact.rolling_impulse = accumulated;
+                self.apply(&contact, radius, [0.0, 0.0], torque);
+            }
+
+            self.contacts[c] = contact;
+        }
+    }
+
+    /// Pushes overlapping particles apart without adding velocity.
+    fn correct_positions(&mut self, radius: f32, bounds: [f32; 2]) {
+        const ITERATIONS: usize = 4;
+        const STRENGTH: f32 = 0.8;
+        for _ in 0..ITERATIONS {
+            for contact in &self.contacts {
+                let a = contact.a as usize;
+                let pa = self.positions[a];
+                if contact.b == WALL {
+                    let n = contact.normal;
+                    let gap = if n[0] != 0.0 {
+                        bounds[0] - pa[0] * n[0]
+                    } else {
+                        bounds[1] - pa[1] * n[1]
+                    };
+                    let penetration = radius - gap - SLOP;
+                    if penetration > 0.0 {
+                        self.positions[a][0] -= n[0] * penetration * STRENGTH;
+                        self.positions[a][1] -= n[1] * penetration * STRENGTH;
+                    }
+                    continue;
+                }
+
+                let b = contact.b as usize;
+                let pb = self.positions[b];
+                let d = [pb[0] - pa[0], pb[1] - pa[1]];
+                let distance = (d[0] * d[0] + d[1] * d[1]).sqrt();
+                let penetration = 2.0 * radius - distance - SLOP;
+                if penetration <= 0.0 || distance == 0.0 {
+                    continue;
+                }
+                // The lighter particle moves further
+                let (wa, wb) = (self.inv_masses[a], self.inv_masses[b]);
+                let shift = penetration * STRENGTH / (distance * (wa + wb));
+                self.positions[a][0] -= d[0] * shift * wa;
+                self.positions[a][1] -= d[1] * shift * wa;
+                self.positions[b][0] += d[0] * shift * wb;
+                self.positions[b][1] += d[1] * shift * wb;
+            }
+        }
+    }
+}
+
+impl Sim {
+    /// Integrates one substep with particle contacts, friction and rotation.
+    /// Returns the number of contacts.
+    pub fn step_granular(&mut self, dt: f32) -> u32 {
+        let mut granular = std::mem::take(&mut self.granular);
+        let radius = self.radius;
+        let bounds = self.bounds;
+        granular.gather(self);
+
+        for velocity in granular.velocities.iter_mut() {
+            velocity[1] += self.gravity * dt;
+        }
+
+        granular.find_contacts(radius, bounds);
+
+        // Bounce targets come from the approach speed before any impulses
+        for c in 0..granular.contacts.len() {
+            let contact = granular.contacts[c];
+            let v = granular.relative_velocity(&contact, radius);
+            let vn = v[0] * contact.normal[0] + v[1] * contact.normal[1];
+            if vn < -BOUNCE_THRESHOLD {
+                granular.contacts[c].bounce = -granular.settings.restitution * vn;
+            }
+        }
+
+        // Warm start from the impulses the same pairs needed last substep
+        for c in 0..granular.contacts.len() {
+            let mut contact = granular.contacts[c];
+            if let Some(&[normal, tangent, rolling]) = granular.previous.get(&Granular::key(&contact)) {
+                let n = contact.normal;
+                let t = [-n[1], n[0]];
+                let p = [n[0] * normal + t[0] * tangent, n[1] * normal + t[1] * tangent];
+                granular.apply(&contact, radius, p, rolling);
+                contact.normal_impulse = normal;
+                contact.tangent_impulse = tangent;
+                contact.rolling_impulse = rolling;
+                granular.contacts[c] = contact;
+            }
+        }
+
+        for _ in 0..granular.settings.iterations {
+            granular.solve_velocities(radius);
+        }
+
+        granular.previous.clear();
+        for contact in &granular.contacts {
+            granular.previous.insert(
+                Granular::key(contact),
+                [contact.normal_impulse, contact.tangent_impulse, contact.rolling_impulse],
+            );
+        }
+
+        for (position, velocity) in granular.positions.iter_mut().zip(&granular.velocities) {
+            position[0] += velocity[0] * dt;
+            position[1] += velocity[1] * dt;
+        }
+        granular.correct_positions(radius, bounds);
+        for position in granular.positions.iter_mut() {
+            position[0] = position[0].clamp(-bounds[0] + radius, bounds[0] - radius);
+            position[1] = position[1].clamp(-bounds[1] + radius, bounds[1] - radius);
+        }
+
+        // Normal force per unit circumference, summed over both sides of each contact
+        let circumference = std::f32::consts::TAU * radius;
+        self.pressure.fill(0.0);
+        for contact in &granular.contacts {
+            let force = contact.normal_impulse / (dt * circumference);
+            self.pressure[contact.a as usize] += force;
+            if contact.b != WALL {
+                self.pressure[contact.b as usize] += force;
+            }
+        }
+
+        granular.scatter(self, dt);
+        let contacts = granular.contacts.len() as u32;
+        self.granular = granular;
+        contacts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::Preset;
+    use crate::sim::{SimConfig, TimeStep};
+
+    #[test]
+    fn pile_settles() {
+        let mut sim = Sim::new(&SimConfig {
+            count: 150,
+            preset: Preset::Grid,
+            seed: 1,
+            spacing: 2.5,
+            speed: 0.0,
+        });
+        sim.bounds = [20.0, 60.0];
+        sim.gravity = -9.8;
+        sim.granular.settings.enabled = true;
+        let time_step = TimeStep {
+            dt: 1.0 / 60.0,
+            ..Default::default()
+        };
+
+        // Ten simulated seconds is plenty for the grid to fall and come to rest
+        for _ in 0..600 {
+            sim.advance(&time_step);
+        }
+
+        let stats = sim.stats();
+        assert!(stats.max_speed < 0.1, "pile still moving: max speed {}", stats.max_speed);
+
+        let positions: Vec<[f32; 2]> = sim
+            .particles()
+            .iter()
+            .map(|particle| particle.position.map(|v| v as f32))
+            .collect();
+        for (a, pa) in positions.iter().enumerate() {
+            assert!(pa[1] >= -sim.bounds[1], "particle {} fell through the floor", a);
+            for pb in &positions[a + 1..] {
+                let distance = ((pa[0] - pb[0]).powi(2) + (pa[1] - pb[1]).powi(2)).sqrt();
+                assert!(distance > 1.8 * sim.radius, "particles overlap: {}", distance);
+            }
+        }
+
+        // Stacked at least a few layers high, not spread into a single row
+        let top = positions.iter().map(|p| p[1]).fold(f32::MIN, f32::max);
+        assert!(top > -sim.bounds[1] + 6.0 * sim.radius, "pile collapsed flat: top at {}", top);
+    }
+}
@@ -0,0 +1,198 @@
+/// Uniform grid over a set of points, rebuilt from scratch with a counting sort.
+///
+/// Cells cover the bounding box of the points it was built from; queries
+/// outside that box are clamped to the border cells.
+#[derive(Clone, Default)]
+pub struct SpatialGrid {
+    pub cell_size: f32,
+    origin: [f32; 2],
+    dims: [usize; 2],
+    // Entries of cell `c` are `entries[cell_start[c]..cell_start[c + 1]]`
+    cell_start: Vec<u32>,
+    entries: Vec<u32>,
+}
+
+impl SpatialGrid {
+    /// Caps memory when points are spread far apart relative to the cell size.
+    const MAX_CELLS_PER_POINT: usize = 4;
+
+    pub fn build(&mut self, positions: &[[f32; 2]], cell_size: f32) {
+        self.cell_size = cell_size.max(f32::EPSILON);
+        self.entries.clear();
+        self.cell_start.clear();
+        if positions.is_empty() {
+            self.dims = [0, 0];
+            return;
+        }
+
+        let mut min = [f32::MAX; 2];
+        let mut max = [f32::MIN; 2];
+        for p in positions {
+            min = [min[0].min(p[0]), min[1].min(p[1])];
+            max = [max[0].max(p[0]), max[1].max(p[1])];
+        }
+
+        // Grow cells until the grid is small relative to the point count
+        let budget = positions.len() * Self::MAX_CELLS_PER_POINT + 1024;
+        loop {
+            self.dims = [
+                ((max[0] - min[0]) / self.cell_size) as usize + 1,
+                ((max[1] - min[1]) / self.cell_size) as usize + 1,
+            ];
+            if self.dims[0].saturating_mul(self.dims[1]) <= budget {
+                break;
+            }
+            self.cell_size *= 2.0;
+        }
+        self.origin = min;
+
+        let cells = self.dims[0] * self.dims[1];
+        self.cell_start.resize(cells + 1, 0);
+        for p in positions {
+            let cell = self.cell_index(*p);
+            self.cell_start[cell + 1] += 1;
+        }
+        for c in 0..cells {
+            self.cell_start[c + 1] += self.cell_start[c];
+        }
+
+        self.entries.resize(positions.len(), 0);
+        let mut cursor = self.cell_start.clone();
+        for (i, p) in positions.iter().enumerate() {
+            let cell = self.cell_index(*p);
+            self.entries[cursor[cell] as usize] = i as u32;
+            cursor[cell] += 1;
+        }
+    }
+
+    fn cell_coords(&self, p: [f32; 2]) -> [usize; 2] {
+        let x = ((p[0] - self.origin[0]) / self.cell_size).max(0.0) as usize;
+        let y = ((p[1] - self.origin[1]) / self.cell_size).max(0.0) as usize;
+        [x.min(self.dims[0] - 1), y.min(self.dims[1] - 1)]
+    }
+
+    fn cell_index(&self, p: [f32; 2]) -> usize {
+        let [x, y] = self.cell_coords(p);
+        y * self.dims[0] + x
+    }
+
+    /// Calls `f` with every point in the cells overlapping the box `min..max`.
+    /// Callers filter by exact distance.
+    pub fn for_each_in_box(&self, min: [f32; 2], max: [f32; 2], mut f: impl FnMut(u32)) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let [x0, y0] = self.cell_coords(min);
+        let [x1, y1] = self.cell_coords(max);
+        for y in y0..=y1 {
+            let row = y * self.dims[0];
+            for x in x0..=x1 {
+                let cell = row + x;
+                let range = self.cell_start[cell] as usize..self.cell_start[cell + 1] as usize;
+                for &entry in &self.entries[range] {
+                    f(entry);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with every point in the 3x3 block of cells around `cell`,
+    /// which may lie one cell outside the grid.
+    pub fn for_each_near_cell(&self, cell: [isize; 2], mut f: impl FnMut(u32)) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let range = |axis: usize| (cell[axis] - 1).max(0)..=(cell[axis] + 1).min(self.dims[axis] as isize - 1);
+        for y in range(1) {
+            for x in range(0) {
+                let cell = y as usize * self.dims[0] + x as usize;
+                let entries = self.cell_start[cell] as usize..self.cell_start[cell + 1] as usize;
+                for &entry in &self.entries[entries] {
+                    f(entry);
+                }
+            }
+        }
+    }
+
+    /// Walks the cells crossed by the segment `from..to` in order, calling
+    /// `visit` with the segment parameter (0..1) where each cell is entered.
+    /// The walk covers a ring of empty cells around the grid, so points near
+    /// the border are reachable through `for_each_near_cell`. Stops early
+    /// when `visit` returns false.
+    pub fn traverse(&self, from: [f32; 2], to: [f32; 2], mut visit: impl FnMut(f32, [isize; 2]) -> bool) {
+        let Some((min, max)) = self.bounds() else {
+            return;
+        };
+        let origin = [min[0] - self.cell_size, min[1] - self.cell_size];
+        let max = [max[0] + self.cell_size, max[1] + self.cell_size];
+        let d = [to[0] - from[0], to[1] - from[1]];
+
+        // Clip the segment to the padded grid
+        let (mut t_enter, mut t_exit) = (0.0f32, 1.0f32);
+        for axis in 0..2 {
+            if d[axis] == 0.0 {
+                if from[axis] < origin[axis] || from[axis] > max[axis] {
+                    return;
+                }
+                continue;
+            }
+            let t0 = (origin[axis] - from[axis]) / d[axis];
+            let t1 = (max[axis] - from[axis]) / d[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter > t_exit {
+            return;
+        }
+
+        let mut cell = [0isize; 2];
+        let mut step = [0isize; 2];
+        let mut t_max = [f32::INFINITY; 2];
+        let mut t_delta = [f32::INFINITY; 2];
+        for axis in 0..2 {
+            let start = from[axis] + d[axis] * t_enter;
+            let last = self.dims[axis] as isize;
+            cell[axis] = (((start - self.origin[axis]) / self.cell_size).floor() as isize).clamp(-1, last);
+            if d[axis] > 0.0 {
+                step[axis] = 1;
+                let boundary = self.origin[axis] + (cell[axis] + 1) as f32 * self.cell_size;
+                t_max[axis] = (boundary - from[axis]) / d[axis];
+                t_delta[axis] = self.cell_size / d[axis];
+            } else if d[axis] < 0.0 {
+                step[axis] = -1;
+                let boundary = self.origin[axis] + cell[axis] as f32 * self.cell_size;
+                t_max[axis] = (boundary - from[axis]) / d[axis];
+                t_delta[axis] = -self.cell_size / d[axis];
+            }
+        }
+
+        let mut t = t_enter;
+        loop {
+            if !visit(t, cell) {
+                return;
+            }
+            let axis = if t_max[0] < t_max[1] { 0 } else { 1 };
+            if t_max[axis] > t_exit {
+                return;
+            }
+            cell[axis] += step[axis];
+            if cell[axis] < -1 || cell[axis] > self.dims[axis] as isize {
+                return;
+            }
+            t = t_max[axis];
+            t_max[axis] += t_delta[axis];
+        }
+    }
+
+    /// Corners of the area covered by cells, or `None` when empty.
+    pub fn bounds(&self) -> Option<([f32; 2], [f32; 2])> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let max = [
+            self.origin[0] + self.dims[0] as f32 * self.cell_size,
+            self.origin[1] + self.dims[1] as f32 * self.cell_size,
+        ];
+        Some((self.origin, max))
+    }
+}
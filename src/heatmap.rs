@@ -0,0 +1,302 @@
+use std::borrow::Cow;
+use wgpu::ShaderSource;
+
+use crate::render_graph::{RenderGraph, ResourceId, TargetSize};
+use crate::trails::SCENE_FORMAT;
+use crate::wgpu_ctx::InstanceData;
+
+// Counts per pixel; half floats are blendable everywhere and exact up to 2048
+const DENSITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+/// Whether particles are drawn one by one or as a density heatmap.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LodMode {
+    Auto,
+    Particles,
+    Heatmap,
+}
+
+impl LodMode {
+    pub const ALL: [LodMode; 3] = [LodMode::Auto, LodMode::Particles, LodMode::Heatmap];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LodMode::Auto => "Auto",
+            LodMode::Particles => "Particles",
+            LodMode::Heatmap => "Heatmap",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lod {
+    pub mode: LodMode,
+    // In auto mode, particles narrower than this many pixels switch to the heatmap
+    pub switch_diameter: f32,
+    // Particles per pixel mapped to the top of the colormap
+    pub saturation: f32,
+}
+
+impl Default for Lod {
+    fn default() -> Self {
+        Self {
+            mode: LodMode::Auto,
+            switch_diameter: 1.5,
+            saturation: 64.0,
+        }
+    }
+}
+
+impl Lod {
+    /// Whether particles of world radius `radius` go to the heatmap at this zoom.
+    pub fn use_heatmap(&self, radius: f32, world_per_pixel: f32) -> bool {
+        match self.mode {
+            LodMode::Auto => 2.0 * radius / world_per_pixel < self.switch_diameter,
+            LodMode::Particles => false,
+            LodMode::Heatmap => true,
+        }
+    }
+}
+
+/// Splats particle centres into a screen-sized density texture, then draws it
+/// through the colormap. Cost no longer depends on how many particles share a pixel.
+pub struct Heatmap {
+    sampler: wgpu::Sampler,
+    colormap: wgpu::TextureView,
+    colormap_sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    splat_pipeline: wgpu::RenderPipeline,
+    tonemap_pipeline: wgpu::RenderPipeline,
+}
+
+impl Heatmap {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        colormap: &wgpu::TextureView,
+        colormap_sampler: &wgpu::Sampler,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Density"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tonemap Uniform Buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Heatmap Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                sampler_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Heatmap Shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("heatmap.wgsl"))),
+        });
+
+        let splat_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Heatmap Splat Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let splat_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Heatmap Splat Pipeline"),
+            layout: Some(&splat_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_splat"),
+                // The particle instance buffer, read one point per particle
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<InstanceData>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_splat"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: DENSITY_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::PointList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let tonemap_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Heatmap Tonemap Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &layout],
+            push_constant_ranges: &[],
+        });
+        let tonemap_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Heatmap Tonemap Pipeline"),
+            layout: Some(&tonemap_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_tonemap"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_tonemap"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: SCENE_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            sampler,
+            colormap: colormap.clone(),
+            colormap_sampler: colormap_sampler.clone(),
+            uniform_buffer,
+            layout,
+            splat_pipeline,
+            tonemap_pipeline,
+        }
+    }
+
+    pub fn set_saturation(&self, queue: &wgpu::Queue, saturation: f32) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[saturation.max(1.0), 0.0, 0.0, 0.0]));
+    }
+
+    /// Adds a node counting the first `count` instances into a density texture,
+    /// and returns that texture for `draw`.
+    pub fn add_splat_node<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        instances: &'a wgpu::Buffer,
+        count: u32,
+    ) -> ResourceId {
+        let density = graph.transient(DENSITY_FORMAT, TargetSize::Output);
+        let instances = graph.import_buffer(instances);
+        graph.add_node("Heatmap splat", &[instances], &[density], move |encoder, resources| {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Heatmap Splat Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: resources.view(density),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.splat_pipeline);
+            pass.set_bind_group(0, camera_bind_group, &[]);
+            pass.set_vertex_buffer(0, resources.buffer(instances).slice(..));
+            pass.draw(0..count, 0..1);
+        });
+        density
+    }
+
+    /// Draws the splatted `density` over the scene; empty pixels are left alone.
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        pass: &mut wgpu::RenderPass<'_>,
+        camera_bind_group: &wgpu::BindGroup,
+        density: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Heatmap Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(density),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.colormap),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.colormap_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        pass.set_pipeline(&self.tonemap_pipeline);
+        pass.set_bind_group(0, camera_bind_group, &[]);
+        pass.set_bind_group(1, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,51 @@
+struct CameraUniform {
+    view_matrix: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+// One point per particle; additive blending counts them per pixel
+@vertex
+fn vs_splat(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return camera.view_matrix * vec4<f32>(position, 0.0, 1.0);
+}
+
+@fragment
+fn fs_splat() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 0.0, 0.0);
+}
+
+struct Tonemap {
+    // Particles per pixel mapped to the top of the colormap
+    saturation: f32,
+};
+@group(1) @binding(0) var density: texture_2d<f32>;
+@group(1) @binding(1) var density_sampler: sampler;
+@group(1) @binding(2) var colormap: texture_2d<f32>;
+@group(1) @binding(3) var colormap_sampler: sampler;
+@group(1) @binding(4) var<uniform> tonemap: Tonemap;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_tonemap(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // One triangle covering the screen
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return output;
+}
+
+@fragment
+fn fs_tonemap(in: VertexOutput) -> @location(0) vec4<f32> {
+    let count = textureSample(density, density_sampler, in.uv).r;
+    if (count <= 0.0) {
+        discard;
+    }
+    // Log scale, so sparse regions stay visible next to dense ones
+    let t = clamp(log2(1.0 + count) / log2(1.0 + tonemap.saturation), 0.0, 1.0);
+    return vec4<f32>(textureSampleLevel(colormap, colormap_sampler, vec2<f32>(t, 0.5), 0.0).rgb, 1.0);
+}
@@ -0,0 +1,210 @@
+use std::simd::*;
+
+use crate::rigid::RigidBody;
+use crate::sim::Sim;
+
+/// Simulation state of one tick, kept in the layout the simulation steps on
+/// so saving and restoring are plain copies.
+#[derive(Clone, Default)]
+pub struct SavedState {
+    pub tick: u64,
+    // Spawning and erasing change the particle count between ticks
+    pub count: usize,
+    pub x: Vec<f32x32>,
+    pub y: Vec<f32x32>,
+    pub x_vel: Vec<f32x32>,
+    pub y_vel: Vec<f32x32>,
+    pub angle: Vec<f32x32>,
+    pub angular_velocity: Vec<f32x32>,
+    pub mass: Vec<f32x32>,
+    pub species: Vec<u8>,
+    pub born: Vec<u64>,
+    pub pressure: Vec<f32>,
+    pub bodies: Vec<RigidBody>,
+}
+
+impl SavedState {
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<f32x32>()
+            * (self.x.len()
+                + self.y.len()
+                + self.x_vel.len()
+                + self.y_vel.len()
+                + self.angle.len()
+                + self.angular_velocity.len()
+                + self.mass.len())
+            + self.species.len()
+            + std::mem::size_of::<u64>() * self.born.len()
+            + std::mem::size_of::<f32>() * self.pressure.len()
+            + std::mem::size_of::<RigidBody>() * self.bodies.len()
+    }
+}
+
+impl Sim {
+    pub fn save_into(&self, state: &mut SavedState) {
+        state.tick = self.tick;
+        state.count = self.count;
+        state.x.clone_from(&self.x);
+        state.y.clone_from(&self.y);
+        state.x_vel.clone_from(&self.x_vel);
+        state.y_vel.clone_from(&self.y_vel);
+        state.angle.clone_from(&self.angle);
+        state.angular_velocity.clone_from(&self.angular_velocity);
+        state.mass.clone_from(&self.mass);
+        state.species.clone_from(&self.species);
+        state.born.clone_from(&self.born);
+        state.pressure.clone_from(&self.pressure);
+        state.bodies.clone_from(&self.bodies.bodies);
+    }
+
+    pub fn restore(&mut self, state: &SavedState) {
+        self.tick = state.tick;
+        self.count = state.count;
+        self.x.clone_from(&state.x);
+        self.y.clone_from(&state.y);
+        self.x_vel.clone_from(&state.x_vel);
+        self.y_vel.clone_from(&state.y_vel);
+        self.angle.clone_from(&state.angle);
+        self.angular_velocity.clone_from(&state.angular_velocity);
+        self.mass.clone_from(&state.mass);
+        self.species.clone_from(&state.species);
+        self.born.clone_from(&state.born);
+        self.pressure.clone_from(&state.pressure);
+        self.bodies.bodies.clone_from(&state.bodies);
+        self.granular.forget_contacts();
+    }
+}
+
+/// Fixed-capacity ring buffer of the most recent simulation ticks.
+///
+/// Slots are reused once the buffer wraps, so recording does not allocate
+/// after the first `capacity` ticks.
+pub struct StateRing {
+    slots: Vec<SavedState>,
+    head: usize,
+    len: usize,
+}
+
+impl StateRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: vec![SavedState::default(); capacity.max(1)],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn record(&mut self, sim: &Sim) {
+        let capacity = self.capacity();
+        sim.save_into(&mut self.slots[self.head]);
+        self.head = (self.head + 1) % capacity;
+        self.len = (self.len + 1).min(capacity);
+    }
+
+    fn slot(&self, age: usize) -> &SavedState {
+        let capacity = self.capacity();
+        &self.slots[(self.head + capacity - 1 - age) % capacity]
+    }
+
+    pub fn newest(&self) -> Option<&SavedState> {
+        (self.len > 0).then(|| self.slot(0))
+    }
+
+    pub fn oldest(&self) -> Option<&SavedState> {
+        (self.len > 0).then(|| self.slot(self.len - 1))
+    }
+
+    /// Recorded ticks are contiguous, so a tick maps directly to its age.
+    pub fn get(&self, tick: u64) -> Option<&SavedState> {
+        let newest = self.newest()?.tick;
+        let age = newest.checked_sub(tick)? as usize;
+        (age < self.len).then(|| self.slot(age))
+    }
+
+    /// Drops every tick after `tick` so recording continues from it as a new branch.
+    pub fn truncate_after(&mut self, tick: u64) {
+        let Some(newest) = self.newest().map(|state| state.tick) else {
+            return;
+        };
+        let dropped = (newest.saturating_sub(tick) as usize).min(self.len);
+        let capacity = self.capacity();
+        self.head = (self.head + capacity - dropped) % capacity;
+        self.len -= dropped;
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.slots.iter().map(SavedState::size_in_bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimConfig;
+
+    /// A ring with `ticks` recorded in order, starting at zero.
+    fn recorded(capacity: usize, ticks: u64) -> (StateRing, Sim) {
+        let mut sim = Sim::new(&SimConfig {
+            count: 4,
+            ..SimConfig::default()
+        });
+        let mut ring = StateRing::new(capacity);
+        for tick in 0..ticks {
+            sim.tick = tick;
+            ring.record(&sim);
+        }
+        (ring, sim)
+    }
+
+    #[test]
+    fn wrapping_evicts_the_oldest_ticks() {
+        let (ring, _) = recorded(4, 6);
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.oldest().map(|state| state.tick), Some(2));
+        assert_eq!(ring.newest().map(|state| state.tick), Some(5));
+        for tick in 2..6 {
+            assert_eq!(ring.get(tick).map(|state| state.tick), Some(tick));
+        }
+    }
+
+    #[test]
+    fn evicted_and_future_ticks_are_missing() {
+        let (ring, _) = recorded(4, 6);
+        assert!(ring.get(0).is_none());
+        assert!(ring.get(1).is_none());
+        assert!(ring.get(6).is_none());
+        assert!(StateRing::new(4).get(0).is_none());
+    }
+
+    #[test]
+    fn recording_after_truncation_starts_a_branch() {
+        let (mut ring, mut sim) = recorded(4, 6);
+        ring.truncate_after(3);
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.newest().map(|state| state.tick), Some(3));
+        assert!(ring.get(4).is_none());
+
+        // The new branch overwrites the dropped slots
+        sim.tick = 4;
+        sim.count = 3;
+        ring.record(&sim);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.oldest().map(|state| state.tick), Some(2));
+        assert_eq!(ring.get(4).map(|state| state.count), Some(3));
+        assert_eq!(ring.get(3).map(|state| state.count), Some(4));
+        assert!(ring.get(5).is_none());
+
+        // Truncating before the oldest tick empties the ring
+        ring.truncate_after(0);
+        assert_eq!(ring.len(), 0);
+        assert!(ring.newest().is_none());
+    }
+}
@@ -1,10 +1,33 @@
 use input_actions::{
-	binding, event,
-	source::{Key, MouseButton},
+	action::Action,
+	binding::{self, ActionMap, ActionSet, ActionSetId, LayoutId},
+	event,
+	source::{self, Key, MouseButton},
 };
 use winit::keyboard::KeyCode;
 use std::convert::{TryFrom, TryInto};
 
+/// Captures the current frame to a PNG.
+pub const SCREENSHOT: &str = "screenshot";
+
+/// The app's actions with their default bindings, for a single user.
+pub fn actions() -> input_actions::System {
+	let mut system = input_actions::System::new();
+	system
+		.add_users(1)
+		.add_action(SCREENSHOT, Action::new(source::Kind::Button))
+		.add_layout(LayoutId::default())
+		.add_action_set(
+			ActionSetId::default(),
+			ActionSet::default().with(
+				LayoutId::default(),
+				ActionMap::default().bind(SCREENSHOT, vec![binding::Source::Keyboard(Key::F12).bound()]),
+			),
+		)
+		.enable_action_set_for_all(ActionSetId::default());
+	system
+}
+
 // TODO: Winit gamepad support is still in progress https://github.com/rust-windowing/winit/issues/944
 pub fn parse_winit_event<T>(
 	event: &winit::event::Event<T>,
@@ -257,7 +280,7 @@ impl TryFrom<KeyCode> for Key {
 			KeyCode::Copy => Err(()),
 			KeyCode::Paste => Err(()),
 			KeyCode::Cut => Err(()),
-            _ => unimplemented!("Key")
+            _ => Err(())
 		}
 	}
 }
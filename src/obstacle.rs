@@ -0,0 +1,315 @@
+use std::path::Path;
+
+use crate::texture_quad::TextureQuad;
+use crate::trails::SCENE_FORMAT;
+use crate::wgpu_ctx::WgpuCtx;
+use crate::Camera;
+
+const SOLID_COLOR: [u8; 4] = [96, 104, 116, 220];
+
+/// Which image channel marks a pixel as solid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaskChannel {
+    /// Dark pixels are solid.
+    Luminance,
+    /// Opaque pixels are solid.
+    Alpha,
+}
+
+/// Static obstacle built from an image mask, stored as a signed distance field.
+///
+/// Distances are in world units, negative inside solid pixels. The image's
+/// top-left pixel maps to `origin + [0, height * cell_size]` so images appear upright.
+#[derive(Clone, Debug)]
+pub struct Obstacle {
+    pub width: usize,
+    pub height: usize,
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    // Row-major from the bottom row up
+    pub sdf: Vec<f32>,
+    pub restitution: f32,
+}
+
+impl Obstacle {
+    /// Loads a PNG mask and places it with its bottom-left corner at `origin`, `world_width` wide.
+    pub fn from_image(
+        path: impl AsRef<Path>,
+        channel: MaskChannel,
+        threshold: u8,
+        origin: [f32; 2],
+        world_width: f32,
+    ) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.to_luma_alpha8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+
+        let mut solid = vec![false; width * height];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [luma, alpha] = pixel.0;
+            let value = match channel {
+                MaskChannel::Luminance => 255 - luma,
+                MaskChannel::Alpha => alpha,
+            };
+            // Flip so row 0 is the bottom of the obstacle in world space
+            let row = height - 1 - y as usize;
+            solid[row * width + x as usize] = value >= threshold;
+        }
+
+        Ok(Self::from_mask(&solid, width, height, origin, world_width / width.max(1) as f32))
+    }
+
+    pub fn from_mask(
+        solid: &[bool],
+        width: usize,
+        height: usize,
+        origin: [f32; 2],
+        cell_size: f32,
+    ) -> Self {
+        let outside = distance_transform(solid, width, height, true);
+        let inside = distance_transform(solid, width, height, false);
+        // A mask with no solid or no empty pixels leaves the transform at infinity;
+        // nothing inside the mask is further from its edge than the diagonal
+        let diagonal = (width * width + height * height) as f32;
+        let sdf = outside
+            .iter()
+            .zip(&inside)
+            .map(|(out, inn)| (out.min(diagonal).sqrt() - inn.min(diagonal).sqrt()) * cell_size)
+            .collect();
+
+        Self {
+            width,
+            height,
+            origin,
+            cell_size,
+            sdf,
+            restitution: 0.8,
+        }
+    }
+
+    pub fn world_size(&self) -> [f32; 2] {
+        [
+            self.width as f32 * self.cell_size,
+            self.height as f32 * self.cell_size,
+        ]
+    }
+
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.sdf[y * self.width + x]
+    }
+
+    /// Bilinearly sampled distance and outward gradient at a world position,
+    /// or `None` outside the obstacle's rectangle.
+    pub fn sample(&self, position: [f32; 2]) -> Option<(f32, [f32; 2])> {
+        // Sample at pixel centres
+        let gx = (position[0] - self.origin[0]) / self.cell_size - 0.5;
+        let gy = (position[1] - self.origin[1]) / self.cell_size - 0.5;
+        if gx < -0.5 || gy < -0.5 || gx > self.width as f32 - 0.5 || gy > self.height as f32 - 0.5 {
+            return None;
+        }
+
+        let gx = gx.clamp(0.0, (self.width - 1) as f32);
+        let gy = gy.clamp(0.0, (self.height - 1) as f32);
+        let x0 = (gx as usize).min(self.width.saturating_sub(2));
+        let y0 = (gy as usize).min(self.height.saturating_sub(2));
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let tx = gx - x0 as f32;
+        let ty = gy - y0 as f32;
+
+        let d00 = self.at(x0, y0);
+        let d10 = self.at(x1, y0);
+        let d01 = self.at(x0, y1);
+        let d11 = self.at(x1, y1);
+
+        let distance = (d00 * (1.0 - tx) + d10 * tx) * (1.0 - ty) + (d01 * (1.0 - tx) + d11 * tx) * ty;
+        let grad_x = (d10 - d00) * (1.0 - ty) + (d11 - d01) * ty;
+        let grad_y = (d01 - d00) * (1.0 - tx) + (d11 - d10) * tx;
+        let length = (grad_x * grad_x + grad_y * grad_y).sqrt();
+        let normal = if length > 0.0 {
+            [grad_x / length, grad_y / length]
+        } else {
+            [0.0, 1.0]
+        };
+
+        Some((distance, normal))
+    }
+
+    /// Pushes a circle of `radius` out of the obstacle and reflects its velocity.
+    /// Returns true if the circle was touching.
+    pub fn collide(&self, position: &mut [f32; 2], velocity: &mut [f32; 2], radius: f32) -> bool {
+        let Some((distance, normal)) = self.sample(*position) else {
+            return false;
+        };
+        let penetration = radius - distance;
+        if penetration <= 0.0 {
+            return false;
+        }
+
+        position[0] += normal[0] * penetration;
+        position[1] += normal[1] * penetration;
+
+        // Only reflect velocity heading into the surface
+        let normal_speed = velocity[0] * normal[0] + velocity[1] * normal[1];
+        if normal_speed < 0.0 {
+            let impulse = (1.0 + self.restitution) * normal_speed;
+            velocity[0] -= impulse * normal[0];
+            velocity[1] -= impulse * normal[1];
+        }
+        true
+    }
+
+    /// Straight-alpha RGBA8 pixels, top row first, opaque where the obstacle is solid.
+    pub fn write_rgba(&self, color: [u8; 4], out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(self.width * self.height * 4);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let alpha = if self.at(x, y) < 0.0 { color[3] } else { 0 };
+                out.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+            }
+        }
+    }
+}
+
+/// An obstacle drawn through a texture quad over its rectangle.
+pub struct ObstacleLayer {
+    pub quad: TextureQuad,
+    pub origin: [f32; 2],
+    pub size: [f32; 2],
+}
+
+impl ObstacleLayer {
+    pub fn new(wgpu_ctx: &WgpuCtx, obstacle: &Obstacle) -> Self {
+        let quad = TextureQuad::new(
+            &wgpu_ctx.device,
+            SCENE_FORMAT,
+            &wgpu_ctx.uniform_bind_group_layout,
+            (obstacle.width as u32, obstacle.height as u32),
+            wgpu::FilterMode::Linear,
+            "Obstacle Texture",
+        );
+        let mut pixels = Vec::new();
+        obstacle.write_rgba(SOLID_COLOR, &mut pixels);
+        quad.upload(&wgpu_ctx.queue, &pixels);
+        Self {
+            quad,
+            origin: obstacle.origin,
+            size: obstacle.world_size(),
+        }
+    }
+
+    pub fn set_view(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let max = [self.origin[0] + self.size[0], self.origin[1] + self.size[1]];
+        self.quad.set_rect(queue, camera, self.origin, max);
+    }
+}
+
+/// Squared Euclidean distance from every pixel to the nearest pixel where `solid == target`
+/// (Felzenszwalb & Huttenlocher).
+fn distance_transform(solid: &[bool], width: usize, height: usize, target: bool) -> Vec<f32> {
+    const INF: f32 = 1e20;
+    let mut grid: Vec<f32> = solid
+        .iter()
+        .map(|&s| if s == target { 0.0 } else { INF })
+        .collect();
+
+    let mut column = vec![0.0; height];
+    for x in 0..width {
+        for y in 0..height {
+            column[y] = grid[y * width + x];
+        }
+        let transformed = distance_transform_1d(&column);
+        for y in 0..height {
+            grid[y * width + x] = transformed[y];
+        }
+    }
+    for y in 0..height {
+        let row = &mut grid[y * width..(y + 1) * width];
+        let transformed = distance_transform_1d(row);
+        row.copy_from_slice(&transformed);
+    }
+    grid
+}
+
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut d = vec![0.0; n];
+    if n == 0 {
+        return d;
+    }
+    // Lower envelope of the parabolas rooted at each sample
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0f32; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+
+    for q in 1..n {
+        loop {
+            let p = v[k];
+            let s = ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * (q as f32 - p as f32));
+            if s <= z[k] {
+                // z[0] is -inf, so this never underflows
+                k -= 1;
+            } else {
+                k += 1;
+                v[k] = q;
+                z[k] = s;
+                z[k + 1] = f32::INFINITY;
+                break;
+            }
+        }
+    }
+
+    k = 0;
+    for (q, out) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let p = v[k];
+        let dq = q as f32 - p as f32;
+        *out = dq * dq + f[p];
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_transform_matches_brute_force() {
+        // A 5x4 mask with two solid pixels
+        let (width, height) = (5, 4);
+        let mut solid = vec![false; width * height];
+        solid[width + 1] = true;
+        solid[3 * width + 4] = true;
+
+        let transformed = distance_transform(&solid, width, height, true);
+        for y in 0..height {
+            for x in 0..width {
+                let expected = [(1, 1), (4, 3)]
+                    .iter()
+                    .map(|&(sx, sy): &(i32, i32)| ((x as i32 - sx).pow(2) + (y as i32 - sy).pow(2)) as f32)
+                    .fold(f32::MAX, f32::min);
+                assert_eq!(transformed[y * width + x], expected, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn all_solid_mask_stays_finite() {
+        let obstacle = Obstacle::from_mask(&[true; 6], 3, 2, [0.0, 0.0], 2.0);
+        let limit = (3.0f32 * 3.0 + 2.0 * 2.0).sqrt() * 2.0;
+        for &distance in &obstacle.sdf {
+            assert!(distance < 0.0 && distance >= -limit, "distance {distance}");
+        }
+
+        // A particle inside is pushed out by no more than the mask's diagonal
+        let mut position = [3.0, 2.0];
+        let mut velocity = [0.0, 0.0];
+        assert!(obstacle.collide(&mut position, &mut velocity, 0.5));
+        let moved = ((position[0] - 3.0).powi(2) + (position[1] - 2.0).powi(2)).sqrt();
+        assert!(moved <= limit + 0.5, "moved {moved}");
+    }
+}
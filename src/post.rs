@@ -0,0 +1,411 @@
+use std::borrow::Cow;
+use wgpu::ShaderSource;
+
+use crate::render_graph::{RenderGraph, ResourceId, TargetSize};
+use crate::trails::SCENE_FORMAT;
+
+/// Longest chain the parameter buffer has room for.
+pub const MAX_PASSES: usize = 8;
+
+// Uniform bindings have to start on this alignment
+const PARAMS_STRIDE: u64 = 256;
+// Smallest bloom level is 1/64 of the scene. Matches BLOOM_LEVELS in post.wgsl
+const BLOOM_LEVELS: u32 = 6;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bloom {
+    // Brightness above which pixels glow
+    pub threshold: f32,
+    // Fraction of the threshold over which the glow fades in
+    pub knee: f32,
+    pub intensity: f32,
+    // Spread of the upsampling filter, in texels of each level
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            knee: 0.5,
+            intensity: 0.6,
+            radius: 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tonemapper {
+    Reinhard,
+    Aces,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 2] = [Tonemapper::Reinhard, Tonemapper::Aces];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemapper::Reinhard => "Reinhard",
+            Tonemapper::Aces => "ACES",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tonemap {
+    pub tonemapper: Tonemapper,
+    // In stops; each one doubles the brightness
+    pub exposure: f32,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self {
+        Self {
+            tonemapper: Tonemapper::Aces,
+            exposure: 0.0,
+        }
+    }
+}
+
+/// One step of the post-processing chain. New effects get a variant here, a
+/// fragment shader in post.wgsl and an arm in `PostChain::run`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    Bloom(Bloom),
+    Tonemap(Tonemap),
+}
+
+impl PostEffect {
+    /// Every effect with its default settings, for adding to a chain.
+    pub fn all() -> [PostEffect; 2] {
+        [PostEffect::Bloom(Bloom::default()), PostEffect::Tonemap(Tonemap::default())]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostEffect::Bloom(_) => "Bloom",
+            PostEffect::Tonemap(_) => "Tonemap",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PostPass {
+    pub enabled: bool,
+    pub effect: PostEffect,
+}
+
+/// The effects applied to the HDR scene, in order, before it is shown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostSettings {
+    pub passes: Vec<PostPass>,
+}
+
+impl Default for PostSettings {
+    // Off until asked for, so the scene looks as drawn
+    fn default() -> Self {
+        Self {
+            passes: PostEffect::all()
+                .into_iter()
+                .map(|effect| PostPass { enabled: false, effect })
+                .collect(),
+        }
+    }
+}
+
+// Matches `Params` in post.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Default)]
+struct PostParams {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+    exposure: f32,
+    tonemapper: u32,
+    _padding: [u32; 2],
+}
+
+/// Runs the enabled passes of a `PostSettings` over a finished scene.
+pub struct PostChain {
+    sampler: wgpu::Sampler,
+    params_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    combine_layout: wgpu::BindGroupLayout,
+    threshold_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    combine_pipeline: wgpu::RenderPipeline,
+    tonemap_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostChain {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Params Buffer"),
+            size: PARAMS_STRIDE * MAX_PASSES as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let entries = [
+            texture_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            texture_entry(3),
+        ];
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Bind Group Layout"),
+            entries: &entries[..3],
+        });
+        let combine_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Combine Bind Group Layout"),
+            entries: &entries,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Post Shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("post.wgsl"))),
+        });
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let pipeline = |entry_point, layout, blend| create_pipeline(device, &shader, entry_point, layout, blend);
+
+        Self {
+            threshold_pipeline: pipeline("fs_threshold", &layout, wgpu::BlendState::REPLACE),
+            downsample_pipeline: pipeline("fs_downsample", &layout, wgpu::BlendState::REPLACE),
+            upsample_pipeline: pipeline(
+                "fs_upsample",
+                &layout,
+                wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                },
+            ),
+            combine_pipeline: pipeline("fs_combine", &combine_layout, wgpu::BlendState::REPLACE),
+            tonemap_pipeline: pipeline("fs_tonemap", &layout, wgpu::BlendState::REPLACE),
+            sampler,
+            params_buffer,
+            layout,
+            combine_layout,
+        }
+    }
+
+    /// Uploads the settings of each pass into its slot of the parameter buffer.
+    pub fn set_params(&self, queue: &wgpu::Queue, settings: &PostSettings) {
+        for (slot, pass) in settings.passes.iter().take(MAX_PASSES).enumerate() {
+            let params = match pass.effect {
+                PostEffect::Bloom(bloom) => PostParams {
+                    threshold: bloom.threshold,
+                    knee: bloom.knee,
+                    intensity: bloom.intensity,
+                    radius: bloom.radius,
+                    ..Default::default()
+                },
+                PostEffect::Tonemap(tonemap) => PostParams {
+                    exposure: tonemap.exposure,
+                    tonemapper: tonemap.tonemapper as u32,
+                    ..Default::default()
+                },
+            };
+            queue.write_buffer(&self.params_buffer, slot as u64 * PARAMS_STRIDE, bytemuck::bytes_of(&params));
+        }
+    }
+
+    /// Adds a node per enabled pass to `graph`, each reading the result of the
+    /// one before, starting from `input`. Returns the resource holding the final
+    /// result, which is `input` itself if nothing is enabled.
+    pub fn add_nodes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        device: &'a wgpu::Device,
+        settings: &PostSettings,
+        input: ResourceId,
+    ) -> ResourceId {
+        let mut current = input;
+        for (slot, pass) in settings.passes.iter().take(MAX_PASSES).enumerate() {
+            if !pass.enabled {
+                continue;
+            }
+            let source = current;
+            let output = graph.transient(SCENE_FORMAT, TargetSize::Output);
+            match pass.effect {
+                PostEffect::Bloom(_) => {
+                    // Halving in size from half the output
+                    let levels: Vec<ResourceId> = (1..=BLOOM_LEVELS)
+                        .map(|level| graph.transient(SCENE_FORMAT, TargetSize::Halved(level)))
+                        .collect();
+                    let mut writes = levels.clone();
+                    writes.push(output);
+                    graph.add_node("Bloom", &[source], &writes, move |encoder, resources| {
+                        let levels: Vec<&wgpu::TextureView> = levels.iter().map(|&level| resources.view(level)).collect();
+                        let bind = |source: &wgpu::TextureView| self.bind_group(device, source, slot, None);
+                        let source = resources.view(source);
+                        draw(encoder, &self.threshold_pipeline, &bind(source), levels[0], false);
+                        for level in 1..levels.len() {
+                            draw(encoder, &self.downsample_pipeline, &bind(levels[level - 1]), levels[level], false);
+                        }
+                        for level in (0..levels.len() - 1).rev() {
+                            draw(encoder, &self.upsample_pipeline, &bind(levels[level + 1]), levels[level], true);
+                        }
+                        let bind_group = self.bind_group(device, source, slot, Some(levels[0]));
+                        draw(encoder, &self.combine_pipeline, &bind_group, resources.view(output), false);
+                    });
+                }
+                PostEffect::Tonemap(_) => {
+                    graph.add_node("Tonemap", &[source], &[output], move |encoder, resources| {
+                        let bind_group = self.bind_group(device, resources.view(source), slot, None);
+                        draw(encoder, &self.tonemap_pipeline, &bind_group, resources.view(output), false);
+                    });
+                }
+            }
+            current = output;
+        }
+        current
+    }
+
+    // Bind groups are made per use, since the graph hands out different textures each time
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        slot: usize,
+        bloom: Option<&wgpu::TextureView>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &self.params_buffer,
+                    offset: slot as u64 * PARAMS_STRIDE,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<PostParams>() as u64),
+                }),
+            },
+        ];
+        if let Some(bloom) = bloom {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(bloom),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Bind Group"),
+            layout: if bloom.is_some() { &self.combine_layout } else { &self.layout },
+            entries: &entries,
+        })
+    }
+}
+
+// A fullscreen triangle into `view`, added to what is there if `accumulate` is set
+fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    view: &wgpu::TextureView,
+    accumulate: bool,
+) {
+    let load = if accumulate {
+        wgpu::LoadOp::Load
+    } else {
+        wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+    };
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Post Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    layout: &wgpu::BindGroupLayout,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(entry_point),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: SCENE_FORMAT,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
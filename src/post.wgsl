@@ -0,0 +1,96 @@
+struct Params {
+    // Bloom
+    threshold: f32,
+    // Fraction of the threshold over which bright pixels fade in
+    knee: f32,
+    intensity: f32,
+    // Spread of the upsampling filter, in texels
+    radius: f32,
+    // Tonemapping, in stops
+    exposure: f32,
+    // 0: Reinhard, 1: ACES
+    tonemapper: u32,
+};
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+// Only bound for the bloom combine
+@group(0) @binding(3) var bloom: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // One triangle covering the screen
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return output;
+}
+
+fn sample(uv: vec2<f32>) -> vec3<f32> {
+    return textureSample(source, source_sampler, uv).rgb;
+}
+
+// Keeps what is brighter than the threshold, with a soft knee below it
+@fragment
+fn fs_threshold(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample(in.uv);
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = params.threshold * params.knee + 0.00001;
+    var soft = clamp(brightness - params.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - params.threshold) / max(brightness, 0.00001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// Halves the size; each bilinear tap already averages four texels
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    var sum = sample(in.uv) * 4.0;
+    sum += sample(in.uv + texel * vec2<f32>(-1.0, -1.0));
+    sum += sample(in.uv + texel * vec2<f32>(1.0, -1.0));
+    sum += sample(in.uv + texel * vec2<f32>(-1.0, 1.0));
+    sum += sample(in.uv + texel * vec2<f32>(1.0, 1.0));
+    return vec4<f32>(sum / 8.0, 1.0);
+}
+
+// 3x3 tent filter, added onto the next larger level
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = params.radius / vec2<f32>(textureDimensions(source));
+    var sum = sample(in.uv) * 4.0;
+    sum += (sample(in.uv + vec2<f32>(texel.x, 0.0)) + sample(in.uv - vec2<f32>(texel.x, 0.0))) * 2.0;
+    sum += (sample(in.uv + vec2<f32>(0.0, texel.y)) + sample(in.uv - vec2<f32>(0.0, texel.y))) * 2.0;
+    sum += sample(in.uv + texel) + sample(in.uv - texel);
+    sum += sample(in.uv + vec2<f32>(texel.x, -texel.y)) + sample(in.uv + vec2<f32>(-texel.x, texel.y));
+    return vec4<f32>(sum / 16.0, 1.0);
+}
+
+// Levels summed into the first one by the upsampling
+const BLOOM_LEVELS: f32 = 6.0;
+
+@fragment
+fn fs_combine(in: VertexOutput) -> @location(0) vec4<f32> {
+    let glow = textureSample(bloom, source_sampler, in.uv).rgb / BLOOM_LEVELS;
+    return vec4<f32>(sample(in.uv) + glow * params.intensity, 1.0);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp(x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_tonemap(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample(in.uv) * exp2(params.exposure);
+    if (params.tonemapper == 1u) {
+        return vec4<f32>(aces(color), 1.0);
+    }
+    return vec4<f32>(color / (1.0 + color), 1.0);
+}
@@ -0,0 +1,224 @@
+use std::f32::consts::TAU;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Initial particle layouts. Every preset is deterministic for a given seed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Grid,
+    UniformRandom,
+    PoissonDisk,
+    GaussianBlob,
+    GalaxyDisk,
+    CollidingClusters,
+    ShearLayers,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 7] = [
+        Preset::Grid,
+        Preset::UniformRandom,
+        Preset::PoissonDisk,
+        Preset::GaussianBlob,
+        Preset::GalaxyDisk,
+        Preset::CollidingClusters,
+        Preset::ShearLayers,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Grid => "Grid",
+            Preset::UniformRandom => "Uniform random",
+            Preset::PoissonDisk => "Poisson disk",
+            Preset::GaussianBlob => "Gaussian blob",
+            Preset::GalaxyDisk => "Galaxy disk",
+            Preset::CollidingClusters => "Colliding clusters",
+            Preset::ShearLayers => "Shear layers",
+        }
+    }
+
+    /// The name as typed on the command line, e.g. `gaussian-blob`.
+    pub fn flag_name(&self) -> String {
+        self.name().to_lowercase().replace(' ', "-")
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.flag_name() == name.to_lowercase())
+    }
+
+    /// Generates `count` particles as `(position, velocity)` pairs.
+    /// `spacing` is the mean distance between neighbours and `speed` scales every velocity.
+    pub fn generate(&self, count: usize, spacing: f32, speed: f32, seed: u64) -> Vec<([f32; 2], [f32; 2])> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Half-width of a square that fits `count` particles at `spacing`
+        let extent = (count as f32).sqrt() * spacing / 2.0;
+
+        match self {
+            Preset::Grid => {
+                let grid_size = count.isqrt().max(1);
+                (0..count)
+                    .map(|index| {
+                        let row = index / grid_size;
+                        let col = index % grid_size;
+                        let position = [
+                            (col as f32 - grid_size as f32 / 2.0) * spacing,
+                            (row as f32 - grid_size as f32 / 2.0) * spacing,
+                        ];
+                        (position, random_velocity(&mut rng, speed))
+                    })
+                    .collect()
+            }
+            Preset::UniformRandom => (0..count)
+                .map(|_| (random_in_box(&mut rng, extent), random_velocity(&mut rng, speed)))
+                .collect(),
+            Preset::PoissonDisk => {
+                // Bridson's dart throwing packs roughly 0.7 / r^2 points per unit area
+                let radius = (0.7 * (2.0 * extent) * (2.0 * extent) / count.max(1) as f32).sqrt();
+                let mut points = poisson_disk(&mut rng, extent, radius, count);
+                while points.len() < count {
+                    points.push(random_in_box(&mut rng, extent));
+                }
+                points
+                    .into_iter()
+                    .map(|position| (position, random_velocity(&mut rng, speed)))
+                    .collect()
+            }
+            Preset::GaussianBlob => (0..count)
+                .map(|_| {
+                    let [x, y] = gaussian(&mut rng);
+                    let sigma = extent / 2.0;
+                    ([x * sigma, y * sigma], random_velocity(&mut rng, speed))
+                })
+                .collect(),
+            Preset::GalaxyDisk => (0..count)
+                .map(|_| {
+                    // sqrt gives uniform density over the disk area
+                    let r = extent * rng.random::<f32>().sqrt();
+                    let angle = rng.random::<f32>() * TAU;
+                    let (sin, cos) = angle.sin_cos();
+                    // Flat rotation curve outside a small solid-body core
+                    let core = extent * 0.1;
+                    let orbital = speed * 2.0 * r / (r + core);
+                    let [jx, jy] = random_velocity(&mut rng, speed * 0.05);
+                    ([r * cos, r * sin], [-sin * orbital + jx, cos * orbital + jy])
+                })
+                .collect(),
+            Preset::CollidingClusters => (0..count)
+                .map(|index| {
+                    let side = if index % 2 == 0 { -1.0 } else { 1.0 };
+                    let [x, y] = gaussian(&mut rng);
+                    let sigma = extent / 4.0;
+                    let [jx, jy] = random_velocity(&mut rng, speed * 0.1);
+                    (
+                        [x * sigma + side * extent, y * sigma + side * extent * 0.2],
+                        [-side * speed * 2.0 + jx, jy],
+                    )
+                })
+                .collect(),
+            Preset::ShearLayers => (0..count)
+                .map(|_| {
+                    let position = random_in_box(&mut rng, extent);
+                    let direction = if position[1] > 0.0 { 1.0 } else { -1.0 };
+                    // A small perpendicular kick seeds the Kelvin-Helmholtz instability
+                    let kick = speed * 0.05 * (position[0] / extent * TAU).sin();
+                    (position, [direction * speed, kick])
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Velocity with each component uniform in `-speed..speed`.
+fn random_velocity(rng: &mut StdRng, speed: f32) -> [f32; 2] {
+    if speed <= 0.0 {
+        return [0.0, 0.0];
+    }
+    [rng.random_range(-speed..speed), rng.random_range(-speed..speed)]
+}
+
+fn random_in_box(rng: &mut StdRng, extent: f32) -> [f32; 2] {
+    [
+        rng.random_range(-extent..=extent),
+        rng.random_range(-extent..=extent),
+    ]
+}
+
+/// Standard normal pair via the Box-Muller transform.
+fn gaussian(rng: &mut StdRng) -> [f32; 2] {
+    let u1 = rng.random::<f32>().max(f32::MIN_POSITIVE);
+    let u2 = rng.random::<f32>();
+    let r = (-2.0 * u1.ln()).sqrt();
+    let (sin, cos) = (TAU * u2).sin_cos();
+    [r * cos, r * sin]
+}
+
+/// Bridson's Poisson-disk sampling in the square `-extent..extent`, stopping at `limit` points.
+fn poisson_disk(rng: &mut StdRng, extent: f32, radius: f32, limit: usize) -> Vec<[f32; 2]> {
+    const ATTEMPTS: usize = 30;
+    let cell = radius / std::f32::consts::SQRT_2;
+    let cells = ((2.0 * extent / cell).ceil() as usize).max(1);
+    let mut grid = vec![usize::MAX; cells * cells];
+    let mut points = Vec::with_capacity(limit);
+    let mut active = Vec::new();
+
+    let cell_of = |p: [f32; 2]| {
+        let cx = (((p[0] + extent) / cell) as usize).min(cells - 1);
+        let cy = (((p[1] + extent) / cell) as usize).min(cells - 1);
+        (cx, cy)
+    };
+
+    let first = random_in_box(rng, extent);
+    let (cx, cy) = cell_of(first);
+    grid[cy * cells + cx] = 0;
+    points.push(first);
+    active.push(0);
+
+    while !active.is_empty() && points.len() < limit {
+        let slot = rng.random_range(0..active.len());
+        let origin = points[active[slot]];
+        let mut found = false;
+
+        for _ in 0..ATTEMPTS {
+            let angle = rng.random::<f32>() * TAU;
+            let distance = radius * (1.0 + rng.random::<f32>());
+            let candidate = [
+                origin[0] + angle.cos() * distance,
+                origin[1] + angle.sin() * distance,
+            ];
+            if candidate[0].abs() > extent || candidate[1].abs() > extent {
+                continue;
+            }
+
+            let (cx, cy) = cell_of(candidate);
+            let mut clear = true;
+            'neighbours: for ny in cy.saturating_sub(2)..(cy + 3).min(cells) {
+                for nx in cx.saturating_sub(2)..(cx + 3).min(cells) {
+                    let other = grid[ny * cells + nx];
+                    if other != usize::MAX {
+                        let dx = points[other][0] - candidate[0];
+                        let dy = points[other][1] - candidate[1];
+                        if dx * dx + dy * dy < radius * radius {
+                            clear = false;
+                            break 'neighbours;
+                        }
+                    }
+                }
+            }
+
+            if clear {
+                grid[cy * cells + cx] = points.len();
+                active.push(points.len());
+                points.push(candidate);
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            active.swap_remove(slot);
+        }
+    }
+
+    points
+}
@@ -0,0 +1,311 @@
+use crate::grid::SpatialGrid;
+use crate::sim::Particle;
+
+/// A particle found by a query. `index` is its position in the snapshot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub index: usize,
+    pub position: [f32; 2],
+    pub distance: f32,
+}
+
+/// First particle along a ray.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub index: usize,
+    // Where the ray enters the particle's disk
+    pub point: [f32; 2],
+    pub normal: [f32; 2],
+    pub distance: f32,
+}
+
+/// Particle positions of one snapshot with a grid over them, so the render
+/// thread can ask which particles are where without touching the sim.
+#[derive(Default)]
+pub struct ParticleIndex {
+    pub positions: Vec<[f32; 2]>,
+    pub radius: f32,
+    grid: SpatialGrid,
+}
+
+impl ParticleIndex {
+    pub fn new(particles: &[Particle], radius: f32) -> Self {
+        let positions = particles
+            .iter()
+            .map(|particle| particle.position.map(|v| v as f32))
+            .collect();
+        Self::from_positions(positions, radius)
+    }
+
+    fn from_positions(positions: Vec<[f32; 2]>, radius: f32) -> Self {
+        let mut grid = SpatialGrid::default();
+        // Cells at least a diameter wide keep every disk within one cell of its centre's
+        grid.build(&positions, 2.0 * radius);
+        Self {
+            positions,
+            radius,
+            grid,
+        }
+    }
+
+    pub fn grid(&self) -> &SpatialGrid {
+        &self.grid
+    }
+
+    fn hit(&self, index: u32, point: [f32; 2]) -> Hit {
+        let position = self.positions[index as usize];
+        let d = [position[0] - point[0], position[1] - point[1]];
+        Hit {
+            index: index as usize,
+            position,
+            distance: (d[0] * d[0] + d[1] * d[1]).sqrt(),
+        }
+    }
+
+    /// Closest particle centre to `point`, if any lies within `max_distance`.
+    pub fn nearest(&self, point: [f32; 2], max_distance: f32) -> Option<Hit> {
+        let (grid_min, grid_max) = self.grid.bounds()?;
+        // Widen the search until the best candidate is inside the searched box
+        let mut reach = self.grid.cell_size.min(max_distance);
+        loop {
+            let mut best: Option<Hit> = None;
+            self.grid.for_each_in_box(
+                [point[0] - reach, point[1] - reach],
+                [point[0] + reach, point[1] + reach],
+                |index| {
+                    let hit = self.hit(index, point);
+                    if hit.distance <= max_distance && best.is_none_or(|best| hit.distance < best.distance) {
+                        best = Some(hit);
+                    }
+                },
+            );
+            if best.is_some_and(|best| best.distance <= reach) || reach >= max_distance {
+                return best;
+            }
+            let covers_grid = point[0] - reach <= grid_min[0]
+                && point[1] - reach <= grid_min[1]
+                && point[0] + reach >= grid_max[0]
+                && point[1] + reach >= grid_max[1];
+            if covers_grid {
+                return best;
+            }
+            reach = (reach * 2.0).min(max_distance);
+        }
+    }
+
+    /// Particles whose centres are within `radius` of `center`, nearest first.
+    pub fn within_radius(&self, center: [f32; 2], radius: f32) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        self.grid.for_each_in_box(
+            [center[0] - radius, center[1] - radius],
+            [center[0] + radius, center[1] + radius],
+            |index| {
+                let hit = self.hit(index, center);
+                if hit.distance <= radius {
+                    hits.push(hit);
+                }
+            },
+        );
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    /// Number of particle centres within `radius` of `center`, without collecting them.
+    pub fn count_within(&self, center: [f32; 2], radius: f32) -> usize {
+        let mut count = 0;
+        self.grid.for_each_in_box(
+            [center[0] - radius, center[1] - radius],
+            [center[0] + radius, center[1] + radius],
+            |index| {
+                if self.hit(index, center).distance <= radius {
+                    count += 1;
+                }
+            },
+        );
+        count
+    }
+
+    /// Indices of particles whose centres lie in the box `min..max`.
+    pub fn in_aabb(&self, min: [f32; 2], max: [f32; 2]) -> Vec<usize> {
+        let (min, max) = (
+            [min[0].min(max[0]), min[1].min(max[1])],
+            [min[0].max(max[0]), min[1].max(max[1])],
+        );
+        let mut indices = Vec::new();
+        self.grid.for_each_in_box(min, max, |index| {
+            let p = self.positions[index as usize];
+            if p[0] >= min[0] && p[0] <= max[0] && p[1] >= min[1] && p[1] <= max[1] {
+                indices.push(index as usize);
+            }
+        });
+        indices.sort_unstable();
+        indices
+    }
+
+    /// First particle disk hit by the segment `from..to`.
+    pub fn raycast(&self, from: [f32; 2], to: [f32; 2]) -> Option<RayHit> {
+        let d = [to[0] - from[0], to[1] - from[1]];
+        let length = (d[0] * d[0] + d[1] * d[1]).sqrt();
+        if length == 0.0 {
+            return None;
+        }
+        let dir = [d[0] / length, d[1] / length];
+        let radius_squared = self.radius * self.radius;
+
+        let mut best: Option<RayHit> = None;
+        self.grid.traverse(from, to, |t, cell| {
+            // Disks reach one cell past their centre's, so nothing nearer can turn up after this
+            if best.is_some_and(|best| t * length > best.distance) {
+                return false;
+            }
+            self.grid.for_each_near_cell(cell, |index| {
+                let center = self.positions[index as usize];
+                let m = [from[0] - center[0], from[1] - center[1]];
+                let b = m[0] * dir[0] + m[1] * dir[1];
+                let c = m[0] * m[0] + m[1] * m[1] - radius_squared;
+                if c > 0.0 && b > 0.0 {
+                    return;
+                }
+                let discriminant = b * b - c;
+                if discriminant < 0.0 {
+                    return;
+                }
+                // Rays starting inside a disk hit it immediately
+                let distance = (-b - discriminant.sqrt()).max(0.0);
+                if distance > length || best.is_some_and(|best| distance >= best.distance) {
+                    return;
+                }
+                let point = [from[0] + dir[0] * distance, from[1] + dir[1] * distance];
+                let offset = [point[0] - center[0], point[1] - center[1]];
+                let offset_length = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+                let normal = if offset_length > 0.0 {
+                    [offset[0] / offset_length, offset[1] / offset_length]
+                } else {
+                    [-dir[0], -dir[1]]
+                };
+                best = Some(RayHit {
+                    index: index as usize,
+                    point,
+                    normal,
+                    distance,
+                });
+            });
+            true
+        });
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const RADIUS: f32 = 1.0;
+
+    fn scattered(seed: u64) -> ParticleIndex {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let positions = (0..400)
+            .map(|_| [rng.random_range(-40.0..40.0), rng.random_range(-25.0..25.0)])
+            .collect();
+        ParticleIndex::from_positions(positions, RADIUS)
+    }
+
+    fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+        let d = [a[0] - b[0], a[1] - b[1]];
+        (d[0] * d[0] + d[1] * d[1]).sqrt()
+    }
+
+    /// Distance along `from..to` to where it enters the disk at `center`, if it does.
+    fn ray_distance(from: [f32; 2], to: [f32; 2], center: [f32; 2]) -> Option<f32> {
+        let length = distance(from, to);
+        let dir = [(to[0] - from[0]) / length, (to[1] - from[1]) / length];
+        if distance(from, center) <= RADIUS {
+            return Some(0.0);
+        }
+        let along = (center[0] - from[0]) * dir[0] + (center[1] - from[1]) * dir[1];
+        let closest = [from[0] + dir[0] * along, from[1] + dir[1] * along];
+        let offset = distance(closest, center);
+        let distance = along - (RADIUS * RADIUS - offset * offset).max(0.0).sqrt();
+        (offset <= RADIUS && along > 0.0 && distance <= length).then_some(distance)
+    }
+
+    #[test]
+    fn point_queries_match_brute_force() {
+        let index = scattered(7);
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..200 {
+            let point = [rng.random_range(-50.0..50.0), rng.random_range(-35.0..35.0)];
+            let radius = rng.random_range(0.5..15.0);
+
+            let expected = index
+                .positions
+                .iter()
+                .map(|&p| distance(p, point))
+                .filter(|&d| d <= radius)
+                .fold(None, |best: Option<f32>, d| Some(best.map_or(d, |b| b.min(d))));
+            assert_eq!(index.nearest(point, radius).map(|hit| hit.distance), expected);
+
+            let mut within: Vec<usize> = index.within_radius(point, radius).iter().map(|hit| hit.index).collect();
+            within.sort_unstable();
+            let expected: Vec<usize> = (0..index.positions.len())
+                .filter(|&i| distance(index.positions[i], point) <= radius)
+                .collect();
+            assert_eq!(within, expected);
+            assert_eq!(index.count_within(point, radius), expected.len());
+
+            let corner = [point[0] + rng.random_range(-20.0..20.0), point[1] + rng.random_range(-20.0..20.0)];
+            let (min, max) = ([point[0].min(corner[0]), point[1].min(corner[1])], [point[0].max(corner[0]), point[1].max(corner[1])]);
+            let expected: Vec<usize> = (0..index.positions.len())
+                .filter(|&i| {
+                    let p = index.positions[i];
+                    p[0] >= min[0] && p[0] <= max[0] && p[1] >= min[1] && p[1] <= max[1]
+                })
+                .collect();
+            assert_eq!(index.in_aabb(point, corner), expected);
+        }
+    }
+
+    #[test]
+    fn raycast_matches_brute_force() {
+        let index = scattered(11);
+        let mut rng = StdRng::seed_from_u64(12);
+        // Long rays cross many cells, some start outside the grid, some inside a disk
+        let mut rays: Vec<([f32; 2], [f32; 2])> = (0..300)
+            .map(|_| {
+                let from = [rng.random_range(-60.0..60.0), rng.random_range(-40.0..40.0)];
+                let to = [rng.random_range(-60.0..60.0), rng.random_range(-40.0..40.0)];
+                (from, to)
+            })
+            .collect();
+        for i in 0..50 {
+            let center = index.positions[i];
+            let from = [center[0] + 0.5 * RADIUS, center[1]];
+            rays.push((from, [from[0] + rng.random_range(-30.0..30.0), from[1] + rng.random_range(-30.0..30.0)]));
+        }
+
+        for (from, to) in rays {
+            let expected = index
+                .positions
+                .iter()
+                .filter_map(|&center| ray_distance(from, to, center))
+                .fold(None, |best: Option<f32>, d| Some(best.map_or(d, |b| b.min(d))));
+            let hit = index.raycast(from, to);
+            match (hit, expected) {
+                (Some(hit), Some(expected)) => {
+                    assert!((hit.distance - expected).abs() < 1e-3, "{from:?} -> {to:?}: {} vs {expected}", hit.distance);
+                    let center = index.positions[hit.index];
+                    assert!((distance(hit.point, center) - RADIUS).abs() < 1e-3 || hit.distance == 0.0);
+                }
+                (None, None) => {}
+                _ => panic!("{from:?} -> {to:?}: got {hit:?}, expected {expected:?}"),
+            }
+        }
+
+        // Starting inside a disk hits it at once
+        let center = index.positions[0];
+        let hit = index.raycast(center, [center[0] + 100.0, center[1]]).unwrap();
+        assert_eq!(hit.distance, 0.0);
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::colormap::Coloring;
+use crate::post::PostSettings;
+use crate::query::ParticleIndex;
+use crate::sim::{Sim, TimeStep};
+use crate::trails::TrailSettings;
+use crate::wgpu_ctx::{ParticleStyle, WgpuCtx};
+
+/// An offline render of the simulation, one frame every `ticks_per_frame` ticks.
+#[derive(Clone, Debug)]
+pub struct RecordSettings {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    pub fps: u32,
+    pub ticks_per_frame: u32,
+    pub camera_position: [f64; 2],
+    pub zoom: f32,
+    pub particle_style: ParticleStyle,
+    pub trails: TrailSettings,
+    pub post: PostSettings,
+    pub coloring: Coloring,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        Self {
+            path: "recording.y4m".to_string(),
+            width: 1280,
+            height: 720,
+            frames: 600,
+            fps: 60,
+            ticks_per_frame: 1,
+            camera_position: [0.0, 0.0],
+            zoom: 1.0 / 1000.0,
+            particle_style: ParticleStyle::default(),
+            trails: TrailSettings::default(),
+            post: PostSettings::default(),
+            coloring: Coloring::default(),
+        }
+    }
+}
+
+/// Shared with the UI while a recording runs on its own thread.
+#[derive(Default)]
+pub struct RecordStatus {
+    pub frames_written: AtomicU32,
+    pub finished: AtomicBool,
+}
+
+/// Writes RGBA frames as 8-bit 4:2:0 YUV4MPEG2, BT.601 limited range.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: u32,
+    height: u32,
+    planes: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut writer: W, width: u32, height: u32, fps: u32) -> io::Result<Self> {
+        writeln!(writer, "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C420jpeg")?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            planes: Vec::new(),
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &image::RgbaImage) -> io::Result<()> {
+        if image.dimensions() != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "frame is {:?}, video is {}x{}",
+                    image.dimensions(),
+                    self.width,
+                    self.height
+                ),
+            ));
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let rgb = |x: usize, y: usize| {
+            let pixel = image.get_pixel(x as u32, y as u32).0;
+            [pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0)
+        };
+
+        self.planes.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = rgb(x, y);
+                self.planes.push((16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8);
+            }
+        }
+        // Each chroma sample averages a 2x2 block; odd edges repeat the last row or column
+        let mut cb = Vec::with_capacity(chroma_width * chroma_height);
+        let mut cr = Vec::with_capacity(chroma_width * chroma_height);
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let mut sum = [0.0; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sample = rgb((2 * cx + dx).min(width - 1), (2 * cy + dy).min(height - 1));
+                    for c in 0..3 {
+                        sum[c] += sample[c] / 4.0;
+                    }
+                }
+                let [r, g, b] = sum;
+                cb.push((128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8);
+                cr.push((128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8);
+            }
+        }
+        self.planes.extend_from_slice(&cb);
+        self.planes.extend_from_slice(&cr);
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.planes)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Renders `settings.frames` frames of `sim` offscreen into a Y4M file,
+/// stepping a fixed number of ticks between frames regardless of how long
+/// rendering takes. `progress` is called with the number of frames written.
+pub fn record(
+    sim: &mut Sim,
+    time_step: &TimeStep,
+    settings: &RecordSettings,
+    mut progress: impl FnMut(u32),
+) -> io::Result<()> {
+    let Some(mut wgpu_ctx) = WgpuCtx::new_headless(settings.width, settings.height) else {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "no graphics adapter available"));
+    };
+    wgpu_ctx.camera.position = settings.camera_position;
+    wgpu_ctx.camera.zoom = settings.zoom;
+    wgpu_ctx.particle_style = settings.particle_style;
+    wgpu_ctx.trails = settings.trails;
+    wgpu_ctx.post = settings.post.clone();
+
+    let file = BufWriter::new(File::create(&settings.path)?);
+    let mut writer = Y4mWriter::new(file, settings.width.max(1), settings.height.max(1), settings.fps.max(1))?;
+    let mut coloring = settings.coloring.clone();
+    wgpu_ctx.set_colormap(&coloring.table());
+    let mut bodies = Vec::new();
+    let mut values = Vec::new();
+    for frame in 0..settings.frames {
+        bodies.clear();
+        sim.bodies.tessellate(&mut bodies);
+        let particles = sim.particles();
+        if coloring.attribute.is_some() {
+            let index = ParticleIndex::new(&particles, sim.radius);
+            coloring.evaluate(&particles, &index, &mut values);
+        }
+        wgpu_ctx.update_instances(&particles, &[], &values);
+        wgpu_ctx.update_polygons(&bodies);
+        writer.write_frame(&wgpu_ctx.render_to_image())?;
+
+        for _ in 0..settings.ticks_per_frame {
+            sim.advance(time_step);
+        }
+        progress(frame + 1);
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Records a copy of `sim` on a background thread, leaving the live run untouched.
+pub fn spawn(mut sim: Sim, time_step: TimeStep, settings: RecordSettings, status: Arc<RecordStatus>) {
+    std::thread::spawn(move || {
+        println!("Recording {} frames to {}", settings.frames, settings.path);
+        let result = record(&mut sim, &time_step, &settings, |frames| {
+            status.frames_written.store(frames, Ordering::Relaxed);
+        });
+        match result {
+            Ok(()) => println!("Finished recording {}", settings.path),
+            Err(e) => eprintln!("Recording to {} failed: {e}", settings.path),
+        }
+        status.finished.store(true, Ordering::Relaxed);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    fn encode(width: u32, height: u32, frames: &[image::RgbaImage]) -> Vec<u8> {
+        let mut writer = Y4mWriter::new(Vec::new(), width, height, 30).unwrap();
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    /// Splits the output after the header into its frames' Y, Cb and Cr planes.
+    fn planes(data: &[u8], header: &str, luma: usize, chroma: usize) -> Vec<[Vec<u8>; 3]> {
+        let mut rest = data.strip_prefix(header.as_bytes()).expect("header");
+        let mut frames = Vec::new();
+        while !rest.is_empty() {
+            rest = rest.strip_prefix(b"FRAME\n").expect("frame marker");
+            assert!(rest.len() >= luma + 2 * chroma, "truncated frame");
+            let (y, tail) = rest.split_at(luma);
+            let (cb, tail) = tail.split_at(chroma);
+            let (cr, tail) = tail.split_at(chroma);
+            frames.push([y.to_vec(), cb.to_vec(), cr.to_vec()]);
+            rest = tail;
+        }
+        frames
+    }
+
+    #[test]
+    fn solid_frames_use_limited_range() {
+        let frames = [WHITE, BLACK, RED].map(|color| image::RgbaImage::from_pixel(2, 2, image::Rgba(color)));
+        let data = encode(2, 2, &frames);
+        let frames = planes(&data, "YUV4MPEG2 W2 H2 F30:1 Ip A1:1 C420jpeg\n", 4, 1);
+        assert_eq!(frames.len(), 3);
+        for ([y, cb, cr], expected) in frames.iter().zip([[235, 128, 128], [16, 128, 128], [81, 90, 240]]) {
+            assert_eq!(y, &vec![expected[0]; 4]);
+            assert_eq!(cb, &vec![expected[1]]);
+            assert_eq!(cr, &vec![expected[2]]);
+        }
+    }
+
+    #[test]
+    fn odd_sizes_round_chroma_up() {
+        // Red with a white last column, which gets a chroma column of its own
+        let frame = image::RgbaImage::from_fn(3, 3, |x, _| image::Rgba(if x == 2 { WHITE } else { RED }));
+        let data = encode(3, 3, &[frame]);
+        let frames = planes(&data, "YUV4MPEG2 W3 H3 F30:1 Ip A1:1 C420jpeg\n", 9, 4);
+        let [y, cb, cr] = &frames[0];
+        assert_eq!(y, &[81, 81, 235].repeat(3));
+        assert_eq!(cb, &[90, 128, 90, 128]);
+        assert_eq!(cr, &[240, 128, 240, 128]);
+
+        let mut writer = Y4mWriter::new(Vec::new(), 3, 3, 30).unwrap();
+        assert!(writer.write_frame(&image::RgbaImage::new(2, 2)).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Timestamp pairs the GPU timer has room for in one frame
+const MAX_TIMED_NODES: usize = 32;
+
+/// Handle to a texture or buffer declared in a `RenderGraph`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// Size of a transient texture, relative to the graph's output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TargetSize {
+    Output,
+    // Halved this many times, down to one texel
+    Halved(u32),
+}
+
+enum Resource<'node> {
+    Texture(&'node wgpu::TextureView),
+    Buffer(&'node wgpu::Buffer),
+    // Allocated from the pool when the graph runs
+    Transient(TransientKey),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct TransientKey {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+}
+
+type NodeFn<'node> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &NodeResources) + 'node>;
+
+struct Node<'node> {
+    name: &'static str,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    run: NodeFn<'node>,
+}
+
+/// Views of the graph's resources, handed to each node as it runs.
+pub struct NodeResources {
+    textures: Vec<Option<wgpu::TextureView>>,
+    buffers: Vec<Option<wgpu::Buffer>>,
+}
+
+impl NodeResources {
+    pub fn view(&self, id: ResourceId) -> &wgpu::TextureView {
+        self.textures[id.0].as_ref().expect("render graph resource is not a texture")
+    }
+
+    pub fn buffer(&self, id: ResourceId) -> &wgpu::Buffer {
+        self.buffers[id.0].as_ref().expect("render graph resource is not a buffer")
+    }
+}
+
+/// One frame's passes. Nodes declare what they read and write; the graph runs
+/// writers of a resource in the order they were added, and readers after all
+/// of its writers, whatever order the nodes were added in.
+pub struct RenderGraph<'node> {
+    width: u32,
+    height: u32,
+    resources: Vec<Resource<'node>>,
+    nodes: Vec<Node<'node>>,
+}
+
+impl<'node> RenderGraph<'node> {
+    /// A graph whose transient textures are sized against `width` x `height`.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+            resources: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// A texture that lives outside the graph, such as the swapchain image.
+    pub fn import_texture(&mut self, view: &'node wgpu::TextureView) -> ResourceId {
+        self.add_resource(Resource::Texture(view))
+    }
+
+    pub fn import_buffer(&mut self, buffer: &'node wgpu::Buffer) -> ResourceId {
+        self.add_resource(Resource::Buffer(buffer))
+    }
+
+    /// A texture only used within the frame, taken from the pool when the graph runs.
+    pub fn transient(&mut self, format: wgpu::TextureFormat, size: TargetSize) -> ResourceId {
+        let (width, height) = match size {
+            TargetSize::Output => (self.width, self.height),
+            TargetSize::Halved(times) => ((self.width >> times).max(1), (self.height >> times).max(1)),
+        };
+        self.add_resource(Resource::Transient(TransientKey { format, width, height }))
+    }
+
+    fn add_resource(&mut self, resource: Resource<'node>) -> ResourceId {
+        self.resources.push(resource);
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn add_node(
+        &mut self,
+        name: &'static str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+        run: impl FnOnce(&mut wgpu::CommandEncoder, &NodeResources) + 'node,
+    ) {
+        self.nodes.push(Node {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            run: Box::new(run),
+        });
+    }
+
+    /// Node indices in the order they have to run.
+    fn order(&self) -> Vec<usize> {
+        let count = self.nodes.len();
+        let mut after: Vec<Vec<usize>> = vec![Vec::new(); count];
+        for resource in 0..self.resources.len() {
+            let id = ResourceId(resource);
+            let writers: Vec<usize> = (0..count).filter(|&n| self.nodes[n].writes.contains(&id)).collect();
+            for pair in writers.windows(2) {
+                after[pair[1]].push(pair[0]);
+            }
+            for reader in (0..count).filter(|&n| self.nodes[n].reads.contains(&id)) {
+                if !writers.contains(&reader) {
+                    after[reader].extend(&writers);
+                }
+            }
+        }
+
+        // Kahn's algorithm, taking the earliest added node whenever several are ready
+        let mut order = Vec::with_capacity(count);
+        let mut done = vec![false; count];
+        while order.len() < count {
+            let Some(next) = (0..count).find(|&n| !done[n] && after[n].iter().all(|&d| done[d])) else {
+                let stuck: Vec<&str> = (0..count).filter(|&n| !done[n]).map(|n| self.nodes[n].name).collect();
+                panic!("render graph has a cycle between {stuck:?}");
+            };
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    /// Records every node into `encoder` in dependency order, replacing the
+    /// cache's pass timings with this graph's.
+    pub fn execute(self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, cache: &mut GraphCache) {
+        self.run(device, encoder, cache, true);
+    }
+
+    /// Like `execute`, but leaves the timings alone, for one-off graphs such
+    /// as captures that would otherwise hide the window's.
+    pub fn execute_untimed(self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, cache: &mut GraphCache) {
+        self.run(device, encoder, cache, false);
+    }
+
+    fn run(self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, cache: &mut GraphCache, timed: bool) {
+        let order = self.order();
+        cache.generation += 1;
+        cache.collect_gpu_timings();
+        let mut timer = if timed { cache.take_gpu_timer(device, order.len()) } else { None };
+
+        let mut resources = NodeResources {
+            textures: Vec::with_capacity(self.resources.len()),
+            buffers: Vec::with_capacity(self.resources.len()),
+        };
+        let mut taken: HashMap<TransientKey, usize> = HashMap::new();
+        for resource in &self.resources {
+            let (view, buffer) = match resource {
+                Resource::Texture(view) => (Some((*view).clone()), None),
+                Resource::Buffer(buffer) => (None, Some((*buffer).clone())),
+                Resource::Transient(key) => {
+                    let index = taken.entry(*key).or_insert(0);
+                    let view = cache.pool.take(*key, *index, cache.generation, || create_transient(device, *key));
+                    *index += 1;
+                    (Some(view), None)
+                }
+            };
+            resources.textures.push(view);
+            resources.buffers.push(buffer);
+        }
+        cache.pool.trim(cache.generation);
+
+        let mut nodes: Vec<Option<Node>> = self.nodes.into_iter().map(Some).collect();
+        if timed {
+            cache.timings.clear();
+        }
+        for (slot, &index) in order.iter().enumerate() {
+            let node = nodes[index].take().unwrap();
+            if let Some(timer) = timer.as_ref() {
+                encoder.write_timestamp(&timer.queries, 2 * slot as u32);
+            }
+            let start = Instant::now();
+            (node.run)(encoder, &resources);
+            if timed {
+                cache.timings.push(PassTiming {
+                    name: node.name,
+                    cpu: start.elapsed(),
+                    gpu: cache.gpu_timings.get(node.name).copied(),
+                });
+            }
+            if let Some(timer) = timer.as_ref() {
+                encoder.write_timestamp(&timer.queries, 2 * slot as u32 + 1);
+            }
+        }
+        if let Some(mut timer) = timer.take() {
+            timer.resolve(encoder, cache.timings.iter().map(|timing| timing.name).collect());
+            cache.timer = Some(timer);
+        }
+    }
+}
+
+/// How long one node took in the last frame.
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub name: &'static str,
+    // Recording the commands
+    pub cpu: Duration,
+    // Running them, when the adapter supports timestamp queries; a few frames behind
+    pub gpu: Option<Duration>,
+}
+
+/// What the graph keeps between frames: pooled transient textures and timings.
+#[derive(Default)]
+pub struct GraphCache {
+    pool: TexturePool<wgpu::TextureView>,
+    generation: u64,
+    timer: Option<GpuTimer>,
+    pub timings: Vec<PassTiming>,
+    // Per node name, from the last readback
+    gpu_timings: HashMap<&'static str, Duration>,
+}
+
+impl GraphCache {
+    /// Starts reading back GPU timings; call after submitting the executed graph.
+    pub fn after_submit(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if let Some(timer) = self.timer.as_mut() {
+            timer.period = queue.get_timestamp_period();
+            timer.map();
+            device.poll(wgpu::Maintain::Poll);
+        }
+    }
+
+    // The timer to write into this graph, if the device has one and it isn't busy.
+    // It goes back into the cache once resolved
+    fn take_gpu_timer(&mut self, device: &wgpu::Device, nodes: usize) -> Option<GpuTimer> {
+        let needed = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        if !device.features().contains(needed) || nodes > MAX_TIMED_NODES {
+            return None;
+        }
+        let timer = self.timer.take().unwrap_or_else(|| GpuTimer::new(device));
+        if timer.state == TimerState::Idle {
+            Some(timer)
+        } else {
+            self.timer = Some(timer);
+            None
+        }
+    }
+
+    fn collect_gpu_timings(&mut self) {
+        if let Some(timings) = self.timer.as_mut().and_then(GpuTimer::read) {
+            self.gpu_timings = timings;
+        }
+    }
+}
+
+fn create_transient(device: &wgpu::Device, key: TransientKey) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Transient Target"),
+        size: wgpu::Extent3d {
+            width: key.width,
+            height: key.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: key.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// Generic over the pooled item so the reuse rules can be tested without a device
+struct TexturePool<T> {
+    // Textures of one key, with the generation that last used each
+    textures: HashMap<TransientKey, Vec<(T, u64)>>,
+}
+
+impl<T> Default for TexturePool<T> {
+    fn default() -> Self {
+        Self {
+            textures: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> TexturePool<T> {
+    /// The `index`th texture of `key` in this generation, made with `create` if the pool is short.
+    fn take(&mut self, key: TransientKey, index: usize, generation: u64, mut create: impl FnMut() -> T) -> T {
+        let textures = self.textures.entry(key).or_default();
+        while textures.len() <= index {
+            textures.push((create(), generation));
+        }
+        textures[index].1 = generation;
+        textures[index].0.clone()
+    }
+
+    // Drops textures the last two graphs didn't use, such as those of an old
+    // window size, while a capture in between doesn't evict the window's
+    fn trim(&mut self, generation: u64) {
+        for textures in self.textures.values_mut() {
+            textures.retain(|(_, used)| generation - used <= 1);
+        }
+        self.textures.retain(|_, textures| !textures.is_empty());
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum TimerState {
+    Idle,
+    // Resolved into the readback buffer by the frame's commands
+    Resolved,
+    Mapping,
+}
+
+struct GpuTimer {
+    queries: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    // Nanoseconds per tick
+    period: f32,
+    state: TimerState,
+    names: Vec<&'static str>,
+    mapped: Arc<AtomicBool>,
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device) -> Self {
+        let count = 2 * MAX_TIMED_NODES as u32;
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+        Self {
+            queries: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: 1.0,
+            state: TimerState::Idle,
+            names: Vec::new(),
+            mapped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder, names: Vec<&'static str>) {
+        let count = 2 * names.len() as u32;
+        encoder.resolve_query_set(&self.queries, 0..count, &self.resolve_buffer, 0);
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+        self.names = names;
+        self.state = TimerState::Resolved;
+    }
+
+    fn map(&mut self) {
+        if self.state != TimerState::Resolved {
+            return;
+        }
+        self.mapped.store(false, Ordering::Relaxed);
+        let mapped = Arc::clone(&self.mapped);
+        self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            mapped.store(result.is_ok(), Ordering::Relaxed);
+        });
+        self.state = TimerState::Mapping;
+    }
+
+    // The durations per node once the readback has landed
+    fn read(&mut self) -> Option<HashMap<&'static str, Duration>> {
+        if self.state != TimerState::Mapping || !self.mapped.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut timings = HashMap::new();
+        {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let ticks: &[u64] = bytemuck::cast_slice(&data);
+            for (slot, name) in self.names.iter().enumerate() {
+                let elapsed = ticks[2 * slot + 1].saturating_sub(ticks[2 * slot]);
+                let nanos = (elapsed as f64 * self.period as f64) as u64;
+                *timings.entry(*name).or_default() += Duration::from_nanos(nanos);
+            }
+        }
+        self.readback_buffer.unmap();
+        self.state = TimerState::Idle;
+        Some(timings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: TransientKey = TransientKey {
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        width: 4,
+        height: 4,
+    };
+
+    fn names(graph: &RenderGraph) -> Vec<&'static str> {
+        graph.order().into_iter().map(|n| graph.nodes[n].name).collect()
+    }
+
+    #[test]
+    fn writers_run_in_order_and_readers_after_them() {
+        let mut graph = RenderGraph::new(4, 4);
+        let scene = graph.transient(KEY.format, TargetSize::Output);
+        let blurred = graph.transient(KEY.format, TargetSize::Halved(1));
+        let output = graph.transient(KEY.format, TargetSize::Output);
+        // Readers added before the writers they depend on, and in between them
+        graph.add_node("Present", &[output], &[], |_, _| {});
+        graph.add_node("Clear", &[], &[scene], |_, _| {});
+        graph.add_node("Blur", &[scene], &[blurred], |_, _| {});
+        graph.add_node("Overlay", &[scene], &[scene], |_, _| {});
+        graph.add_node("Composite", &[scene, blurred], &[output], |_, _| {});
+        assert_eq!(names(&graph), ["Clear", "Overlay", "Blur", "Composite", "Present"]);
+    }
+
+    #[test]
+    fn independent_nodes_keep_their_order() {
+        let mut graph = RenderGraph::new(4, 4);
+        let a = graph.transient(KEY.format, TargetSize::Output);
+        let b = graph.transient(KEY.format, TargetSize::Output);
+        graph.add_node("B", &[], &[b], |_, _| {});
+        graph.add_node("A", &[], &[a], |_, _| {});
+        graph.add_node("Read A", &[a], &[], |_, _| {});
+        assert_eq!(names(&graph), ["B", "A", "Read A"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "render graph has a cycle")]
+    fn cycles_panic() {
+        let mut graph = RenderGraph::new(4, 4);
+        let a = graph.transient(KEY.format, TargetSize::Output);
+        let b = graph.transient(KEY.format, TargetSize::Output);
+        graph.add_node("A to B", &[a], &[b], |_, _| {});
+        graph.add_node("B to A", &[b], &[a], |_, _| {});
+        graph.order();
+    }
+
+    #[test]
+    fn pool_reuses_and_trims_across_generations() {
+        let mut pool = TexturePool::default();
+        let mut created = 0;
+        let mut take = |pool: &mut TexturePool<u32>, key, index, generation| {
+            pool.take(key, index, generation, || {
+                created += 1;
+                created
+            })
+        };
+        let resized = TransientKey { width: 8, ..KEY };
+
+        // Two textures of one key in the first graph
+        assert_eq!([take(&mut pool, KEY, 0, 1), take(&mut pool, KEY, 1, 1)], [1, 2]);
+        pool.trim(1);
+        // A graph using one of them keeps the other around for the next
+        assert_eq!(take(&mut pool, KEY, 0, 2), 1);
+        pool.trim(2);
+        assert_eq!(pool.textures[&KEY].len(), 2);
+        assert_eq!(take(&mut pool, KEY, 0, 3), 1);
+        pool.trim(3);
+        assert_eq!(pool.textures[&KEY].len(), 1);
+
+        // After a resize the old size survives one graph, then goes
+        assert_eq!(take(&mut pool, resized, 0, 4), 3);
+        pool.trim(4);
+        assert!(pool.textures.contains_key(&KEY));
+        assert_eq!(take(&mut pool, resized, 0, 5), 3);
+        pool.trim(5);
+        assert!(!pool.textures.contains_key(&KEY));
+        assert_eq!(take(&mut pool, KEY, 0, 6), 4);
+    }
+}
@@ -0,0 +1,951 @@
+use std::collections::HashMap;
+
+type Vec2 = [f32; 2];
+
+/// Vertex of a tessellated body as published to the render thread. Positions
+/// are world coordinates, rebased onto the camera before upload like particles.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BodyVertex {
+    pub position: [f64; 2],
+    pub color: [f32; 3],
+}
+
+fn add(a: Vec2, b: Vec2) -> Vec2 {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale(a: Vec2, s: f32) -> Vec2 {
+    [a[0] * s, a[1] * s]
+}
+
+fn dot(a: Vec2, b: Vec2) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Velocity of a point at offset `r` on a body spinning at `w`.
+fn cross_scalar(w: f32, r: Vec2) -> Vec2 {
+    [-w * r[1], w * r[0]]
+}
+
+fn length(a: Vec2) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn rotate(a: Vec2, angle: f32) -> Vec2 {
+    let (s, c) = angle.sin_cos();
+    [c * a[0] - s * a[1], s * a[0] + c * a[1]]
+}
+
+/// Collision shape in body-local coordinates, centred on the centre of mass.
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Circle { radius: f32 },
+    /// Counter-clockwise convex polygon with outward edge normals.
+    Polygon { vertices: Vec<Vec2>, normals: Vec<Vec2> },
+}
+
+impl Shape {
+    pub fn circle(radius: f32) -> Self {
+        Shape::Circle { radius }
+    }
+
+    pub fn rect(half_width: f32, half_height: f32) -> Self {
+        Self::polygon(&[
+            [-half_width, -half_height],
+            [half_width, -half_height],
+            [half_width, half_height],
+            [-half_width, half_height],
+        ])
+        .expect("rectangle is a valid polygon")
+    }
+
+    /// Builds the convex hull of `points`, recentred on its centroid.
+    /// Returns `None` for fewer than three non-collinear points.
+    pub fn polygon(points: &[Vec2]) -> Option<Self> {
+        let mut hull = convex_hull(points);
+        if hull.len() < 3 {
+            return None;
+        }
+
+        let (area, centroid) = polygon_area_centroid(&hull);
+        if area <= f32::EPSILON {
+            return None;
+        }
+        for vertex in hull.iter_mut() {
+            *vertex = sub(*vertex, centroid);
+        }
+
+        let normals = (0..hull.len())
+            .map(|i| {
+                let edge = sub(hull[(i + 1) % hull.len()], hull[i]);
+                scale([edge[1], -edge[0]], 1.0 / length(edge))
+            })
+            .collect();
+        Some(Shape::Polygon {
+            vertices: hull,
+            normals,
+        })
+    }
+
+    /// Mass and moment of inertia about the centre of mass.
+    fn mass_properties(&self, density: f32) -> (f32, f32) {
+        match self {
+            Shape::Circle { radius } => {
+                let mass = density * std::f32::consts::PI * radius * radius;
+                (mass, 0.5 * mass * radius * radius)
+            }
+            Shape::Polygon { vertices, .. } => {
+                let mut area = 0.0;
+                let mut inertia = 0.0;
+                for i in 0..vertices.len() {
+                    let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+                    let triangle = cross(a, b);
+                    area += 0.5 * triangle;
+                    inertia += triangle * (dot(a, a) + dot(a, b) + dot(b, b)) / 12.0;
+                }
+                (density * area, density * inertia)
+            }
+        }
+    }
+
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            Shape::Circle { radius } => *radius,
+            Shape::Polygon { vertices, .. } => {
+                vertices.iter().map(|v| length(*v)).fold(0.0, f32::max)
+            }
+        }
+    }
+}
+
+/// Andrew's monotone chain, counter-clockwise without collinear points.
+fn convex_hull(points: &[Vec2]) -> Vec<Vec2> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut hull: Vec<Vec2> = Vec::with_capacity(sorted.len() * 2);
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Vec2>> = if pass == 0 {
+            Box::new(sorted.iter())
+        } else {
+            Box::new(sorted.iter().rev())
+        };
+        for &p in iter {
+            while hull.len() >= start + 2 {
+                let (a, b) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+                if cross(sub(b, a), sub(p, a)) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // The last point of each chain starts the other one
+        hull.pop();
+    }
+    hull
+}
+
+fn polygon_area_centroid(vertices: &[Vec2]) -> (f32, Vec2) {
+    let mut area = 0.0;
+    let mut centroid = [0.0, 0.0];
+    for i in 0..vertices.len() {
+        let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+        let triangle = 0.5 * cross(a, b);
+        area += triangle;
+        centroid = add(centroid, scale(add(a, b), triangle / 3.0));
+    }
+    (area, scale(centroid, 1.0 / area))
+}
+
+#[derive(Clone, Debug)]
+pub struct RigidBody {
+    pub shape: Shape,
+    pub position: Vec2,
+    pub angle: f32,
+    pub velocity: Vec2,
+    pub angular_velocity: f32,
+    // Zero for static bodies
+    pub inv_mass: f32,
+    pub inv_inertia: f32,
+    pub friction: f32,
+    pub restitution: f32,
+    pub awake: bool,
+    // Seconds spent below the sleep velocity thresholds
+    pub sleep_time: f32,
+}
+
+impl RigidBody {
+    pub fn new(shape: Shape, position: Vec2, density: f32) -> Self {
+        let (mass, inertia) = shape.mass_properties(density);
+        Self {
+            inv_mass: if mass > 0.0 { 1.0 / mass } else { 0.0 },
+            inv_inertia: if inertia > 0.0 { 1.0 / inertia } else { 0.0 },
+            ..Self::fixed(shape, position)
+        }
+    }
+
+    /// A body that never moves.
+    pub fn fixed(shape: Shape, position: Vec2) -> Self {
+        Self {
+            shape,
+            position,
+            angle: 0.0,
+            velocity: [0.0, 0.0],
+            angular_velocity: 0.0,
+            inv_mass: 0.0,
+            inv_inertia: 0.0,
+            friction: 0.6,
+            restitution: 0.1,
+            awake: true,
+            sleep_time: 0.0,
+        }
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.inv_mass == 0.0
+    }
+
+    /// Dynamic and not asleep.
+    fn is_moving(&self) -> bool {
+        !self.is_static() && self.awake
+    }
+
+    /// Inverse mass and inertia as seen by the solver; sleeping bodies act static.
+    fn inverse_masses(&self) -> (f32, f32) {
+        if self.awake {
+            (self.inv_mass, self.inv_inertia)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    pub fn wake(&mut self) {
+        self.awake = true;
+        self.sleep_time = 0.0;
+    }
+
+    pub fn to_world(&self, local: Vec2) -> Vec2 {
+        add(self.position, rotate(local, self.angle))
+    }
+
+    fn to_local(&self, world: Vec2) -> Vec2 {
+        rotate(sub(world, self.position), -self.angle)
+    }
+
+    fn point_velocity(&self, world: Vec2) -> Vec2 {
+        add(
+            self.velocity,
+            cross_scalar(self.angular_velocity, sub(world, self.position)),
+        )
+    }
+
+    fn world_vertices(&self) -> Vec<Vec2> {
+        match &self.shape {
+            Shape::Circle { .. } => Vec::new(),
+            Shape::Polygon { vertices, .. } => vertices.iter().map(|v| self.to_world(*v)).collect(),
+        }
+    }
+
+    fn world_normals(&self) -> Vec<Vec2> {
+        match &self.shape {
+            Shape::Circle { .. } => Vec::new(),
+            Shape::Polygon { normals, .. } => normals.iter().map(|n| rotate(*n, self.angle)).collect(),
+        }
+    }
+
+    /// Closest surface point to a circle at `center`, if they overlap.
+    /// Returns the normal pointing from the body to the circle and the penetration depth.
+    pub fn circle_contact(&self, center: Vec2, radius: f32) -> Option<(Vec2, f32)> {
+        match &self.shape {
+            Shape::Circle { radius: own } => {
+                let d = sub(center, self.position);
+                let distance = length(d);
+                if distance >= own + radius {
+                    return None;
+                }
+                let normal = if distance > f32::EPSILON {
+                    scale(d, 1.0 / distance)
+                } else {
+                    [0.0, 1.0]
+                };
+                Some((normal, own + radius - distance))
+            }
+            Shape::Polygon { vertices, normals } => {
+                let (normal, penetration) = polygon_circle(vertices, normals, self.to_local(center), radius)?;
+                Some((rotate(normal, self.angle), penetration))
+            }
+        }
+    }
+}
+
+/// Separating axis test of a local-space circle against a polygon.
+fn polygon_circle(vertices: &[Vec2], normals: &[Vec2], center: Vec2, radius: f32) -> Option<(Vec2, f32)> {
+    let mut face = 0;
+    let mut separation = f32::MIN;
+    for i in 0..vertices.len() {
+        let s = dot(normals[i], sub(center, vertices[i]));
+        if s > radius {
+            return None;
+        }
+        if s > separation {
+            separation = s;
+            face = i;
+        }
+    }
+
+    // Centre inside the polygon
+    if separation < f32::EPSILON {
+        return Some((normals[face], radius - separation));
+    }
+
+    let v1 = vertices[face];
+    let v2 = vertices[(face + 1) % vertices.len()];
+    let closest = if dot(sub(center, v1), sub(v2, v1)) <= 0.0 {
+        v1
+    } else if dot(sub(center, v2), sub(v1, v2)) <= 0.0 {
+        v2
+    } else {
+        return Some((normals[face], radius - separation));
+    };
+    let d = sub(center, closest);
+    let distance = length(d);
+    if distance >= radius {
+        return None;
+    }
+    Some((scale(d, 1.0 / distance), radius - distance))
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ManifoldPoint {
+    position: Vec2,
+    penetration: f32,
+    // Identifies the features in contact so impulses can be matched across steps
+    id: u32,
+}
+
+#[derive(Clone, Debug)]
+struct Manifold {
+    // Points from body `a` to body `b`
+    normal: Vec2,
+    points: Vec<ManifoldPoint>,
+}
+
+fn collide(a: &RigidBody, b: &RigidBody) -> Option<Manifold> {
+    match (&a.shape, &b.shape) {
+        (Shape::Circle { .. }, Shape::Circle { radius }) | (Shape::Polygon { .. }, Shape::Circle { radius }) => {
+            let (normal, penetration) = a.circle_contact(b.position, *radius)?;
+            Some(Manifold {
+                normal,
+                points: vec![ManifoldPoint {
+                    position: sub(b.position, scale(normal, radius - 0.5 * penetration)),
+                    penetration,
+                    id: 0,
+                }],
+            })
+        }
+        (Shape::Circle { .. }, Shape::Polygon { .. }) => {
+            let mut manifold = collide(b, a)?;
+            manifold.normal = scale(manifold.normal, -1.0);
+            Some(manifold)
+        }
+        (Shape::Polygon { .. }, Shape::Polygon { .. }) => collide_polygons(a, b),
+    }
+}
+
+/// Edge of `a` with the largest separation from `b`'s vertices.
+fn max_separation(vertices_a: &[Vec2], normals_a: &[Vec2], vertices_b: &[Vec2]) -> (usize, f32) {
+    let mut best = (0, f32::MIN);
+    for i in 0..vertices_a.len() {
+        let separation = vertices_b
+            .iter()
+            .map(|v| dot(normals_a[i], sub(*v, vertices_a[i])))
+            .fold(f32::MAX, f32::min);
+        if separation > best.1 {
+            best = (i, separation);
+        }
+    }
+    best
+}
+
+/// Keeps the part of segment `points` with `dot(normal, p) <= offset`.
+fn clip_segment(points: [(Vec2, usize); 2], normal: Vec2, offset: f32) -> Option<[(Vec2, usize); 2]> {
+    let d0 = dot(normal, points[0].0) - offset;
+    let d1 = dot(normal, points[1].0) - offset;
+    let mut out = Vec::with_capacity(2);
+    if d0 <= 0.0 {
+        out.push(points[0]);
+    }
+    if d1 <= 0.0 {
+        out.push(points[1]);
+    }
+    if d0 * d1 < 0.0 {
+        let t = d0 / (d0 - d1);
+        let crossing = add(points[0].0, scale(sub(points[1].0, points[0].0), t));
+        // The clipped point inherits the feature that was cut off
+        let feature = if d0 > 0.0 { points[0].1 } else { points[1].1 };
+        out.push((crossing, feature));
+    }
+    (out.len() == 2).then(|| [out[0], out[1]])
+}
+
+/// SAT with reference-face clipping, producing up to two contact points.
+fn collide_polygons(a: &RigidBody, b: &RigidBody) -> Option<Manifold> {
+    let (vertices_a, normals_a) = (a.world_vertices(), a.world_normals());
+    let (vertices_b, normals_b) = (b.world_vertices(), b.world_normals());
+
+    let (edge_a, separation_a) = max_separation(&vertices_a, &normals_a, &vertices_b);
+    if separation_a > 0.0 {
+        return None;
+    }
+    let (edge_b, separation_b) = max_separation(&vertices_b, &normals_b, &vertices_a);
+    if separation_b > 0.0 {
+        return None;
+    }
+
+    // Prefer `a` as the reference so the choice doesn't flicker between frames
+    let flip = separation_b > separation_a + 0.005;
+    let (reference, incident, edge) = if flip {
+        ((&vertices_b, &normals_b), (&vertices_a, &normals_a), edge_b)
+    } else {
+        ((&vertices_a, &normals_a), (&vertices_b, &normals_b), edge_a)
+    };
+    let normal = reference.1[edge];
+
+    // Incident edge is the one most opposed to the reference normal
+    let incident_edge = (0..incident.1.len())
+        .min_by(|&i, &j| dot(normal, incident.1[i]).total_cmp(&dot(normal, incident.1[j])))
+        .unwrap_or(0);
+    let incident_count = incident.0.len();
+    let segment = [
+        (incident.0[incident_edge], incident_edge),
+        (incident.0[(incident_edge + 1) % incident_count], (incident_edge + 1) % incident_count),
+    ];
+
+    let v1 = reference.0[edge];
+    let v2 = reference.0[(edge + 1) % reference.0.len()];
+    let tangent = scale(sub(v2, v1), 1.0 / length(sub(v2, v1)));
+    let segment = clip_segment(segment, scale(tangent, -1.0), -dot(tangent, v1))?;
+    let segment = clip_segment(segment, tangent, dot(tangent, v2))?;
+
+    let offset = dot(normal, v1);
+    let points: Vec<ManifoldPoint> = segment
+        .iter()
+        .filter_map(|&(point, feature)| {
+            let separation = dot(normal, point) - offset;
+            (separation <= 0.0).then(|| ManifoldPoint {
+                position: point,
+                penetration: -separation,
+                id: (edge as u32) << 16 | (feature as u32) << 1 | flip as u32,
+            })
+        })
+        .collect();
+    if points.is_empty() {
+        return None;
+    }
+
+    Some(Manifold {
+        normal: if flip { scale(normal, -1.0) } else { normal },
+        points,
+    })
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ContactPoint {
+    // Offsets from each centre of mass
+    r_a: Vec2,
+    r_b: Vec2,
+    normal_mass: f32,
+    tangent_mass: f32,
+    // Separating speed the normal impulse aims for
+    target: f32,
+    normal_impulse: f32,
+    tangent_impulse: f32,
+    id: u32,
+}
+
+// Index of the bounds when it stands in for body `b`
+const BOUNDS: usize = usize::MAX;
+
+struct Contact {
+    a: usize,
+    b: usize,
+    normal: Vec2,
+    friction: f32,
+    points: Vec<ContactPoint>,
+}
+
+/// Rigid bodies stepped with a sequential-impulse solver.
+#[derive(Clone)]
+pub struct RigidWorld {
+    pub bodies: Vec<RigidBody>,
+    pub iterations: u32,
+    pub sleeping: bool,
+    // Accumulated impulses by (a, b, feature), for warm starting
+    cache: HashMap<(usize, usize, u32), (f32, f32)>,
+}
+
+impl Default for RigidWorld {
+    fn default() -> Self {
+        Self {
+            bodies: Vec::new(),
+            iterations: 10,
+            sleeping: true,
+            cache: HashMap::new(),
+        }
+    }
+}
+
+// Penetration allowed before position correction kicks in
+const SLOP: f32 = 0.05;
+// Fraction of the penetration corrected per step
+const BAUMGARTE: f32 = 0.2;
+const BOUNCE_THRESHOLD: f32 = 1.0;
+const SLEEP_LINEAR: f32 = 0.1;
+const SLEEP_ANGULAR: f32 = 0.05;
+const TIME_TO_SLEEP: f32 = 0.5;
+
+impl RigidWorld {
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn add(&mut self, body: RigidBody) {
+        self.bodies.push(body);
+    }
+
+    pub fn clear(&mut self) {
+        self.bodies.clear();
+        self.cache.clear();
+    }
+
+    fn contact_for(
+        &self,
+        a: usize,
+        b: usize,
+        manifold: Manifold,
+        dt: f32,
+    ) -> Contact {
+        let body_a = &self.bodies[a];
+        let static_body = RigidBody::fixed(Shape::circle(0.0), [0.0, 0.0]);
+        let body_b = if b == BOUNDS { &static_body } else { &self.bodies[b] };
+        let normal = manifold.normal;
+        let tangent = [-normal[1], normal[0]];
+
+        let points = manifold
+            .points
+            .iter()
+            .map(|point| {
+                let r_a = sub(point.position, body_a.position);
+                let r_b = sub(point.position, body_b.position);
+                let (inv_mass_a, inv_inertia_a) = body_a.inverse_masses();
+                let (inv_mass_b, inv_inertia_b) = body_b.inverse_masses();
+                let mass = |axis: Vec2| {
+                    let (ra, rb) = (cross(r_a, axis), cross(r_b, axis));
+                    let k = inv_mass_a + inv_mass_b + inv_inertia_a * ra * ra + inv_inertia_b * rb * rb;
+                    if k > 0.0 { 1.0 / k } else { 0.0 }
+                };
+
+                let relative = sub(body_b.point_velocity(point.position), body_a.point_velocity(point.position));
+                let approach = dot(relative, normal);
+                let restitution = body_a.restitution.max(body_b.restitution);
+                let bounce = if approach < -BOUNCE_THRESHOLD { -restitution * approach } else { 0.0 };
+                let correction = BAUMGARTE / dt * (point.penetration - SLOP).max(0.0);
+
+                let (normal_impulse, tangent_impulse) =
+                    self.cache.get(&(a, b, point.id)).copied().unwrap_or((0.0, 0.0));
+                ContactPoint {
+                    r_a,
+                    r_b,
+                    normal_mass: mass(normal),
+                    tangent_mass: mass(tangent),
+                    target: bounce.max(correction),
+                    normal_impulse,
+                    tangent_impulse,
+                    id: point.id,
+                }
+            })
+            .collect();
+
+        Contact {
+            a,
+            b,
+            normal,
+            friction: (body_a.friction * body_b.friction).sqrt(),
+            points,
+        }
+    }
+
+    fn find_contacts(&mut self, bounds: Vec2, dt: f32) -> Vec<Contact> {
+        let mut contacts = Vec::new();
+        for a in 0..self.bodies.len() {
+            for b in a + 1..self.bodies.len() {
+                let (body_a, body_b) = (&self.bodies[a], &self.bodies[b]);
+                if !body_a.is_moving() && !body_b.is_moving() {
+                    continue;
+                }
+                let reach = body_a.shape.bounding_radius() + body_b.shape.bounding_radius();
+                let d = sub(body_b.position, body_a.position);
+                if dot(d, d) > reach * reach {
+                    continue;
+                }
+                if let Some(manifold) = collide(body_a, body_b) {
+                    contacts.push(self.contact_for(a, b, manifold, dt));
+                }
+            }
+
+            if self.bodies[a].is_moving() {
+                for manifold in self.bounds_manifolds(a, bounds) {
+                    contacts.push(self.contact_for(a, BOUNDS, manifold, dt));
+                }
+            }
+        }
+        contacts
+    }
+
+    /// Contacts of body `a` with the walls of the simulation box, one manifold
+    /// per wall so a body wedged in a corner is held by both.
+    fn bounds_manifolds(&self, a: usize, bounds: Vec2) -> Vec<Manifold> {
+        let body = &self.bodies[a];
+        // Outward wall normals; ids keep each wall's points apart
+        let walls = [([1.0, 0.0], bounds[0]), ([-1.0, 0.0], bounds[0]), ([0.0, 1.0], bounds[1]), ([0.0, -1.0], bounds[1])];
+        let mut manifolds = Vec::new();
+        for (wall, (normal, offset)) in walls.iter().enumerate() {
+            let mut points: Vec<ManifoldPoint> = match &body.shape {
+                Shape::Circle { radius } => {
+                    let penetration = dot(*normal, body.position) + radius - offset;
+                    (penetration > 0.0)
+                        .then_some(ManifoldPoint {
+                            position: add(body.position, scale(*normal, radius - 0.5 * penetration)),
+                            penetration,
+                            id: wall as u32,
+                        })
+                        .into_iter()
+                        .collect()
+                }
+                Shape::Polygon { .. } => body
+                    .world_vertices()
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(i, vertex)| {
+                        let penetration = dot(*normal, vertex) - offset;
+                        (penetration > 0.0).then_some(ManifoldPoint {
+                            position: vertex,
+                            penetration,
+                            id: (wall as u32) << 16 | i as u32,
+                        })
+                    })
+                    .collect(),
+            };
+            if points.is_empty() {
+                continue;
+            }
+            // Keep the two deepest vertices
+            points.sort_by(|p, q| q.penetration.total_cmp(&p.penetration));
+            points.truncate(2);
+            manifolds.push(Manifold {
+                normal: *normal,
+                points,
+            });
+        }
+        manifolds
+    }
+
+    fn apply_impulse(&mut self, contact: &Contact, point: &ContactPoint, impulse: Vec2) {
+        let a = &mut self.bodies[contact.a];
+        let (inv_mass, inv_inertia) = a.inverse_masses();
+        a.velocity = sub(a.velocity, scale(impulse, inv_mass));
+        a.angular_velocity -= inv_inertia * cross(point.r_a, impulse);
+        if contact.b != BOUNDS {
+            let b = &mut self.bodies[contact.b];
+            let (inv_mass, inv_inertia) = b.inverse_masses();
+            b.velocity = add(b.velocity, scale(impulse, inv_mass));
+            b.angular_velocity += inv_inertia * cross(point.r_b, impulse);
+        }
+    }
+
+    fn relative_velocity(&self, contact: &Contact, point: &ContactPoint) -> Vec2 {
+        let a = &self.bodies[contact.a];
+        let va = add(a.velocity, cross_scalar(a.angular_velocity, point.r_a));
+        if contact.b == BOUNDS {
+            return scale(va, -1.0);
+        }
+        let b = &self.bodies[contact.b];
+        let vb = add(b.velocity, cross_scalar(b.angular_velocity, point.r_b));
+        sub(vb, va)
+    }
+
+    fn solve(&mut self, contacts: &mut [Contact]) {
+        for contact in contacts.iter_mut() {
+            let normal = contact.normal;
+            let tangent = [-normal[1], normal[0]];
+            for p in 0..contact.points.len() {
+                let mut point = contact.points[p];
+
+                let vt = dot(self.relative_velocity(contact, &point), tangent);
+                let limit = contact.friction * point.normal_impulse;
+                let accumulated = (point.tangent_impulse - vt * point.tangent_mass).clamp(-limit, limit);
+                let lambda = accumulated - point.tangent_impulse;
+                point.tangent_impulse = accumulated;
+                self.apply_impulse(contact, &point, scale(tangent, lambda));
+
+                let vn = dot(self.relative_velocity(contact, &point), normal);
+                let accumulated = (point.normal_impulse + (point.target - vn) * point.normal_mass).max(0.0);
+                let lambda = accumulated - point.normal_impulse;
+                point.normal_impulse = accumulated;
+                self.apply_impulse(contact, &point, scale(normal, lambda));
+
+                contact.points[p] = point;
+            }
+        }
+    }
+
+    /// Wakes sleeping bodies touched by moving ones.
+    fn wake_touched(&mut self, contacts: &[Contact]) {
+        for contact in contacts {
+            if contact.b == BOUNDS {
+                continue;
+            }
+            let (a, b) = (&self.bodies[contact.a], &self.bodies[contact.b]);
+            let restless = |body: &RigidBody| body.is_moving() && body.sleep_time < TIME_TO_SLEEP;
+            if restless(a) && !b.awake {
+                self.bodies[contact.b].wake();
+            } else if restless(b) && !a.awake {
+                self.bodies[contact.a].wake();
+            }
+        }
+    }
+
+    /// Bodies fall asleep once they and everything they touch have been still for a while.
+    fn update_sleep(&mut self, contacts: &[Contact], dt: f32) {
+        for body in self.bodies.iter_mut().filter(|body| body.is_moving()) {
+            let still = dot(body.velocity, body.velocity) < SLEEP_LINEAR * SLEEP_LINEAR
+                && body.angular_velocity.abs() < SLEEP_ANGULAR;
+            body.sleep_time = if still { body.sleep_time + dt } else { 0.0 };
+        }
+
+        let mut settled: Vec<f32> = self.bodies.iter().map(|body| body.sleep_time).collect();
+        for contact in contacts.iter().filter(|contact| contact.b != BOUNDS) {
+            let (a, b) = (&self.bodies[contact.a], &self.bodies[contact.b]);
+            if !b.is_static() {
+                settled[contact.a] = settled[contact.a].min(b.sleep_time);
+            }
+            if !a.is_static() {
+                settled[contact.b] = settled[contact.b].min(a.sleep_time);
+            }
+        }
+
+        for (body, settled) in self.bodies.iter_mut().zip(settled) {
+            if body.is_moving() && settled >= TIME_TO_SLEEP {
+                body.awake = false;
+                body.velocity = [0.0, 0.0];
+                body.angular_velocity = 0.0;
+            }
+        }
+    }
+
+    pub fn step(&mut self, dt: f32, gravity: Vec2, bounds: Vec2) {
+        if dt <= 0.0 {
+            return;
+        }
+        for body in self.bodies.iter_mut().filter(|body| body.is_moving()) {
+            body.velocity = add(body.velocity, scale(gravity, dt));
+        }
+
+        let mut contacts = self.find_contacts(bounds, dt);
+        self.wake_touched(&contacts);
+
+        for contact in &contacts {
+            for point in &contact.points {
+                let normal = contact.normal;
+                let impulse = add(
+                    scale(normal, point.normal_impulse),
+                    scale([-normal[1], normal[0]], point.tangent_impulse),
+                );
+                self.apply_impulse(contact, point, impulse);
+            }
+        }
+        for _ in 0..self.iterations {
+            self.solve(&mut contacts);
+        }
+
+        self.cache.clear();
+        for contact in &contacts {
+            for point in &contact.points {
+                self.cache.insert(
+                    (contact.a, contact.b, point.id),
+                    (point.normal_impulse, point.tangent_impulse),
+                );
+            }
+        }
+
+        for body in self.bodies.iter_mut().filter(|body| body.is_moving()) {
+            body.position = add(body.position, scale(body.velocity, dt));
+            body.angle += body.angular_velocity * dt;
+        }
+
+        if self.sleeping {
+            self.update_sleep(&contacts, dt);
+        } else {
+            for body in self.bodies.iter_mut() {
+                body.wake();
+            }
+        }
+    }
+
+    /// Pushes a particle out of any body it overlaps and exchanges an impulse
+    /// with that body. Returns whether it hit anything.
+    pub fn collide_particle(&mut self, position: &mut Vec2, velocity: &mut Vec2, radius: f32, mass: f32) -> bool {
+        const RESTITUTION: f32 = 0.5;
+        let mut hit = false;
+        for body in self.bodies.iter_mut() {
+            let reach = body.shape.bounding_radius() + radius;
+            let d = sub(*position, body.position);
+            if dot(d, d) > reach * reach {
+                continue;
+            }
+            let Some((normal, penetration)) = body.circle_contact(*position, radius) else {
+                continue;
+            };
+            hit = true;
+            *position = add(*position, scale(normal, penetration));
+
+            let contact_point = sub(*position, scale(normal, radius));
+            let relative = sub(*velocity, body.point_velocity(contact_point));
+            let approach = dot(relative, normal);
+            if approach >= 0.0 {
+                continue;
+            }
+            // Only hits that would visibly move a sleeping body wake it
+            if !body.is_static() && -approach > SLEEP_LINEAR {
+                body.wake();
+            }
+            let (inv_mass, inv_inertia) = body.inverse_masses();
+            let r = sub(contact_point, body.position);
+            let rn = cross(r, normal);
+            let impulse = -(1.0 + RESTITUTION) * approach / (1.0 / mass + inv_mass + inv_inertia * rn * rn);
+            *velocity = add(*velocity, scale(normal, impulse / mass));
+            body.velocity = sub(body.velocity, scale(normal, impulse * inv_mass));
+            body.angular_velocity -= inv_inertia * rn * impulse;
+        }
+        hit
+    }
+
+    /// Triangulates every body into world-space vertices for the polygon pipeline.
+    pub fn tessellate(&self, out: &mut Vec<BodyVertex>) {
+        const CIRCLE_SEGMENTS: usize = 24;
+        for body in &self.bodies {
+            let color = if body.is_static() {
+                [0.45, 0.45, 0.5]
+            } else if body.awake {
+                [0.85, 0.55, 0.25]
+            } else {
+                [0.45, 0.35, 0.6]
+            };
+            // Local outline placed in f64 so vertices far from the origin keep their shape
+            let local: Vec<Vec2> = match &body.shape {
+                Shape::Circle { radius } => (0..CIRCLE_SEGMENTS)
+                    .map(|i| {
+                        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                        [radius * angle.cos(), radius * angle.sin()]
+                    })
+                    .collect(),
+                Shape::Polygon { vertices, .. } => vertices.clone(),
+            };
+            let center = body.position.map(f64::from);
+            let (sin, cos) = (body.angle as f64).sin_cos();
+            let outline: Vec<[f64; 2]> = local
+                .iter()
+                .map(|&[x, y]| {
+                    let [x, y] = [x as f64, y as f64];
+                    [center[0] + x * cos - y * sin, center[1] + x * sin + y * cos]
+                })
+                .collect();
+
+            // Fan from the centre; the first triangle is darker to show rotation
+            for i in 0..outline.len() {
+                let shade = if i == 0 { 0.6 } else { 1.0 };
+                let color = color.map(|c| c * shade);
+                for position in [center, outline[i], outline[(i + 1) % outline.len()]] {
+                    out.push(BodyVertex { position, color });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+    const GRAVITY: Vec2 = [0.0, -9.8];
+    const WALLS: Vec2 = [100.0, 100.0];
+
+    #[test]
+    fn box_settles_on_floor() {
+        let mut world = RigidWorld::default();
+        world.add(RigidBody::new(Shape::rect(5.0, 5.0), [0.0, -80.0], 0.1));
+        for _ in 0..600 {
+            world.step(DT, GRAVITY, WALLS);
+        }
+        let body = &world.bodies[0];
+        let penetration = -WALLS[1] - (body.position[1] - 5.0);
+        assert!(penetration < 2.0 * SLOP, "penetration {penetration}");
+        assert!(body.angle.abs() < 1e-3, "angle {}", body.angle);
+        assert!(!body.awake, "a resting box falls asleep");
+        assert_eq!(body.velocity, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn stacked_boxes_touch_at_two_points() {
+        let floor = RigidBody::fixed(Shape::rect(10.0, 1.0), [0.0, 0.0]);
+        // Overlaps the floor's top face by 0.1 along its whole bottom edge
+        let block = RigidBody::new(Shape::rect(2.0, 2.0), [0.5, 2.9], 0.1);
+
+        let manifold = collide(&floor, &block).expect("boxes overlap");
+        assert!(length(sub(manifold.normal, [0.0, 1.0])) < 1e-5, "normal {:?}", manifold.normal);
+        assert_eq!(manifold.points.len(), 2);
+        for point in &manifold.points {
+            assert!((point.penetration - 0.1).abs() < 1e-4, "{point:?}");
+        }
+        let mut xs: Vec<f32> = manifold.points.iter().map(|point| point.position[0]).collect();
+        xs.sort_by(f32::total_cmp);
+        assert!((xs[0] + 1.5).abs() < 1e-4 && (xs[1] - 2.5).abs() < 1e-4, "{xs:?}");
+
+        // Swapping the bodies flips the normal so it still points from `a` to `b`
+        let swapped = collide(&block, &floor).expect("boxes overlap");
+        assert!(length(sub(swapped.normal, [0.0, -1.0])) < 1e-5, "normal {:?}", swapped.normal);
+        assert_eq!(swapped.points.len(), 2);
+    }
+
+    #[test]
+    fn stack_rests_without_sinking() {
+        let mut world = RigidWorld::default();
+        for i in 0..3 {
+            world.add(RigidBody::new(Shape::rect(5.0, 5.0), [0.0, -94.0 + 10.5 * i as f32], 0.1));
+        }
+        for _ in 0..900 {
+            world.step(DT, GRAVITY, WALLS);
+        }
+        for (i, body) in world.bodies.iter().enumerate() {
+            // Each box rests on the one below, so its centre sits near 10 units higher
+            let expected = -WALLS[1] + 5.0 + 10.0 * i as f32;
+            assert!((body.position[1] - expected).abs() < 4.0 * SLOP, "box {i} at {:?}", body.position);
+            assert!(body.position[0].abs() < 0.1, "box {i} slid to {:?}", body.position);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What one capture should contain.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Capture {
+    pub include_ui: bool,
+    // Multiple of the window resolution
+    pub scale: u32,
+}
+
+pub struct ScreenshotSettings {
+    pub include_ui: bool,
+    pub scale: u32,
+    pub directory: String,
+    // Set by the hotkey or the button, taken by the next rendered frame
+    pub requested: bool,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            include_ui: false,
+            scale: 1,
+            directory: "screenshots".to_string(),
+            requested: false,
+        }
+    }
+}
+
+impl ScreenshotSettings {
+    pub fn take_request(&mut self) -> Option<Capture> {
+        std::mem::take(&mut self.requested).then_some(Capture {
+            include_ui: self.include_ui,
+            scale: self.scale.max(1),
+        })
+    }
+}
+
+/// Render target for a capture at `scale` times the window size, limited to
+/// what the device supports.
+pub fn capture_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    scale: u32,
+) -> wgpu::Texture {
+    let [width, height] = capture_size(device, config, scale);
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Screenshot Target"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Size of a capture at `scale` times the window size, limited to what the
+/// device supports.
+pub fn capture_size(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, scale: u32) -> [u32; 2] {
+    let max = device.limits().max_texture_dimension_2d;
+    [(config.width * scale).min(max), (config.height * scale).min(max)]
+}
+
+/// `<directory>/screenshot-YYYY-MM-DD_HH-MM-SS.mmm.png`, in UTC.
+pub fn timestamped_path(directory: &Path) -> PathBuf {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time_of_day = seconds % 86_400;
+    directory.join(format!(
+        "screenshot-{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}.{:03}.png",
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis(),
+    ))
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Encodes and writes the PNG off the render thread.
+pub fn save_in_background(image: image::RgbaImage, directory: &str) {
+    let directory = PathBuf::from(directory);
+    std::thread::spawn(move || {
+        if let Err(e) = std::fs::create_dir_all(&directory) {
+            eprintln!("Failed to create {}: {e}", directory.display());
+            return;
+        }
+        let path = timestamped_path(&directory);
+        match image.save(&path) {
+            Ok(()) => println!(
+                "Saved {}x{} screenshot to {}",
+                image.width(),
+                image.height(),
+                path.display()
+            ),
+            Err(e) => eprintln!("Failed to save screenshot to {}: {e}", path.display()),
+        }
+    });
+}
@@ -0,0 +1,586 @@
+use std::thread;
+use std::time::Instant;
+
+use std::simd::cmp::*;
+use std::simd::num::*;
+use std::simd::*;
+
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+
+use crate::granular::{Granular, GranularSettings};
+use crate::history::StateRing;
+use crate::obstacle::Obstacle;
+use crate::presets::Preset;
+use crate::query::ParticleIndex;
+use crate::record::{self, RecordSettings, RecordStatus};
+use crate::rigid::{BodyVertex, RigidBody, RigidWorld};
+use crate::stats::{speed_histogram, SimStats};
+use crate::tools::{Brush, Grab};
+
+pub const SIMD_LEVEL: usize = 32;
+
+/// A particle as published to the render thread, and as edited from it.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Particle {
+    // World coordinates, rebased onto the camera before upload
+    pub position: [f64; 2],
+    pub velocity: [f32; 2],
+    pub angle: f32,
+    pub mass: f32,
+    pub species: u8,
+    // Ticks since the particle was spawned
+    pub age: u64,
+    // Contact force per unit circumference from the granular solver; zero otherwise
+    pub pressure: f32,
+}
+
+/// Everything the render thread needs from one simulation tick.
+pub struct Snapshot {
+    pub particles: Vec<Particle>,
+    // Grid over the particles for spatial queries on the render thread
+    pub index: ParticleIndex,
+    // Triangulated rigid bodies in world space
+    pub bodies: Vec<BodyVertex>,
+    // Half extents of the box particles are kept in
+    pub bounds: [f32; 2],
+    pub stats: SimStats,
+    pub speed_histogram: Vec<f32>,
+    pub timeline: Timeline,
+}
+
+/// Position of the published tick within the recorded history.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Timeline {
+    pub tick: u64,
+    pub oldest_tick: u64,
+    pub newest_tick: u64,
+    pub paused: bool,
+    // Ticks held in the history and the memory they take
+    pub recorded: usize,
+    pub history_bytes: usize,
+}
+
+/// Requests sent from the render thread to the simulation thread.
+pub enum SimCommand {
+    Pause,
+    /// Continues from the currently shown tick, discarding any later history.
+    Resume,
+    /// Pauses and shows a recorded tick.
+    Seek(u64),
+    /// Pauses and advances a single tick.
+    Step,
+    SetTimeStep(TimeStep),
+    /// Discards the current run and history and starts over.
+    Reset(SimConfig),
+    AddObstacle(Obstacle),
+    ClearObstacles,
+    SetGravity(f32),
+    SetGranular(GranularSettings),
+    AddBody(RigidBody),
+    ClearBodies,
+    SetSleeping(bool),
+    SetBrush(Option<Brush>),
+    SetGrab(Option<Grab>),
+    MoveGrab {
+        target: [f32; 2],
+        velocity: [f32; 2],
+    },
+    Spawn {
+        center: [f32; 2],
+        radius: f32,
+        count: u32,
+    },
+    Erase {
+        center: [f32; 2],
+        radius: f32,
+    },
+    /// Adds a velocity to the particles around a point.
+    Fling {
+        center: [f32; 2],
+        radius: f32,
+        velocity: [f32; 2],
+    },
+    /// Overwrites one particle's state, e.g. from the inspector.
+    SetParticle(usize, Particle),
+    /// Renders a copy of the current run to a video file in the background.
+    Record(RecordSettings, Arc<RecordStatus>),
+}
+
+/// Smallest spawn spacing; spawn regions are sized from it and must not be empty.
+pub const MIN_SPACING: f32 = 0.01;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SimConfig {
+    pub count: usize,
+    pub preset: Preset,
+    pub seed: u64,
+    // Mean distance between neighbouring particles at spawn
+    pub spacing: f32,
+    // Scale of the initial velocities
+    pub speed: f32,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            count: 1_000,
+            preset: Preset::Grid,
+            seed: 0,
+            spacing: 2.0,
+            speed: 1.0,
+        }
+    }
+}
+
+/// How a tick is split into substeps.
+///
+/// In adaptive mode the substep length follows a CFL-style condition: no
+/// particle may travel more than `cfl` radii per substep.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeStep {
+    pub dt: f32,
+    pub adaptive: bool,
+    pub cfl: f32,
+    pub min_dt: f32,
+    pub max_dt: f32,
+    pub max_substeps: u32,
+}
+
+impl Default for TimeStep {
+    fn default() -> Self {
+        Self {
+            dt: 0.1,
+            adaptive: false,
+            cfl: 0.5,
+            min_dt: 0.001,
+            max_dt: 0.1,
+            max_substeps: 64,
+        }
+    }
+}
+
+impl TimeStep {
+    /// Returns the substep length and count for a tick.
+    pub fn substeps(&self, max_speed: f32, radius: f32) -> (f32, u32) {
+        if !self.adaptive {
+            return (self.dt, 1);
+        }
+        let stable_dt = if max_speed > 0.0 {
+            self.cfl * radius / max_speed
+        } else {
+            self.max_dt
+        };
+        let sub_dt = stable_dt.clamp(self.min_dt, self.max_dt.max(self.min_dt));
+        let count = ((self.dt / sub_dt).ceil() as u32).clamp(1, self.max_substeps.max(1));
+        (self.dt / count as f32, count)
+    }
+}
+
+#[derive(Clone)]
+pub struct Sim {
+    pub count: usize,
+    pub x: Vec<f32x32>,
+    pub y: Vec<f32x32>,
+    pub x_vel: Vec<f32x32>,
+    pub y_vel: Vec<f32x32>,
+    // Only integrated by the granular solver; stays zero otherwise
+    pub angle: Vec<f32x32>,
+    pub angular_velocity: Vec<f32x32>,
+    // Zero in padding lanes
+    pub mass: Vec<f32x32>,
+    // Free-form tag per particle, used for colouring
+    pub species: Vec<u8>,
+    // Tick each particle was spawned on
+    pub born: Vec<u64>,
+    // Written by the granular solver each substep
+    pub pressure: Vec<f32>,
+    pub bounds: [f32; 2],
+    pub gravity: f32,
+    pub radius: f32,
+    pub obstacles: Vec<Obstacle>,
+    pub granular: Granular,
+    pub bodies: RigidWorld,
+    // Mouse tools held on the render thread
+    pub brush: Option<Brush>,
+    pub grab: Option<Grab>,
+    pub tick: u64,
+}
+
+impl Sim {
+    pub fn new(config: &SimConfig) -> Self {
+        let particles = config
+            .preset
+            .generate(config.count, config.spacing, config.speed, config.seed);
+        let count = particles.len();
+
+        let lanes = count.div_ceil(SIMD_LEVEL);
+        let mut x = vec![f32x32::splat(0.0f32); lanes];
+        let mut y = vec![f32x32::splat(0.0f32); lanes];
+        let mut x_vel = vec![f32x32::splat(0.0f32); lanes];
+        let mut y_vel = vec![f32x32::splat(0.0f32); lanes];
+        let mut mass = vec![f32x32::splat(0.0f32); lanes];
+
+        for (index, (position, velocity)) in particles.into_iter().enumerate() {
+            let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+            x[i][j] = position[0];
+            y[i][j] = position[1];
+            x_vel[i][j] = velocity[0];
+            y_vel[i][j] = velocity[1];
+            mass[i][j] = 1.0;
+        }
+
+        Self {
+            count,
+            x,
+            y,
+            x_vel,
+            y_vel,
+            angle: vec![f32x32::splat(0.0f32); lanes],
+            angular_velocity: vec![f32x32::splat(0.0f32); lanes],
+            mass,
+            species: vec![0; count],
+            born: vec![0; count],
+            pressure: vec![0.0; count],
+            bounds: [1_000.0, 1_000.0],
+            gravity: 0.0,
+            radius: 1.0,
+            obstacles: Vec::new(),
+            granular: Granular::default(),
+            bodies: RigidWorld::default(),
+            brush: None,
+            grab: None,
+            tick: 0,
+        }
+    }
+
+    /// Advances one tick, split into substeps by `time_step`.
+    /// Returns the number of boundary collisions, the substep length and the substep count.
+    pub fn advance(&mut self, time_step: &TimeStep) -> (u32, f32, u32) {
+        let (sub_dt, substeps) = time_step.substeps(self.max_speed(), self.radius);
+        let mut collisions = 0;
+        for _ in 0..substeps {
+            collisions += self.integrate(sub_dt);
+        }
+        self.tick += 1;
+        (collisions, sub_dt, substeps)
+    }
+
+    /// Advances the particles by `dt` and returns the number of boundary collisions,
+    /// or of contacts when the granular solver is enabled.
+    fn integrate(&mut self, dt: f32) -> u32 {
+        self.apply_tools(dt);
+        let mut collisions = if self.granular.settings.enabled {
+            self.step_granular(dt)
+        } else {
+            self.pressure.fill(0.0);
+            self.integrate_free(dt)
+        };
+
+        if !self.obstacles.is_empty() {
+            collisions += self.collide_obstacles();
+        }
+        if !self.bodies.is_empty() {
+            self.bodies.step(dt, [0.0, self.gravity], self.bounds);
+            collisions += self.collide_bodies();
+        }
+
+        collisions
+    }
+
+    /// Integrates particles that only interact with the bounds.
+    fn integrate_free(&mut self, dt: f32) -> u32 {
+        let bounds_x_max = f32x32::splat(self.bounds[0]);
+        let bounds_x_min = f32x32::splat(-self.bounds[0]);
+        let bounds_y_max = f32x32::splat(self.bounds[1]);
+        let bounds_y_min = f32x32::splat(-self.bounds[1]);
+        let gravity = f32x32::splat(self.gravity);
+        let dt = f32x32::splat(dt);
+        // 20% energy loss on bounce
+        let bounce_factor = f32x32::splat(-0.8);
+        let mut collisions = 0;
+
+        for i in 0..self.x.len() {
+            self.y_vel[i] += gravity * dt;
+            self.x[i] += self.x_vel[i] * dt;
+            self.y[i] += self.y_vel[i] * dt;
+
+            // Apply boundary constraints with velocity reflection
+            let x_gt_max = self.x[i].simd_gt(bounds_x_max);
+            let x_lt_min = self.x[i].simd_lt(bounds_x_min);
+            let y_gt_max = self.y[i].simd_gt(bounds_y_max);
+            let y_lt_min = self.y[i].simd_lt(bounds_y_min);
+            collisions += (x_gt_max | x_lt_min | y_gt_max | y_lt_min)
+                .to_bitmask()
+                .count_ones();
+
+            // Clamp positions to bounds
+            self.x[i] = self.x[i].simd_min(bounds_x_max).simd_max(bounds_x_min);
+            self.y[i] = self.y[i].simd_min(bounds_y_max).simd_max(bounds_y_min);
+
+            // Reverse velocities at boundaries
+            self.x_vel[i] = self.x_vel[i]
+                * (x_gt_max.select(bounce_factor, f32x32::splat(1.0)))
+                * (x_lt_min.select(bounce_factor, f32x32::splat(1.0)));
+            self.y_vel[i] = self.y_vel[i]
+                * (y_gt_max.select(bounce_factor, f32x32::splat(1.0)))
+                * (y_lt_min.select(bounce_factor, f32x32::splat(1.0)));
+        }
+
+        collisions
+    }
+
+    fn collide_obstacles(&mut self) -> u32 {
+        let mut collisions = 0;
+        for index in 0..self.count {
+            let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+            let mut position = [self.x[i][j], self.y[i][j]];
+            let mut velocity = [self.x_vel[i][j], self.y_vel[i][j]];
+            let mut hit = false;
+            for obstacle in &self.obstacles {
+                hit |= obstacle.collide(&mut position, &mut velocity, self.radius);
+            }
+            if hit {
+                collisions += 1;
+                self.x[i][j] = position[0];
+                self.y[i][j] = position[1];
+                self.x_vel[i][j] = velocity[0];
+                self.y_vel[i][j] = velocity[1];
+            }
+        }
+        collisions
+    }
+
+    fn collide_bodies(&mut self) -> u32 {
+        let mut collisions = 0;
+        for index in 0..self.count {
+            let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+            let mut position = [self.x[i][j], self.y[i][j]];
+            let mut velocity = [self.x_vel[i][j], self.y_vel[i][j]];
+            if self.bodies.collide_particle(&mut position, &mut velocity, self.radius, self.mass[i][j]) {
+                collisions += 1;
+                self.x[i][j] = position[0];
+                self.y[i][j] = position[1];
+                self.x_vel[i][j] = velocity[0];
+                self.y_vel[i][j] = velocity[1];
+            }
+        }
+        collisions
+    }
+
+    pub fn max_speed(&self) -> f32 {
+        let mut speed_squared = f32x32::splat(0.0);
+        for i in 0..self.x.len() {
+            let v2 = self.x_vel[i] * self.x_vel[i] + self.y_vel[i] * self.y_vel[i];
+            speed_squared = speed_squared.simd_max(v2);
+        }
+        speed_squared.reduce_max().sqrt()
+    }
+
+    /// Iterates the speed of every live particle, skipping SIMD padding.
+    pub fn speeds(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..self.count).map(|index| {
+            let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+            let vx = self.x_vel[i][j];
+            let vy = self.y_vel[i][j];
+            (vx * vx + vy * vy).sqrt()
+        })
+    }
+
+    /// Reduces the current state into per-tick statistics. Energy and momentum are mass-weighted.
+    pub fn stats(&self) -> SimStats {
+        let mut speed_squared = f32x32::splat(0.0);
+        let mut momentum_x = f32x32::splat(0.0);
+        let mut momentum_y = f32x32::splat(0.0);
+        let mut speed_sum = f32x32::splat(0.0);
+        let mut speed_max = f32x32::splat(0.0);
+
+        // Padding particles have zero velocity, so they drop out of every sum
+        for i in 0..self.x.len() {
+            let v2 = self.x_vel[i] * self.x_vel[i] + self.y_vel[i] * self.y_vel[i];
+            let speed = v2.sqrt();
+            speed_squared += self.mass[i] * v2;
+            momentum_x += self.mass[i] * self.x_vel[i];
+            momentum_y += self.mass[i] * self.y_vel[i];
+            speed_sum += speed;
+            speed_max = speed_max.simd_max(speed);
+        }
+
+        SimStats {
+            tick: self.tick,
+            kinetic_energy: 0.5 * speed_squared.reduce_sum(),
+            momentum: [momentum_x.reduce_sum(), momentum_y.reduce_sum()],
+            mean_speed: speed_sum.reduce_sum() / self.count.max(1) as f32,
+            max_speed: speed_max.reduce_max(),
+            ..Default::default()
+        }
+    }
+
+    pub fn particle(&self, index: usize) -> Particle {
+        let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+        Particle {
+            position: [self.x[i][j] as f64, self.y[i][j] as f64],
+            velocity: [self.x_vel[i][j], self.y_vel[i][j]],
+            angle: self.angle[i][j],
+            mass: self.mass[i][j],
+            species: self.species[index],
+            age: self.tick - self.born[index],
+            pressure: self.pressure[index],
+        }
+    }
+
+    pub fn particles(&self) -> Vec<Particle> {
+        (0..self.count).map(|index| self.particle(index)).collect()
+    }
+
+    /// Overwrites position, velocity, angle, mass and species of one particle.
+    /// Age and pressure are kept, as they come from the simulation.
+    pub fn set_particle(&mut self, index: usize, particle: &Particle) {
+        if index >= self.count {
+            return;
+        }
+        let (i, j) = (index / SIMD_LEVEL, index % SIMD_LEVEL);
+        self.x[i][j] = particle.position[0] as f32;
+        self.y[i][j] = particle.position[1] as f32;
+        self.x_vel[i][j] = particle.velocity[0];
+        self.y_vel[i][j] = particle.velocity[1];
+        self.angle[i][j] = particle.angle;
+        // Zero mass would drop the particle out of the stats and break contacts
+        self.mass[i][j] = particle.mass.max(1e-3);
+        self.species[index] = particle.species;
+    }
+}
+
+pub fn run(config: SimConfig, mut time_step: TimeStep, sender: Sender<Snapshot>, commands: Receiver<SimCommand>) {
+    const FRAMES: u64 = 60;
+    const HISTORY_TICKS: usize = 600;
+
+    let mut sim = Sim::new(&config);
+    let mut history = StateRing::new(HISTORY_TICKS);
+    history.record(&sim);
+    let mut gravity = 0.0;
+    let mut granular = GranularSettings::default();
+    let mut paused = false;
+
+    loop {
+        let frame_start = Instant::now();
+
+        let mut step_once = false;
+        let mut dirty = false;
+        for command in commands.try_iter() {
+            dirty = true;
+            match command {
+                SimCommand::Pause => paused = true,
+                SimCommand::Resume => {
+                    // Fork from the shown tick
+                    history.truncate_after(sim.tick);
+                    paused = false;
+                }
+                SimCommand::Seek(tick) => {
+                    if let Some(state) = history.get(tick) {
+                        sim.restore(state);
+                    }
+                    paused = true;
+                }
+                SimCommand::Step => {
+                    paused = true;
+                    step_once = true;
+                }
+                SimCommand::SetTimeStep(new_time_step) => time_step = new_time_step,
+                SimCommand::Reset(config) => {
+                    let obstacles = std::mem::take(&mut sim.obstacles);
+                    let bodies = std::mem::take(&mut sim.bodies);
+                    sim = Sim::new(&config);
+                    sim.obstacles = obstacles;
+                    sim.bodies = bodies;
+                    sim.gravity = gravity;
+                    sim.granular.settings = granular;
+                    history = StateRing::new(HISTORY_TICKS);
+                    history.record(&sim);
+                }
+                SimCommand::AddObstacle(obstacle) => sim.obstacles.push(obstacle),
+                SimCommand::ClearObstacles => sim.obstacles.clear(),
+                SimCommand::SetGravity(new_gravity) => {
+                    gravity = new_gravity;
+                    sim.gravity = gravity;
+                }
+                SimCommand::SetGranular(settings) => {
+                    granular = settings;
+                    sim.granular.settings = granular;
+                }
+                SimCommand::AddBody(body) => sim.bodies.add(body),
+                SimCommand::ClearBodies => sim.bodies.clear(),
+                SimCommand::SetSleeping(sleeping) => sim.bodies.sleeping = sleeping,
+                SimCommand::SetBrush(brush) => sim.brush = brush,
+                SimCommand::SetGrab(grab) => {
+                    sim.grab = grab;
+                    sim.apply_grab();
+                }
+                SimCommand::MoveGrab { target, velocity } => {
+                    if let Some(grab) = sim.grab.as_mut() {
+                        grab.target = target;
+                        grab.velocity = velocity;
+                    }
+                    sim.apply_grab();
+                }
+                SimCommand::Spawn { center, radius, count } => sim.spawn_within(center, radius, count),
+                SimCommand::Erase { center, radius } => {
+                    sim.erase_within(center, radius);
+                }
+                SimCommand::Fling { center, radius, velocity } => sim.fling(center, radius, velocity),
+                SimCommand::SetParticle(index, particle) => sim.set_particle(index, &particle),
+                SimCommand::Record(settings, status) => record::spawn(sim.clone(), time_step, settings, status),
+            }
+        }
+
+        let (mut collisions, mut dt, mut substeps) = (0, 0.0, 0);
+        if !paused || step_once {
+            if step_once {
+                history.truncate_after(sim.tick);
+            }
+            (collisions, dt, substeps) = sim.advance(&time_step);
+            history.record(&sim);
+        }
+        let step_time = frame_start.elapsed();
+
+        if !paused || dirty {
+            let mut stats = sim.stats();
+            stats.collisions = collisions;
+            stats.step_time = step_time;
+            stats.dt = dt;
+            stats.substeps = substeps;
+
+            let mut bodies = Vec::new();
+            sim.bodies.tessellate(&mut bodies);
+            let particles = sim.particles();
+            let snapshot = Snapshot {
+                index: ParticleIndex::new(&particles, sim.radius),
+                particles,
+                bodies,
+                bounds: sim.bounds,
+                speed_histogram: speed_histogram(sim.speeds(), stats.max_speed),
+                stats,
+                timeline: Timeline {
+                    tick: sim.tick,
+                    oldest_tick: history.oldest().map_or(sim.tick, |state| state.tick),
+                    newest_tick: history.newest().map_or(sim.tick, |state| state.tick),
+                    paused,
+                    recorded: history.len(),
+                    history_bytes: history.size_in_bytes(),
+                },
+            };
+
+            // Send updated particles to renderer
+            if sender.send(snapshot).is_err() {
+                break; // Exit if receiver is dropped
+            }
+        }
+
+        if !paused && sim.tick % FRAMES == 0 {
+            let elapsed = frame_start.elapsed();
+            println!("{:#?} {:#?}fps", elapsed, 1.0 / elapsed.as_secs_f32());
+        }
+
+        thread::sleep(std::time::Duration::from_millis(16).saturating_sub(frame_start.elapsed())); // ~60 FPS
+    }
+}
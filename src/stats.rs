@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+pub const HISTOGRAM_BINS: usize = 32;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SimStats {
+    pub tick: u64,
+    pub kinetic_energy: f32,
+    pub momentum: [f32; 2],
+    pub mean_speed: f32,
+    pub max_speed: f32,
+    pub collisions: u32,
+    pub step_time: Duration,
+    // Substep length and count chosen for the tick
+    pub dt: f32,
+    pub substeps: u32,
+}
+
+impl SimStats {
+    pub fn momentum_magnitude(&self) -> f32 {
+        (self.momentum[0] * self.momentum[0] + self.momentum[1] * self.momentum[1]).sqrt()
+    }
+}
+
+/// Rolling window of per-tick statistics received from the simulation thread.
+pub struct StatsHistory {
+    pub capacity: usize,
+    pub samples: VecDeque<SimStats>,
+    // Speed distribution of the latest tick, binned from 0 to `max_speed`
+    pub speed_histogram: Vec<f32>,
+}
+
+impl StatsHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            speed_histogram: vec![0.0; HISTOGRAM_BINS],
+        }
+    }
+
+    pub fn push(&mut self, stats: SimStats) {
+        // A tick that isn't newer than the last one means the run forked from
+        // an earlier tick, so the plots drop the abandoned branch
+        while self.samples.back().is_some_and(|last| last.tick >= stats.tick) {
+            self.samples.pop_back();
+        }
+        while self.samples.len() >= self.capacity.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(stats);
+    }
+
+    pub fn latest(&self) -> Option<&SimStats> {
+        self.samples.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Extracts one field of every recorded sample, oldest first, for plotting.
+    pub fn series(&self, field: impl Fn(&SimStats) -> f32) -> Vec<f32> {
+        self.samples.iter().map(field).collect()
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(
+            out,
+            "tick,kinetic_energy,momentum_x,momentum_y,mean_speed,max_speed,collisions,step_time_us,dt,substeps"
+        )?;
+        for s in &self.samples {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{}",
+                s.tick,
+                s.kinetic_energy,
+                s.momentum[0],
+                s.momentum[1],
+                s.mean_speed,
+                s.max_speed,
+                s.collisions,
+                s.step_time.as_micros(),
+                s.dt,
+                s.substeps,
+            )?;
+        }
+        out.flush()
+    }
+}
+
+/// Bins `speeds` into `HISTOGRAM_BINS` buckets covering `0..=max_speed`.
+pub fn speed_histogram(speeds: impl Iterator<Item = f32>, max_speed: f32) -> Vec<f32> {
+    let mut bins = vec![0.0f32; HISTOGRAM_BINS];
+    let scale = if max_speed > 0.0 { HISTOGRAM_BINS as f32 / max_speed } else { 0.0 };
+    for speed in speeds {
+        let bin = ((speed * scale) as usize).min(HISTOGRAM_BINS - 1);
+        bins[bin] += 1.0;
+    }
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(tick: u64) -> SimStats {
+        SimStats {
+            tick,
+            ..SimStats::default()
+        }
+    }
+
+    #[test]
+    fn older_tick_forks_the_history() {
+        let mut history = StatsHistory::new(8);
+        for tick in 0..6 {
+            history.push(sample(tick));
+        }
+        // Resuming from tick 2 replaces everything recorded after it
+        history.push(sample(3));
+        let ticks: Vec<u64> = history.samples.iter().map(|stats| stats.tick).collect();
+        assert_eq!(ticks, [0, 1, 2, 3]);
+        assert_eq!(history.latest().map(|stats| stats.tick), Some(3));
+    }
+}
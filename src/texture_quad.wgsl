@@ -0,0 +1,43 @@
+struct CameraUniform {
+    view_matrix: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+struct QuadRect {
+    min: vec2<f32>,
+    max: vec2<f32>,
+};
+@group(1) @binding(0) var quad_texture: texture_2d<f32>;
+@group(1) @binding(1) var quad_sampler: sampler;
+@group(1) @binding(2) var<uniform> rect: QuadRect;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // Two triangles covering the unit square
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let world_pos = mix(rect.min, rect.max, corner);
+
+    var output: VertexOutput;
+    output.position = camera.view_matrix * vec4<f32>(world_pos, 0.0, 1.0);
+    // Texture rows are uploaded top row first
+    output.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return output;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(quad_texture, quad_sampler, in.uv);
+}
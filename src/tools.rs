@@ -0,0 +1,408 @@
+use std::simd::*;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use crate::query::ParticleIndex;
+use crate::sim::{Sim, SimCommand, SIMD_LEVEL};
+
+/// What a left-drag over the world does.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tool {
+    Pan,
+    Select,
+    Grab,
+    Attract,
+    Repel,
+    Spawn,
+    Erase,
+    Fling,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 8] = [
+        Tool::Pan,
+        Tool::Select,
+        Tool::Grab,
+        Tool::Attract,
+        Tool::Repel,
+        Tool::Spawn,
+        Tool::Erase,
+        Tool::Fling,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Pan => "Pan",
+            Tool::Select => "Select",
+            Tool::Grab => "Grab",
+            Tool::Attract => "Attract",
+            Tool::Repel => "Repel",
+            Tool::Spawn => "Spawn",
+            Tool::Erase => "Erase",
+            Tool::Fling => "Fling",
+        }
+    }
+
+    /// Whether the tool acts on an area around the cursor.
+    pub fn uses_radius(self) -> bool {
+        !matches!(self, Tool::Pan | Tool::Select)
+    }
+}
+
+/// Radial acceleration around a point; positive strength pulls inwards.
+#[derive(Copy, Clone, Debug)]
+pub struct Brush {
+    pub center: [f32; 2],
+    pub radius: f32,
+    pub strength: f32,
+}
+
+/// Particles pinned to the cursor while it drags them.
+#[derive(Clone, Debug)]
+pub struct Grab {
+    pub indices: Vec<usize>,
+    // Offsets from the cursor at the moment of grabbing
+    pub offsets: Vec<[f32; 2]>,
+    pub target: [f32; 2],
+    pub velocity: [f32; 2],
+}
+
+/// Particles picked with the select tool, as indices into the latest snapshot.
+#[derive(Default)]
+pub struct Selection {
+    pub indices: Vec<usize>,
+    // World-space corners of a box drag in progress
+    pub dragging: Option<([f32; 2], [f32; 2])>,
+}
+
+/// A tool drag in progress.
+struct Gesture {
+    start: [f32; 2],
+    last: [f32; 2],
+    last_time: Instant,
+}
+
+pub struct Tools {
+    pub tool: Tool,
+    pub radius: f32,
+    pub strength: f32,
+    // Particles added per frame while the spawn brush is held
+    pub spawn_rate: u32,
+    // Velocity given per world unit of fling drag
+    pub fling_scale: f32,
+    pub selection: Selection,
+    gesture: Option<Gesture>,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Self {
+            tool: Tool::Pan,
+            radius: 20.0,
+            strength: 50.0,
+            spawn_rate: 5,
+            fling_scale: 2.0,
+            selection: Selection::default(),
+            gesture: None,
+        }
+    }
+}
+
+impl Tools {
+    fn brush(&self, center: [f32; 2]) -> Option<Brush> {
+        let strength = match self.tool {
+            Tool::Attract => self.strength,
+            Tool::Repel => -self.strength,
+            _ => return None,
+        };
+        Some(Brush {
+            center,
+            radius: self.radius,
+            strength,
+        })
+    }
+
+    pub fn press(&mut self, world: [f32; 2], particles: &ParticleIndex, commands: &Sender<SimCommand>) {
+        if self.tool == Tool::Pan {
+            return;
+        }
+        self.gesture = Some(Gesture {
+            start: world,
+            last: world,
+            last_time: Instant::now(),
+        });
+
+        match self.tool {
+            Tool::Select => {
+                // A click picks the particle under the cursor; dragging turns it into a box
+                self.selection.indices = particles
+                    .nearest(world, particles.radius)
+                    .map(|hit| vec![hit.index])
+                    .unwrap_or_default();
+            }
+            Tool::Grab => {
+                let hits = particles.within_radius(world, self.radius);
+                if !hits.is_empty() {
+                    let grab = Grab {
+                        indices: hits.iter().map(|hit| hit.index).collect(),
+                        offsets: hits
+                            .iter()
+                            .map(|hit| [hit.position[0] - world[0], hit.position[1] - world[1]])
+                            .collect(),
+                        target: world,
+                        velocity: [0.0, 0.0],
+                    };
+                    let _ = commands.send(SimCommand::SetGrab(Some(grab)));
+                }
+            }
+            Tool::Attract | Tool::Repel => {
+                let _ = commands.send(SimCommand::SetBrush(self.brush(world)));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn drag(&mut self, world: [f32; 2], particles: &ParticleIndex, commands: &Sender<SimCommand>) {
+        let Some(gesture) = self.gesture.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed = (now - gesture.last_time).as_secs_f32().max(1e-3);
+        let velocity = [
+            (world[0] - gesture.last[0]) / elapsed,
+            (world[1] - gesture.last[1]) / elapsed,
+        ];
+        gesture.last = world;
+        gesture.last_time = now;
+
+        match self.tool {
+            Tool::Select => {
+                // Small jitters during a click shouldn't drop the picked particle
+                let moved = [world[0] - gesture.start[0], world[1] - gesture.start[1]];
+                if self.selection.dragging.is_none() && moved[0].hypot(moved[1]) < particles.radius {
+                    return;
+                }
+                let corners = (gesture.start, world);
+                self.selection.indices = particles.in_aabb(corners.0, corners.1);
+                self.selection.dragging = Some(corners);
+            }
+            Tool::Grab => {
+                let _ = commands.send(SimCommand::MoveGrab {
+                    target: world,
+                    velocity,
+                });
+            }
+            Tool::Attract | Tool::Repel => {
+                let _ = commands.send(SimCommand::SetBrush(self.brush(world)));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn release(&mut self, commands: &Sender<SimCommand>) {
+        let Some(gesture) = self.gesture.take() else {
+            return;
+        };
+        match self.tool {
+            Tool::Select => {
+                self.selection.dragging = None;
+            }
+            Tool::Grab => {
+                let _ = commands.send(SimCommand::SetGrab(None));
+            }
+            Tool::Attract | Tool::Repel => {
+                let _ = commands.send(SimCommand::SetBrush(None));
+            }
+            Tool::Fling => {
+                let velocity = [
+                    (gesture.last[0] - gesture.start[0]) * self.fling_scale,
+                    (gesture.last[1] - gesture.start[1]) * self.fling_scale,
+                ];
+                let _ = commands.send(SimCommand::Fling {
+                    center: gesture.start,
+                    radius: self.radius,
+                    velocity,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Continuous tools act once per frame while held.
+    pub fn frame(&mut self, commands: &Sender<SimCommand>) {
+        let Some(gesture) = self.gesture.as_ref() else {
+            return;
+        };
+        match self.tool {
+            Tool::Spawn => {
+                let _ = commands.send(SimCommand::Spawn {
+                    center: gesture.last,
+                    radius: self.radius,
+                    count: self.spawn_rate,
+                });
+            }
+            Tool::Erase => {
+                let _ = commands.send(SimCommand::Erase {
+                    center: gesture.last,
+                    radius: self.radius,
+                });
+                // Erasing reorders particles, so the selected indices no longer hold
+                self.selection.indices.clear();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Sim {
+    fn lane(index: usize) -> (usize, usize) {
+        (index / SIMD_LEVEL, index % SIMD_LEVEL)
+    }
+
+    pub fn spawn(&mut self, position: [f32; 2], velocity: [f32; 2]) {
+        let (i, j) = Self::lane(self.count);
+        if i == self.x.len() {
+            for lanes in [
+                &mut self.x,
+                &mut self.y,
+                &mut self.x_vel,
+                &mut self.y_vel,
+                &mut self.angle,
+                &mut self.angular_velocity,
+                &mut self.mass,
+            ] {
+                lanes.push(f32x32::splat(0.0));
+            }
+        }
+        self.x[i][j] = position[0];
+        self.y[i][j] = position[1];
+        self.x_vel[i][j] = velocity[0];
+        self.y_vel[i][j] = velocity[1];
+        self.angle[i][j] = 0.0;
+        self.angular_velocity[i][j] = 0.0;
+        self.mass[i][j] = 1.0;
+        self.species.push(0);
+        self.born.push(self.tick);
+        self.pressure.push(0.0);
+        self.count += 1;
+    }
+
+    /// Adds `count` resting particles at random spots within `radius` of `center`.
+    pub fn spawn_within(&mut self, center: [f32; 2], radius: f32, count: u32) {
+        for _ in 0..count {
+            let angle = rand::random::<f32>() * std::f32::consts::TAU;
+            let distance = radius * rand::random::<f32>().sqrt();
+            let position = [center[0] + distance * angle.cos(), center[1] + distance * angle.sin()];
+            self.spawn(position, [0.0, 0.0]);
+        }
+    }
+
+    /// Removes a particle by moving the last one into its slot.
+    fn swap_remove(&mut self, index: usize) {
+        let last = self.count - 1;
+        let (i, j) = Self::lane(index);
+        let (li, lj) = Self::lane(last);
+        for lanes in [
+            &mut self.x,
+            &mut self.y,
+            &mut self.x_vel,
+            &mut self.y_vel,
+            &mut self.angle,
+            &mut self.angular_velocity,
+            &mut self.mass,
+        ] {
+            lanes[i][j] = lanes[li][lj];
+            // Padding must stay zero so it drops out of the stats
+            lanes[li][lj] = 0.0;
+        }
+        self.species.swap_remove(index);
+        self.born.swap_remove(index);
+        self.pressure.swap_remove(index);
+        self.count = last;
+        if lj == 0 {
+            for lanes in [
+                &mut self.x,
+                &mut self.y,
+                &mut self.x_vel,
+                &mut self.y_vel,
+                &mut self.angle,
+                &mut self.angular_velocity,
+                &mut self.mass,
+            ] {
+                lanes.pop();
+            }
+        }
+    }
+
+    /// Removes every particle within `radius` of `center`. Returns how many were removed.
+    pub fn erase_within(&mut self, center: [f32; 2], radius: f32) -> usize {
+        let mut removed = 0;
+        let mut index = 0;
+        while index < self.count {
+            let (i, j) = Self::lane(index);
+            let d = [self.x[i][j] - center[0], self.y[i][j] - center[1]];
+            if d[0] * d[0] + d[1] * d[1] <= radius * radius {
+                // The swapped-in particle is checked on the next pass through this index
+                self.swap_remove(index);
+                removed += 1;
+            } else {
+                index += 1;
+            }
+        }
+        // Indices have shifted under any grab in progress and any warm-started contact
+        if removed > 0 {
+            self.grab = None;
+            self.granular.forget_contacts();
+        }
+        removed
+    }
+
+    /// Adds `velocity` to every particle within `radius` of `center`.
+    pub fn fling(&mut self, center: [f32; 2], radius: f32, velocity: [f32; 2]) {
+        for index in 0..self.count {
+            let (i, j) = Self::lane(index);
+            let d = [self.x[i][j] - center[0], self.y[i][j] - center[1]];
+            if d[0] * d[0] + d[1] * d[1] <= radius * radius {
+                self.x_vel[i][j] += velocity[0];
+                self.y_vel[i][j] += velocity[1];
+            }
+        }
+    }
+
+    /// Moves grabbed particles onto the cursor.
+    pub fn apply_grab(&mut self) {
+        let Some(grab) = self.grab.as_ref() else {
+            return;
+        };
+        for (&index, offset) in grab.indices.iter().zip(&grab.offsets) {
+            if index >= self.count {
+                continue;
+            }
+            let (i, j) = Self::lane(index);
+            self.x[i][j] = grab.target[0] + offset[0];
+            self.y[i][j] = grab.target[1] + offset[1];
+            self.x_vel[i][j] = grab.velocity[0];
+            self.y_vel[i][j] = grab.velocity[1];
+        }
+    }
+
+    /// Applies the active brush and grab for one substep.
+    pub fn apply_tools(&mut self, dt: f32) {
+        if let Some(brush) = self.brush {
+            for index in 0..self.count {
+                let (i, j) = Self::lane(index);
+                let d = [brush.center[0] - self.x[i][j], brush.center[1] - self.y[i][j]];
+                let distance = (d[0] * d[0] + d[1] * d[1]).sqrt();
+                if distance >= brush.radius || distance == 0.0 {
+                    continue;
+                }
+                // Strongest at the centre, fading to zero at the rim
+                let falloff = 1.0 - distance / brush.radius;
+                let acceleration = brush.strength * falloff / distance;
+                self.x_vel[i][j] += d[0] * acceleration * dt;
+                self.y_vel[i][j] += d[1] * acceleration * dt;
+            }
+        }
+        self.apply_grab();
+    }
+}
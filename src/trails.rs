@@ -0,0 +1,288 @@
+use std::borrow::Cow;
+use wgpu::ShaderSource;
+
+/// Format every scene pipeline draws in. Half floats keep faded trails from
+/// getting stuck a step above the fade colour, as they would in 8 bits.
+pub const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Trails count as gone once faded to this fraction of their brightness
+const FADED: f32 = 0.01;
+
+/// How particles are drawn over the faded history.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TrailBlend {
+    Alpha,
+    // Overlapping trails add up and brighten
+    Additive,
+}
+
+impl TrailBlend {
+    pub const ALL: [TrailBlend; 2] = [TrailBlend::Alpha, TrailBlend::Additive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TrailBlend::Alpha => "Alpha",
+            TrailBlend::Additive => "Additive",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrailSettings {
+    pub enabled: bool,
+    // Frames until a trail has faded out
+    pub length: f32,
+    pub fade_color: [f32; 3],
+    pub blend: TrailBlend,
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: 30.0,
+            fade_color: [0.0; 3],
+            blend: TrailBlend::Alpha,
+        }
+    }
+}
+
+impl TrailSettings {
+    /// Fraction of the previous frame that survives each frame.
+    pub fn retention(&self) -> f32 {
+        FADED.powf(1.0 / self.length.max(1.0))
+    }
+}
+
+/// Keeps the scene between frames so trails can build up in it. Each frame the
+/// history is faded instead of cleared, the scene drawn over it, and the result
+/// composited onto the output.
+pub struct AccumulationBuffer {
+    // Sized to the window
+    history: wgpu::TextureView,
+    fade_buffer: wgpu::Buffer,
+    fade_bind_group: wgpu::BindGroup,
+    fade_pipeline: wgpu::RenderPipeline,
+    composite_layout: wgpu::BindGroupLayout,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl AccumulationBuffer {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let fade_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Trail Fade Buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let fade_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail Fade Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let fade_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Trail Fade Bind Group"),
+            layout: &fade_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: fade_buffer.as_entire_binding(),
+            }],
+        });
+        let fade_pipeline = create_fullscreen_pipeline(
+            device,
+            "Trail Fade",
+            include_str!("trails.wgsl"),
+            &fade_layout,
+            SCENE_FORMAT,
+            wgpu::BlendState::ALPHA_BLENDING,
+        );
+
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Composite Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let composite_pipeline = create_fullscreen_pipeline(
+            device,
+            "Composite",
+            include_str!("composite.wgsl"),
+            &composite_layout,
+            config.format,
+            wgpu::BlendState::REPLACE,
+        );
+
+        Self {
+            history: create_history(device, config.width, config.height),
+            fade_buffer,
+            fade_bind_group,
+            fade_pipeline,
+            composite_layout,
+            composite_pipeline,
+        }
+    }
+
+    /// The scene target that persists between frames.
+    pub fn history(&self) -> &wgpu::TextureView {
+        &self.history
+    }
+
+    /// Matches the history to a new window size; what was in it is dropped.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.history = create_history(device, width, height);
+    }
+
+    pub fn set_fade(&self, queue: &wgpu::Queue, settings: &TrailSettings) {
+        let [r, g, b] = settings.fade_color;
+        let cover = 1.0 - settings.retention();
+        queue.write_buffer(&self.fade_buffer, 0, bytemuck::cast_slice(&[r, g, b, cover]));
+    }
+
+    /// Starts the scene pass on `target`, clearing it to black, or fading what
+    /// is already there towards the fade colour when `fade` is set.
+    pub fn begin_scene_pass<'encoder>(
+        &self,
+        encoder: &'encoder mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        fade: bool,
+    ) -> wgpu::RenderPass<'encoder> {
+        let load = if fade {
+            wgpu::LoadOp::Load
+        } else {
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Scene Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        if fade {
+            pass.set_pipeline(&self.fade_pipeline);
+            pass.set_bind_group(0, &self.fade_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        pass
+    }
+
+    /// Copies `source` onto `view`, which must be the same size.
+    pub fn composite(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        view: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Composite Bind Group"),
+            layout: &self.composite_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            }],
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Composite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&self.composite_pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_history(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Scene History"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: SCENE_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// A pipeline drawing one screen-covering triangle with a single bind group
+fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    source: &'static str,
+    layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(Cow::Borrowed(source)),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
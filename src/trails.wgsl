@@ -0,0 +1,17 @@
+struct Fade {
+    // Colour the history fades towards, and how much of it is covered per frame
+    color: vec4<f32>,
+};
+@group(0) @binding(0) var<uniform> fade: Fade;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the screen
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return fade.color;
+}
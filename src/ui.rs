@@ -0,0 +1,1106 @@
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use crate::automaton::{AutomatonLayer, Pattern, Rule};
+use crate::bindings::{self, MouseAction, MouseBindings, BUTTONS};
+use crate::colormap::{self, ColorAttribute, Coloring, Colormap};
+use crate::Camera;
+use crate::fluid::{FluidConfig, FluidView};
+use crate::granular::GranularSettings;
+use crate::heatmap::{Lod, LodMode};
+use crate::obstacle::{MaskChannel, Obstacle};
+use crate::post::{PostEffect, PostPass, PostSettings, Tonemapper, MAX_PASSES};
+use crate::presets::Preset;
+use crate::render_graph::PassTiming;
+use crate::record::{RecordSettings, RecordStatus};
+use crate::rigid::{RigidBody, Shape};
+use crate::screenshot::ScreenshotSettings;
+use crate::sim::{Particle, SimCommand, SimConfig, Timeline, TimeStep, MIN_SPACING};
+use crate::stats::{SimStats, StatsHistory};
+use crate::tools::{Selection, Tool, Tools};
+use crate::trails::{TrailBlend, TrailSettings};
+use crate::wgpu_ctx::{ParticleShape, ParticleStyle};
+use crate::world_grid::{GridSettings, GridSpacing};
+
+const PLOT_HEIGHT: f32 = 50.0;
+
+pub fn stats_window(ui: &imgui::Ui, history: &mut StatsHistory) {
+    ui.window("Statistics")
+        .size([360.0, 520.0], imgui::Condition::FirstUseEver)
+        .position([10.0, 220.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let Some(latest) = history.latest().copied() else {
+                ui.text("Waiting for simulation...");
+                return;
+            };
+
+            ui.text(format!("Tick: {}", latest.tick));
+            ui.text(format!(
+                "Step time: {:.3} ms",
+                latest.step_time.as_secs_f64() * 1000.0
+            ));
+            ui.text(format!("dt: {:.5} x {} substeps", latest.dt, latest.substeps));
+
+            plot(ui, history, "Kinetic energy", latest.kinetic_energy, |s| s.kinetic_energy);
+            plot(ui, history, "Momentum", latest.momentum_magnitude(), |s| s.momentum_magnitude());
+            plot(ui, history, "Mean speed", latest.mean_speed, |s| s.mean_speed);
+            plot(ui, history, "Max speed", latest.max_speed, |s| s.max_speed);
+            plot(ui, history, "Collisions", latest.collisions as f32, |s| s.collisions as f32);
+            plot(ui, history, "Substeps", latest.substeps as f32, |s| s.substeps as f32);
+            plot(ui, history, "Step time (ms)", latest.step_time.as_secs_f32() * 1000.0, |s| {
+                s.step_time.as_secs_f32() * 1000.0
+            });
+
+            ui.separator();
+            ui.text(format!("Speed distribution (0 - {:.2})", latest.max_speed));
+            ui.plot_histogram("##speed_histogram", &history.speed_histogram)
+                .graph_size([0.0, PLOT_HEIGHT * 1.5])
+                .build();
+
+            ui.separator();
+            let mut capacity = history.capacity as i32;
+            if ui.input_int("History length", &mut capacity).build() {
+                history.capacity = capacity.max(1) as usize;
+            }
+            if ui.button("Clear") {
+                history.clear();
+            }
+            ui.same_line();
+            if ui.button("Export CSV") {
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                let path = format!("stats_{}.csv", timestamp);
+                match history.write_csv(&path) {
+                    Ok(()) => println!("Wrote {} samples to {}", history.samples.len(), path),
+                    Err(err) => eprintln!("Failed to write {}: {}", path, err),
+                }
+            }
+        });
+}
+
+fn plot(
+    ui: &imgui::Ui,
+    history: &StatsHistory,
+    label: &str,
+    latest: f32,
+    field: impl Fn(&SimStats) -> f32,
+) {
+    let values = history.series(field);
+    ui.plot_lines(format!("##{}", label), &values)
+        .overlay_text(format!("{}: {:.3}", label, latest))
+        .graph_size([0.0, PLOT_HEIGHT])
+        .build();
+}
+
+pub fn timeline_window(ui: &imgui::Ui, timeline: &Timeline, commands: &Sender<SimCommand>) {
+    ui.window("Timeline")
+        .size([360.0, 110.0], imgui::Condition::FirstUseEver)
+        .position([380.0, 10.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            // Send errors only happen once the sim thread has exited
+            if timeline.paused {
+                if ui.button("Resume") {
+                    let _ = commands.send(SimCommand::Resume);
+                }
+            } else if ui.button("Pause") {
+                let _ = commands.send(SimCommand::Pause);
+            }
+            ui.same_line();
+            if ui.button("Step") {
+                let _ = commands.send(SimCommand::Step);
+            }
+            ui.same_line();
+            ui.text(format!(
+                "{} ticks recorded ({:.1} MB)",
+                timeline.recorded,
+                timeline.history_bytes as f64 / (1024.0 * 1024.0)
+            ));
+
+            let mut tick = timeline.tick;
+            ui.set_next_item_width(-1.0);
+            if ui.slider("##tick", timeline.oldest_tick, timeline.newest_tick, &mut tick) {
+                let _ = commands.send(SimCommand::Seek(tick));
+            }
+            if timeline.paused && timeline.tick < timeline.newest_tick {
+                ui.text_disabled("Resuming forks from this tick and drops later history");
+            }
+        });
+}
+
+pub fn time_step_window(ui: &imgui::Ui, time_step: &mut TimeStep, commands: &Sender<SimCommand>) {
+    ui.window("Time step")
+        .size([360.0, 170.0], imgui::Condition::FirstUseEver)
+        .position([380.0, 130.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let mut changed = false;
+            changed |= ui
+                .input_float("Tick dt", &mut time_step.dt)
+                .step(0.01)
+                .build();
+            changed |= ui.checkbox("Adaptive (CFL)", &mut time_step.adaptive);
+            if time_step.adaptive {
+                changed |= ui.slider("CFL", 0.05, 2.0, &mut time_step.cfl);
+                changed |= ui.input_float("Min dt", &mut time_step.min_dt).build();
+                changed |= ui.input_float("Max dt", &mut time_step.max_dt).build();
+                changed |= ui.slider("Max substeps", 1, 256, &mut time_step.max_substeps);
+            }
+            if changed {
+                time_step.dt = time_step.dt.max(0.0);
+                time_step.min_dt = time_step.min_dt.max(1e-6);
+                let _ = commands.send(SimCommand::SetTimeStep(*time_step));
+            }
+        });
+}
+
+#[derive(Default)]
+pub struct PhysicsSettings {
+    pub gravity: f32,
+    pub granular: GranularSettings,
+}
+
+pub fn physics_window(ui: &imgui::Ui, settings: &mut PhysicsSettings, commands: &Sender<SimCommand>) {
+    ui.window("Physics")
+        .size([360.0, 200.0], imgui::Condition::FirstUseEver)
+        .position([10.0, 560.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            if ui.slider("Gravity", -50.0, 50.0, &mut settings.gravity) {
+                let _ = commands.send(SimCommand::SetGravity(settings.gravity));
+            }
+
+            let granular = &mut settings.granular;
+            let mut changed = ui.checkbox("Granular contacts", &mut granular.enabled);
+            if granular.enabled {
+                changed |= ui.slider("Friction", 0.0, 2.0, &mut granular.friction);
+                changed |= ui.slider("Rolling resistance", 0.0, 1.0, &mut granular.rolling_resistance);
+                changed |= ui.slider("Restitution", 0.0, 1.0, &mut granular.restitution);
+                changed |= ui.slider("Iterations", 1, 50, &mut granular.iterations);
+            }
+            if changed {
+                let _ = commands.send(SimCommand::SetGranular(*granular));
+            }
+        });
+}
+
+/// Returns true when a reset was requested.
+pub fn initial_conditions_window(
+    ui: &imgui::Ui,
+    config: &mut SimConfig,
+    commands: &Sender<SimCommand>,
+) -> bool {
+    let mut reset = false;
+    ui.window("Initial conditions")
+        .size([360.0, 190.0], imgui::Condition::FirstUseEver)
+        .position([380.0, 310.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let names = Preset::ALL.map(|preset| preset.name());
+            let mut selected = Preset::ALL
+                .iter()
+                .position(|preset| *preset == config.preset)
+                .unwrap_or(0);
+            if ui.combo_simple_string("Preset", &mut selected, &names) {
+                config.preset = Preset::ALL[selected];
+            }
+
+            let mut count = config.count as i32;
+            if ui.input_int("Count", &mut count).step(100).build() {
+                config.count = count.max(1) as usize;
+            }
+            let mut seed = config.seed as i32;
+            if ui.input_int("Seed", &mut seed).build() {
+                config.seed = seed.max(0) as u64;
+            }
+            if ui.input_float("Spacing", &mut config.spacing).build() {
+                config.spacing = config.spacing.max(MIN_SPACING);
+            }
+            ui.input_float("Speed", &mut config.speed).build();
+
+            if ui.button("Reset") {
+                let _ = commands.send(SimCommand::Reset(*config));
+                reset = true;
+            }
+            ui.same_line();
+            if ui.button("Random seed") {
+                config.seed = rand::random::<u32>() as u64;
+            }
+        });
+    reset
+}
+
+/// Settings for placing an image mask as an obstacle.
+pub struct ObstacleLoader {
+    pub path: String,
+    pub channel: MaskChannel,
+    pub threshold: u8,
+    pub origin: [f32; 2],
+    pub world_width: f32,
+    pub status: String,
+    // Obstacles sent to the simulation, kept here so they can be drawn
+    pub added: Vec<Obstacle>,
+    pub rebuild: bool,
+}
+
+impl Default for ObstacleLoader {
+    fn default() -> Self {
+        Self {
+            path: "resources/checker.png".to_string(),
+            channel: MaskChannel::Luminance,
+            threshold: 128,
+            origin: [-50.0, -50.0],
+            world_width: 100.0,
+            status: String::new(),
+            added: Vec::new(),
+            rebuild: false,
+        }
+    }
+}
+
+pub fn obstacle_window(ui: &imgui::Ui, loader: &mut ObstacleLoader, commands: &Sender<SimCommand>) {
+    ui.window("Obstacles")
+        .size([360.0, 200.0], imgui::Condition::FirstUseEver)
+        .position([380.0, 510.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            ui.input_text("Mask PNG", &mut loader.path).build();
+            let mut alpha = loader.channel == MaskChannel::Alpha;
+            if ui.checkbox("Solid where opaque (else where dark)", &mut alpha) {
+                loader.channel = if alpha { MaskChannel::Alpha } else { MaskChannel::Luminance };
+            }
+            ui.slider("Threshold", 0, 255, &mut loader.threshold);
+            ui.input_float2("Origin", &mut loader.origin).build();
+            ui.input_float("World width", &mut loader.world_width).build();
+
+            if ui.button("Add") {
+                match Obstacle::from_image(
+                    &loader.path,
+                    loader.channel,
+                    loader.threshold,
+                    loader.origin,
+                    loader.world_width.max(f32::EPSILON),
+                ) {
+                    Ok(obstacle) => {
+                        loader.status = format!("Added {}x{} mask", obstacle.width, obstacle.height);
+                        loader.added.push(obstacle.clone());
+                        loader.rebuild = true;
+                        let _ = commands.send(SimCommand::AddObstacle(obstacle));
+                    }
+                    Err(err) => loader.status = format!("Failed to load {}: {}", loader.path, err),
+                }
+            }
+            ui.same_line();
+            if ui.button("Clear") {
+                let _ = commands.send(SimCommand::ClearObstacles);
+                loader.status.clear();
+                loader.added.clear();
+                loader.rebuild = true;
+            }
+            if !loader.status.is_empty() {
+                ui.text_wrapped(&loader.status);
+            }
+        });
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum BodyShape {
+    Box,
+    Circle,
+    Polygon,
+}
+
+impl BodyShape {
+    pub const ALL: [BodyShape; 3] = [BodyShape::Box, BodyShape::Circle, BodyShape::Polygon];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BodyShape::Box => "Box",
+            BodyShape::Circle => "Circle",
+            BodyShape::Polygon => "Random polygon",
+        }
+    }
+}
+
+/// Settings for spawning rigid bodies.
+pub struct BodySpawner {
+    pub shape: BodyShape,
+    pub size: f32,
+    pub position: [f32; 2],
+    pub density: f32,
+    pub friction: f32,
+    pub restitution: f32,
+    pub fixed: bool,
+    pub sleeping: bool,
+}
+
+impl Default for BodySpawner {
+    fn default() -> Self {
+        Self {
+            shape: BodyShape::Box,
+            size: 10.0,
+            position: [0.0, 50.0],
+            density: 0.1,
+            friction: 0.6,
+            restitution: 0.1,
+            fixed: false,
+            sleeping: true,
+        }
+    }
+}
+
+impl BodySpawner {
+    fn build(&self) -> RigidBody {
+        let half = self.size * 0.5;
+        let shape = match self.shape {
+            BodyShape::Box => Shape::rect(half, half),
+            BodyShape::Circle => Shape::circle(half),
+            BodyShape::Polygon => {
+                let points: Vec<[f32; 2]> = (0..8)
+                    .map(|_| {
+                        let angle = rand::random::<f32>() * std::f32::consts::TAU;
+                        let radius = half * (0.5 + 0.5 * rand::random::<f32>());
+                        [radius * angle.cos(), radius * angle.sin()]
+                    })
+                    .collect();
+                Shape::polygon(&points).unwrap_or_else(|| Shape::rect(half, half))
+            }
+        };
+        let mut body = if self.fixed {
+            RigidBody::fixed(shape, self.position)
+        } else {
+            RigidBody::new(shape, self.position, self.density.max(f32::EPSILON))
+        };
+        body.friction = self.friction;
+        body.restitution = self.restitution;
+        body
+    }
+}
+
+pub fn rigid_body_window(ui: &imgui::Ui, spawner: &mut BodySpawner, commands: &Sender<SimCommand>) {
+    ui.window("Rigid bodies")
+        .size([360.0, 260.0], imgui::Condition::FirstUseEver)
+        .position([1120.0, 10.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let names = BodyShape::ALL.map(|shape| shape.name());
+            let mut selected = BodyShape::ALL
+                .iter()
+                .position(|shape| *shape == spawner.shape)
+                .unwrap_or(0);
+            if ui.combo_simple_string("Shape", &mut selected, &names) {
+                spawner.shape = BodyShape::ALL[selected];
+            }
+            ui.slider("Size", 1.0, 100.0, &mut spawner.size);
+            ui.input_float2("Position", &mut spawner.position).build();
+            ui.slider("Density", 0.01, 2.0, &mut spawner.density);
+            ui.slider("Friction", 0.0, 1.5, &mut spawner.friction);
+            ui.slider("Restitution", 0.0, 1.0, &mut spawner.restitution);
+            ui.checkbox("Static", &mut spawner.fixed);
+
+            if ui.button("Spawn") {
+                let _ = commands.send(SimCommand::AddBody(spawner.build()));
+            }
+            ui.same_line();
+            if ui.button("Clear") {
+                let _ = commands.send(SimCommand::ClearBodies);
+            }
+            if ui.checkbox("Sleeping", &mut spawner.sleeping) {
+                let _ = commands.send(SimCommand::SetSleeping(spawner.sleeping));
+            }
+        });
+}
+
+pub struct FluidSettings {
+    pub enabled: bool,
+    pub paused: bool,
+    pub config: FluidConfig,
+    pub view: FluidView,
+    // Set when the grid size or placement changed and the fluid must be recreated
+    pub rebuild: bool,
+    pub force: f32,
+    pub splat_radius: f32,
+    pub dye_color: [f32; 3],
+}
+
+impl Default for FluidSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paused: false,
+            config: FluidConfig::default(),
+            view: FluidView::Dye,
+            rebuild: false,
+            force: 1.0,
+            splat_radius: 40.0,
+            dye_color: [1.0, 0.4, 0.1],
+        }
+    }
+}
+
+pub fn fluid_window(ui: &imgui::Ui, settings: &mut FluidSettings, bindings: &MouseBindings) {
+    ui.window("Fluid")
+        .size([360.0, 330.0], imgui::Condition::FirstUseEver)
+        .position([750.0, 10.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            ui.checkbox("Enabled", &mut settings.enabled);
+            ui.same_line();
+            ui.checkbox("Paused", &mut settings.paused);
+            ui.text_disabled(format!("{}-drag to stir", bindings::button_name(bindings.button(MouseAction::Stir))));
+
+            let mut show_velocity = settings.view == FluidView::Velocity;
+            if ui.checkbox("Show velocity", &mut show_velocity) {
+                settings.view = if show_velocity { FluidView::Velocity } else { FluidView::Dye };
+            }
+
+            let config = &mut settings.config;
+            ui.slider("Vorticity", 0.0, 50.0, &mut config.vorticity);
+            ui.slider("Velocity kept/s", 0.5, 1.0, &mut config.velocity_dissipation);
+            ui.slider("Dye kept/s", 0.5, 1.0, &mut config.dye_dissipation);
+            ui.slider("Pressure iterations", 1, 100, &mut config.pressure_iterations);
+            ui.slider("Force", 0.0, 5.0, &mut settings.force);
+            ui.input_float("Splat radius", &mut settings.splat_radius).build();
+            ui.color_edit3("Dye", &mut settings.dye_color);
+
+            ui.separator();
+            let mut size = [config.width as i32, config.height as i32];
+            let mut changed = ui.input_int2("Grid size", &mut size).build();
+            changed |= ui.input_float2("Origin", &mut config.origin).build();
+            changed |= ui.input_float("Cell size", &mut config.cell_size).build();
+            config.width = size[0].clamp(8, 2048) as usize;
+            config.height = size[1].clamp(8, 2048) as usize;
+            config.cell_size = config.cell_size.max(f32::EPSILON);
+            if ui.button("Reset fluid") || changed {
+                settings.rebuild = true;
+            }
+        });
+}
+
+pub struct AutomatonSettings {
+    pub enabled: bool,
+    pub running: bool,
+    pub rule_text: String,
+    pub size: [i32; 2],
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    pub steps_per_frame: u32,
+    pub density: f32,
+    pub path: String,
+    pub status: String,
+    // Set when the grid size or placement changed and the automaton must be recreated
+    pub rebuild: bool,
+}
+
+impl Default for AutomatonSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            running: false,
+            rule_text: Rule::LIFE.to_string(),
+            size: [512, 512],
+            origin: [-512.0, -512.0],
+            cell_size: 2.0,
+            steps_per_frame: 1,
+            density: 0.25,
+            path: "pattern.rle".to_string(),
+            status: String::new(),
+            rebuild: false,
+        }
+    }
+}
+
+pub fn automaton_window(
+    ui: &imgui::Ui,
+    settings: &mut AutomatonSettings,
+    layer: Option<&mut AutomatonLayer>,
+    bindings: &MouseBindings,
+) {
+    ui.window("Cellular automaton")
+        .size([360.0, 360.0], imgui::Condition::FirstUseEver)
+        .position([750.0, 350.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            ui.checkbox("Enabled", &mut settings.enabled);
+            ui.same_line();
+            ui.checkbox("Running", &mut settings.running);
+            ui.text_disabled(format!(
+                "{}-click or drag to toggle cells",
+                bindings::button_name(bindings.button(MouseAction::PaintCells))
+            ));
+            ui.slider("Steps per frame", 1, 64, &mut settings.steps_per_frame);
+
+            let mut changed = ui.input_int2("Grid size", &mut settings.size).build();
+            changed |= ui.input_float2("Origin", &mut settings.origin).build();
+            changed |= ui.input_float("Cell size", &mut settings.cell_size).build();
+            settings.size = [settings.size[0].clamp(8, 8192), settings.size[1].clamp(8, 8192)];
+            settings.cell_size = settings.cell_size.max(f32::EPSILON);
+            if changed {
+                settings.rebuild = true;
+            }
+
+            let Some(layer) = layer else {
+                return;
+            };
+            let automaton = &mut layer.automaton;
+            ui.text(format!(
+                "Generation {}, population {}",
+                automaton.generation,
+                automaton.population()
+            ));
+
+            ui.input_text("Rule", &mut settings.rule_text).build();
+            ui.same_line();
+            if ui.button("Apply") {
+                match settings.rule_text.parse::<Rule>() {
+                    Ok(rule) => automaton.rule = rule,
+                    Err(err) => settings.status = err,
+                }
+            }
+
+            if ui.button("Step") {
+                automaton.step();
+            }
+            ui.same_line();
+            if ui.button("Clear") {
+                automaton.clear();
+            }
+            ui.same_line();
+            if ui.button("Randomize") {
+                automaton.randomize(settings.density, rand::random());
+            }
+            ui.slider("Density", 0.0, 1.0, &mut settings.density);
+
+            ui.separator();
+            ui.input_text("RLE file", &mut settings.path).build();
+            if ui.button("Load") {
+                let loaded = std::fs::read_to_string(&settings.path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|text| Pattern::from_rle(&text));
+                match loaded {
+                    Ok(pattern) => {
+                        // Centre the pattern in the grid
+                        automaton.clear();
+                        let x = automaton.width.saturating_sub(pattern.width) / 2;
+                        let y_top = (automaton.height + pattern.height) / 2;
+                        automaton.place(&pattern, x, y_top.min(automaton.height - 1));
+                        if let Some(rule) = pattern.rule {
+                            automaton.rule = rule;
+                            settings.rule_text = rule.to_string();
+                        }
+                        settings.status = format!("Loaded {}x{} pattern", pattern.width, pattern.height);
+                    }
+                    Err(err) => settings.status = format!("Failed to load {}: {}", settings.path, err),
+                }
+            }
+            ui.same_line();
+            if ui.button("Save") {
+                let pattern = automaton.to_pattern();
+                settings.status = match std::fs::write(&settings.path, pattern.to_rle()) {
+                    Ok(()) => format!("Saved {} cells to {}", pattern.cells.len(), settings.path),
+                    Err(err) => format!("Failed to save {}: {}", settings.path, err),
+                };
+            }
+            if !settings.status.is_empty() {
+                ui.text_wrapped(&settings.status);
+            }
+        });
+}
+
+/// Toolbar for what left-drag does in the world.
+pub fn tool_window(ui: &imgui::Ui, tools: &mut Tools, bindings: &mut MouseBindings) {
+    ui.window("Tools")
+        .size([360.0, 150.0], imgui::Condition::FirstUseEver)
+        .position([1120.0, 280.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            for (i, tool) in Tool::ALL.iter().enumerate() {
+                if i > 0 {
+                    ui.same_line();
+                }
+                let selected = tools.tool == *tool;
+                let _highlight = selected.then(|| {
+                    ui.push_style_color(imgui::StyleColor::Button, [0.3, 0.5, 0.8, 1.0])
+                });
+                if ui.button(tool.name()) {
+                    tools.tool = *tool;
+                }
+            }
+
+            if tools.tool.uses_radius() {
+                ui.slider("Radius", 1.0, 200.0, &mut tools.radius);
+            }
+            match tools.tool {
+                Tool::Attract | Tool::Repel => {
+                    ui.slider("Strength", 1.0, 500.0, &mut tools.strength);
+                }
+                Tool::Spawn => {
+                    ui.slider("Per frame", 1, 100, &mut tools.spawn_rate);
+                }
+                Tool::Fling => {
+                    ui.slider("Fling scale", 0.1, 10.0, &mut tools.fling_scale);
+                }
+                _ => {}
+            }
+            ui.text_disabled(format!(
+                "{}-drag or space+drag pans",
+                bindings::button_name(bindings.pan)
+            ));
+
+            if ui.collapsing_header("Mouse buttons", imgui::TreeNodeFlags::empty()) {
+                let names = BUTTONS.map(bindings::button_name);
+                for action in MouseAction::ALL {
+                    let button = bindings.button_mut(action);
+                    let mut selected = BUTTONS.iter().position(|b| b == button).unwrap_or(0);
+                    if ui.combo_simple_string(action.name(), &mut selected, &names) {
+                        *button = BUTTONS[selected];
+                    }
+                }
+                ui.text_disabled("Actions on one button: painting takes cells, stirring the rest");
+            }
+        });
+}
+
+pub fn inspector_window(
+    ui: &imgui::Ui,
+    particles: &[Particle],
+    selection: &mut Selection,
+    command_sender: &Sender<SimCommand>,
+) {
+    ui.window("Inspector")
+        .size([360.0, 230.0], imgui::Condition::FirstUseEver)
+        .position([1120.0, 440.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            // Particles can vanish between snapshots
+            selection.indices.retain(|&index| index < particles.len());
+            match selection.indices.as_slice() {
+                [] => {
+                    ui.text_disabled("Click a particle or drag a box with the Select tool");
+                }
+                &[index] => {
+                    let mut particle = particles[index];
+                    ui.text(format!("Particle #{index}"));
+                    let mut changed = ui.input_scalar_n("Position", &mut particle.position).build();
+                    changed |= ui.input_float2("Velocity", &mut particle.velocity).build();
+                    changed |= ui.input_float("Mass", &mut particle.mass).build();
+                    let mut species = particle.species as i32;
+                    if ui.input_int("Species", &mut species).build() {
+                        particle.species = species.clamp(0, u8::MAX as i32) as u8;
+                        changed = true;
+                    }
+                    if changed {
+                        let _ = command_sender.send(SimCommand::SetParticle(index, particle));
+                    }
+                }
+                indices => {
+                    let count = indices.len() as f64;
+                    let mut centroid = [0.0f64; 2];
+                    let mut momentum = [0.0f32; 2];
+                    let mut total_mass = 0.0f32;
+                    let mut kinetic_energy = 0.0f32;
+                    let mut max_speed = 0.0f32;
+                    for &index in indices {
+                        let particle = &particles[index];
+                        let [vx, vy] = particle.velocity;
+                        let speed_squared = vx * vx + vy * vy;
+                        centroid[0] += particle.position[0] / count;
+                        centroid[1] += particle.position[1] / count;
+                        momentum[0] += particle.mass * vx;
+                        momentum[1] += particle.mass * vy;
+                        total_mass += particle.mass;
+                        kinetic_energy += 0.5 * particle.mass * speed_squared;
+                        max_speed = max_speed.max(speed_squared.sqrt());
+                    }
+                    ui.text(format!("Selected: {}", indices.len()));
+                    ui.text(format!("Centroid: ({:.2}, {:.2})", centroid[0], centroid[1]));
+                    ui.text(format!(
+                        "Mean velocity: ({:.2}, {:.2})",
+                        momentum[0] / total_mass,
+                        momentum[1] / total_mass
+                    ));
+                    ui.text(format!("Total mass: {total_mass:.2}"));
+                    ui.text(format!("Kinetic energy: {kinetic_energy:.2}"));
+                    ui.text(format!("Max speed: {max_speed:.2}"));
+                }
+            }
+            if !selection.indices.is_empty() && ui.button("Clear selection") {
+                selection.indices.clear();
+            }
+        });
+}
+
+pub fn screenshot_window(ui: &imgui::Ui, settings: &mut ScreenshotSettings) {
+    ui.window("Screenshot")
+        .size([360.0, 130.0], imgui::Condition::FirstUseEver)
+        .position([380.0, 720.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            ui.checkbox("Include UI", &mut settings.include_ui);
+            ui.slider("Supersample", 1, 4, &mut settings.scale);
+            ui.input_text("Folder", &mut settings.directory).build();
+            if ui.button("Capture") {
+                settings.requested = true;
+            }
+            ui.same_line();
+            ui.text_disabled("or press F12");
+        });
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    pub settings: RecordSettings,
+    // The recording in progress, if any
+    pub status: Option<Arc<RecordStatus>>,
+}
+
+/// `capture_view` copies the current camera and render settings into the
+/// recording's settings when one starts.
+pub fn record_window(
+    ui: &imgui::Ui,
+    recorder: &mut Recorder,
+    capture_view: impl FnOnce(&mut RecordSettings),
+    commands: &Sender<SimCommand>,
+) {
+    ui.window("Recording")
+        .size([360.0, 210.0], imgui::Condition::FirstUseEver)
+        .position([750.0, 720.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let settings = &mut recorder.settings;
+            ui.input_text("File", &mut settings.path).build();
+            let mut size = [settings.width as i32, settings.height as i32];
+            if ui.input_int2("Resolution", &mut size).build() {
+                settings.width = size[0].clamp(2, 8192) as u32;
+                settings.height = size[1].clamp(2, 8192) as u32;
+            }
+            ui.input_scalar("Frames", &mut settings.frames).build();
+            ui.slider("FPS", 1, 120, &mut settings.fps);
+            ui.slider("Ticks per frame", 1, 20, &mut settings.ticks_per_frame);
+            ui.text_disabled("Particles and bodies only, from the current view");
+
+            if let Some(status) = recorder.status.as_ref() {
+                let written = status.frames_written.load(Ordering::Relaxed);
+                if status.finished.load(Ordering::Relaxed) {
+                    recorder.status = None;
+                } else {
+                    let fraction = written as f32 / settings.frames.max(1) as f32;
+                    imgui::ProgressBar::new(fraction)
+                        .overlay_text(format!("{written}/{} frames", settings.frames))
+                        .build(ui);
+                    return;
+                }
+            }
+            if ui.button("Record") {
+                capture_view(settings);
+                let status = Arc::new(RecordStatus::default());
+                let _ = commands.send(SimCommand::Record(settings.clone(), status.clone()));
+                recorder.status = Some(status);
+            }
+        });
+}
+
+pub fn render_window(
+    ui: &imgui::Ui,
+    style: &mut ParticleStyle,
+    lod: &mut Lod,
+    trails: &mut TrailSettings,
+    grid: &mut GridSettings,
+    heatmap_active: bool,
+    timings: &[PassTiming],
+) {
+    ui.window("Rendering")
+        .size([360.0, 330.0], imgui::Condition::FirstUseEver)
+        .position([10.0, 770.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let names = ParticleShape::ALL.map(|shape| shape.name());
+            let mut selected = ParticleShape::ALL
+                .iter()
+                .position(|shape| *shape == style.shape)
+                .unwrap_or(0);
+            if ui.combo_simple_string("Particles", &mut selected, &names) {
+                style.shape = ParticleShape::ALL[selected];
+            }
+            match style.shape {
+                ParticleShape::Filled => {}
+                ParticleShape::Outline => {
+                    ui.slider("Outline width", 0.05, 1.0, &mut style.outline_width);
+                }
+                ParticleShape::Glow => {
+                    ui.slider("Glow radius", 0.1, 5.0, &mut style.glow_radius);
+                }
+            }
+
+            ui.separator();
+            let names = LodMode::ALL.map(|mode| mode.name());
+            let mut selected = LodMode::ALL.iter().position(|mode| *mode == lod.mode).unwrap_or(0);
+            if ui.combo_simple_string("Level of detail", &mut selected, &names) {
+                lod.mode = LodMode::ALL[selected];
+            }
+            if lod.mode == LodMode::Auto {
+                ui.slider("Heatmap below (px)", 0.1, 10.0, &mut lod.switch_diameter);
+            }
+            ui.slider("Saturation (per px)", 1.0, 4096.0, &mut lod.saturation);
+            ui.text_disabled(if heatmap_active {
+                "Drawing a density heatmap"
+            } else {
+                "Drawing individual particles"
+            });
+
+            ui.separator();
+            ui.checkbox("Motion trails", &mut trails.enabled);
+            if trails.enabled {
+                ui.slider("Trail length (frames)", 1.0, 600.0, &mut trails.length);
+                ui.color_edit3("Fade color", &mut trails.fade_color);
+                let names = TrailBlend::ALL.map(|blend| blend.name());
+                let mut selected = TrailBlend::ALL
+                    .iter()
+                    .position(|blend| *blend == trails.blend)
+                    .unwrap_or(0);
+                if ui.combo_simple_string("Blend", &mut selected, &names) {
+                    trails.blend = TrailBlend::ALL[selected];
+                }
+            }
+
+            ui.separator();
+            ui.checkbox("Background grid", &mut grid.enabled);
+            if grid.enabled {
+                ui.checkbox("Axes", &mut grid.axes);
+                ui.same_line();
+                ui.checkbox("Labels", &mut grid.labels);
+                ui.color_edit4("Grid color", &mut grid.color);
+            }
+
+            if ui.collapsing_header("Pass timings", imgui::TreeNodeFlags::empty()) {
+                ui.text_disabled("Pass              CPU ms   GPU ms");
+                for timing in timings {
+                    let gpu = match timing.gpu {
+                        Some(gpu) => format!("{:8.3}", gpu.as_secs_f64() * 1000.0),
+                        None => format!("{:>8}", "-"),
+                    };
+                    ui.text(format!(
+                        "{:<16} {:8.3} {gpu}",
+                        timing.name,
+                        timing.cpu.as_secs_f64() * 1000.0
+                    ));
+                }
+            }
+        });
+}
+
+/// Coordinates of the major grid lines along the axes, kept on screen when an
+/// axis is not, and a readout of the world position under the cursor.
+pub fn grid_labels(
+    ui: &imgui::Ui,
+    camera: &Camera,
+    settings: &GridSettings,
+    screen: [f32; 2],
+    cursor: Option<[f32; 2]>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    let spacing = GridSpacing::at(camera.world_per_pixel() as f64);
+    let decimals = spacing.decimals();
+    let color = [settings.color[0], settings.color[1], settings.color[2], 1.0];
+    let draw_list = ui.get_background_draw_list();
+    if settings.labels {
+        let min = camera.screen_to_world([0.0, screen[1]]);
+        let max = camera.screen_to_world([screen[0], 0.0]);
+        let origin = camera.world_to_screen([0.0, 0.0]);
+        // Room for one line of text inside the window edges
+        let line = ui.text_line_height();
+        let below_x_axis = (origin[1] + 2.0).clamp(0.0, screen[1] - line);
+        let right_of_y_axis = (origin[0] + 4.0).clamp(0.0, screen[0] - 6.0 * line);
+        let first = |min: f64| (min / spacing.major).ceil() as i64;
+        let last = |max: f64| (max / spacing.major).floor() as i64;
+        for k in first(min[0])..=last(max[0]) {
+            let x = k as f64 * spacing.major;
+            let position = camera.world_to_screen([x, 0.0]);
+            draw_list.add_text([position[0] + 2.0, below_x_axis], color, format!("{x:.decimals$}"));
+        }
+        for k in first(min[1])..=last(max[1]) {
+            if k == 0 {
+                continue;
+            }
+            let y = k as f64 * spacing.major;
+            let position = camera.world_to_screen([0.0, y]);
+            draw_list.add_text([right_of_y_axis, position[1] + 2.0], color, format!("{y:.decimals$}"));
+        }
+    }
+
+    let Some(cursor) = cursor.filter(|_| !ui.io().want_capture_mouse) else {
+        return;
+    };
+    // One digit finer than the minor lines
+    let decimals = (1.0 - spacing.minor.log10()).ceil().max(0.0) as usize;
+    let [x, y] = camera.screen_to_world(cursor);
+    draw_list.add_text(
+        [cursor[0] + 14.0, cursor[1] + 14.0],
+        [1.0, 1.0, 1.0, 1.0],
+        format!("({x:.decimals$}, {y:.decimals$})"),
+    );
+}
+
+pub fn post_window(ui: &imgui::Ui, settings: &mut PostSettings) {
+    ui.window("Post-processing")
+        .size([360.0, 300.0], imgui::Condition::FirstUseEver)
+        .position([380.0, 860.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            ui.text_disabled("Applied top to bottom");
+            let passes = &mut settings.passes;
+            let count = passes.len();
+            let mut swap = None;
+            let mut remove = None;
+            for (i, pass) in passes.iter_mut().enumerate() {
+                let _id = ui.push_id_usize(i);
+                ui.separator();
+                ui.checkbox(pass.effect.name(), &mut pass.enabled);
+                ui.same_line();
+                if ui.arrow_button("up", imgui::Direction::Up) && i > 0 {
+                    swap = Some(i - 1);
+                }
+                ui.same_line();
+                if ui.arrow_button("down", imgui::Direction::Down) && i + 1 < count {
+                    swap = Some(i);
+                }
+                ui.same_line();
+                if ui.small_button("Remove") {
+                    remove = Some(i);
+                }
+                if !pass.enabled {
+                    continue;
+                }
+                match &mut pass.effect {
+                    PostEffect::Bloom(bloom) => {
+                        ui.slider("Threshold", 0.0, 4.0, &mut bloom.threshold);
+                        ui.slider("Knee", 0.0, 1.0, &mut bloom.knee);
+                        ui.slider("Intensity", 0.0, 4.0, &mut bloom.intensity);
+                        ui.slider("Radius", 0.5, 4.0, &mut bloom.radius);
+                    }
+                    PostEffect::Tonemap(tonemap) => {
+                        let names = Tonemapper::ALL.map(|tonemapper| tonemapper.name());
+                        let mut selected = Tonemapper::ALL
+                            .iter()
+                            .position(|tonemapper| *tonemapper == tonemap.tonemapper)
+                            .unwrap_or(0);
+                        if ui.combo_simple_string("Operator", &mut selected, &names) {
+                            tonemap.tonemapper = Tonemapper::ALL[selected];
+                        }
+                        ui.slider("Exposure (stops)", -4.0, 4.0, &mut tonemap.exposure);
+                    }
+                }
+            }
+            if let Some(i) = swap {
+                passes.swap(i, i + 1);
+            }
+            if let Some(i) = remove {
+                passes.remove(i);
+            }
+
+            ui.separator();
+            if passes.len() < MAX_PASSES {
+                for effect in PostEffect::all() {
+                    if ui.button(format!("Add {}", effect.name())) {
+                        passes.push(PostPass { enabled: true, effect });
+                    }
+                    ui.same_line();
+                }
+                ui.new_line();
+            } else {
+                ui.text_disabled(format!("At most {MAX_PASSES} passes"));
+            }
+        });
+}
+
+pub struct ColorSettings {
+    pub coloring: Coloring,
+    pub gradient_path: String,
+    pub status: String,
+}
+
+impl Default for ColorSettings {
+    fn default() -> Self {
+        Self {
+            coloring: Coloring::default(),
+            gradient_path: "resources/gradient.png".to_string(),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn color_window(ui: &imgui::Ui, settings: &mut ColorSettings) {
+    const LEGEND_HEIGHT: f32 = 16.0;
+    const LEGEND_STEPS: usize = 64;
+
+    ui.window("Colors")
+        .size([360.0, 260.0], imgui::Condition::FirstUseEver)
+        .position([1120.0, 680.0], imgui::Condition::FirstUseEver)
+        .build(|| {
+            let coloring = &mut settings.coloring;
+            let mut names = vec!["Species tint"];
+            names.extend(ColorAttribute::ALL.map(|attribute| attribute.name()));
+            let mut selected = coloring
+                .attribute
+                .and_then(|attribute| ColorAttribute::ALL.iter().position(|a| *a == attribute))
+                .map_or(0, |i| i + 1);
+            if ui.combo_simple_string("Color by", &mut selected, &names) {
+                coloring.attribute = selected.checked_sub(1).map(|i| ColorAttribute::ALL[i]);
+            }
+            let Some(attribute) = coloring.attribute else {
+                return;
+            };
+
+            let names = Colormap::ALL.map(|colormap| colormap.name());
+            let mut selected = Colormap::ALL
+                .iter()
+                .position(|colormap| *colormap == coloring.colormap)
+                .unwrap_or(0);
+            if ui.combo_simple_string("Colormap", &mut selected, &names) {
+                coloring.colormap = Colormap::ALL[selected];
+            }
+            if coloring.colormap == Colormap::Custom {
+                ui.input_text("Gradient PNG", &mut settings.gradient_path).build();
+                ui.same_line();
+                if ui.button("Load") {
+                    match colormap::load_gradient(&settings.gradient_path) {
+                        Ok(gradient) => {
+                            coloring.gradient = gradient;
+                            settings.status = format!("Loaded {}", settings.gradient_path);
+                        }
+                        Err(err) => {
+                            settings.status = format!("Failed to load {}: {}", settings.gradient_path, err)
+                        }
+                    }
+                }
+                if !settings.status.is_empty() {
+                    ui.text_wrapped(&settings.status);
+                }
+            }
+
+            ui.checkbox("Auto range", &mut coloring.auto_range);
+            if !coloring.auto_range {
+                ui.input_float2("Min / max", &mut coloring.range).build();
+            }
+
+            // Legend: the gradient with the range it spans
+            ui.separator();
+            ui.text(attribute.name());
+            let width = ui.content_region_avail()[0];
+            let origin = ui.cursor_screen_pos();
+            let draw_list = ui.get_window_draw_list();
+            let step = width / LEGEND_STEPS as f32;
+            for i in 0..LEGEND_STEPS {
+                let t = |i: usize| i as f32 / LEGEND_STEPS as f32;
+                let [left, right] = [t(i), t(i + 1)].map(|t| {
+                    let [r, g, b, a] = coloring.color(t);
+                    imgui::ImColor32::from_rgba(r, g, b, a)
+                });
+                let x = origin[0] + i as f32 * step;
+                draw_list.add_rect_filled_multicolor(
+                    [x, origin[1]],
+                    [x + step, origin[1] + LEGEND_HEIGHT],
+                    left,
+                    right,
+                    right,
+                    left,
+                );
+            }
+            ui.dummy([width, LEGEND_HEIGHT]);
+            let [min, max] = coloring.range;
+            ui.text(format!("{min:.3}"));
+            let max_text = format!("{max:.3}");
+            ui.same_line_with_pos(width - ui.calc_text_size(&max_text)[0]);
+            ui.text(max_text);
+        });
+}
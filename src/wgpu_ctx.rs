@@ -4,6 +4,15 @@ use wgpu::MemoryHints::Performance;
 use wgpu::{BufferDescriptor, BufferUsages, ShaderSource};
 use winit::window::Window;
 
+use crate::colormap::{Coloring, TABLE_SIZE};
+use crate::debug_draw::{DebugDraw, DebugRenderer};
+use crate::heatmap::{Heatmap, Lod};
+use crate::post::{PostChain, PostSettings};
+use crate::render_graph::{GraphCache, RenderGraph, ResourceId, TargetSize};
+use crate::rigid::BodyVertex;
+use crate::sim::Particle;
+use crate::trails::{AccumulationBuffer, TrailBlend, TrailSettings, SCENE_FORMAT};
+use crate::world_grid::{GridSettings, WorldGrid};
 use crate::Camera;
 
 #[repr(C)]
@@ -16,20 +25,165 @@ struct Vertex {
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Default)]
 pub struct InstanceData {
     pub position: [f32; 2],
+    // Radians, counter-clockwise; drawn as a marker from the centre
+    pub angle: f32,
+    // Bits 0-7: species, bit 8: selected, bit 9: coloured by `value`
+    pub flags: u32,
+    // Position in the colormap, 0..1
+    pub value: f32,
+}
+
+pub const INSTANCE_SELECTED: u32 = 1 << 8;
+pub const INSTANCE_COLORMAPPED: u32 = 1 << 9;
+
+// Requested when available, for per-pass GPU timings
+const TIMESTAMP_FEATURES: wgpu::Features =
+    wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS);
+
+// World radius particles are drawn at, matching the unit disc in shader.txt
+const PARTICLE_RADIUS: f32 = 1.0;
+
+/// How particle discs are shaded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParticleShape {
+    Filled,
+    Outline,
+    Glow,
+}
+
+impl ParticleShape {
+    pub const ALL: [ParticleShape; 3] = [
+        ParticleShape::Filled,
+        ParticleShape::Outline,
+        ParticleShape::Glow,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParticleShape::Filled => "Filled",
+            ParticleShape::Outline => "Outline",
+            ParticleShape::Glow => "Glow",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParticleStyle {
+    pub shape: ParticleShape,
+    // Ring width for the outline shape, as a fraction of the radius
+    pub outline_width: f32,
+    // How far the glow reaches past the rim, in radii
+    pub glow_radius: f32,
+}
+
+impl Default for ParticleStyle {
+    fn default() -> Self {
+        Self {
+            shape: ParticleShape::Filled,
+            outline_width: 0.25,
+            glow_radius: 1.5,
+        }
+    }
+}
+
+// Matches `ParticleStyle` in shader.txt
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct StyleUniform {
+    pixel_size: f32,
+    shape: u32,
+    outline_width: f32,
+    glow_radius: f32,
+}
+
+// Corners of the quad each particle is drawn on, as a triangle strip
+const QUAD_VERTICES: [Vertex; 4] = [
+    Vertex {
+        position: [-1.0, -1.0],
+    },
+    Vertex {
+        position: [1.0, -1.0],
+    },
+    Vertex {
+        position: [-1.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 1.0],
+    },
+];
+
+/// Camera-relative vertex of the triangle lists drawn by the polygon pipeline.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub struct PolygonVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 3],
+}
+
+/// The texture one frame is drawn into.
+pub enum Frame {
+    Surface(wgpu::SurfaceTexture),
+    Offscreen(wgpu::Texture),
+}
+
+impl Frame {
+    pub fn texture(&self) -> &wgpu::Texture {
+        match self {
+            Frame::Surface(surface_texture) => &surface_texture.texture,
+            Frame::Offscreen(texture) => texture,
+        }
+    }
+
+    /// Shows the frame in the window; offscreen frames stay where they are.
+    pub fn present(self) {
+        if let Frame::Surface(surface_texture) = self {
+            surface_texture.present();
+        }
+    }
 }
 
 pub struct WgpuCtx<'window> {
-    pub surface: wgpu::Surface<'window>,
+    // None when headless
+    pub surface: Option<wgpu::Surface<'window>>,
+    // Describes the offscreen target too, so size and format live in one place
     pub surface_config: wgpu::SurfaceConfiguration,
+    // Drawn into instead of the surface when headless
+    pub offscreen: Option<wgpu::Texture>,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub render_pipeline: wgpu::RenderPipeline,
+    // Used instead of `render_pipeline` for additive trails
+    additive_pipeline: wgpu::RenderPipeline,
     pub vertex_buffer: wgpu::Buffer,
     pub instance_buffer: wgpu::Buffer,
     pub num_instances: u32,
+    pub particle_style: ParticleStyle,
+    style_buffer: wgpu::Buffer,
+    style_bind_group: wgpu::BindGroup,
+    colormap_texture: wgpu::Texture,
+    pub lod: Lod,
+    heatmap: Heatmap,
+    pub trails: TrailSettings,
+    accumulation: AccumulationBuffer,
+    pub post: PostSettings,
+    post_chain: PostChain,
+    pub grid: GridSettings,
+    world_grid: WorldGrid,
+    // Pooled graph textures and pass timings; taken out while a graph borrows the context
+    pub graph_cache: GraphCache,
+    // Reused between uploads to rebase particles onto the camera
+    staging_instances: Vec<InstanceData>,
+    pub polygon_pipeline: wgpu::RenderPipeline,
+    pub polygon_buffer: wgpu::Buffer,
+    pub num_polygon_vertices: u32,
+    staging_polygons: Vec<PolygonVertex>,
+    // Gizmos for the current frame, drawn over the output and then cleared
+    pub debug: DebugDraw,
+    debug_renderer: DebugRenderer,
     pub camera: Camera,
     pub uniform_buffer: wgpu::Buffer,
+    pub uniform_bind_group_layout: wgpu::BindGroupLayout,
     pub uniform_bind_group: wgpu::BindGroup,
 }
 
@@ -54,7 +208,7 @@ impl<'window> WgpuCtx<'window> {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
+                    required_features: adapter.features() & TIMESTAMP_FEATURES,
                     required_limits: wgpu::Limits::downlevel_webgl2_defaults()
                         .using_resolution(adapter.limits()),
                     memory_hints: Performance,
@@ -70,24 +224,99 @@ impl<'window> WgpuCtx<'window> {
         let surface_config = surface.get_default_config(&adapter, width, height).unwrap();
         surface.configure(&device, &surface_config);
 
-        // Create vertex data for triangle
-        let radius = 2.0;
-        let vertices = [
-            Vertex { position: [radius * f32::cos(0.0), radius * f32::sin(0.0)] }, 
-            Vertex { position: [radius * f32::cos(2.0 * std::f32::consts::PI / 3.0), radius * f32::sin(2.0 * std::f32::consts::PI / 3.0)] },
-            Vertex { position: [radius * f32::cos(4.0 * std::f32::consts::PI / 3.0), radius * f32::sin(4.0 * std::f32::consts::PI / 3.0)] }
-        ];
+        Self::from_device(adapter, device, queue, Some(surface), surface_config)
+    }
+
+    /// Creates a context that renders into an offscreen texture, for tests and
+    /// machines without a display. Falls back to a software adapter when no
+    /// hardware one is available. Returns None if there is no adapter at all.
+    pub fn new_headless(width: u32, height: u32) -> Option<WgpuCtx<'static>> {
+        pollster::block_on(WgpuCtx::new_headless_async(width, height, false))
+    }
+
+    /// Like `new_headless`, but only on the software adapter, so the output
+    /// doesn't depend on the machine's GPU.
+    #[cfg(test)]
+    pub fn new_software(width: u32, height: u32) -> Option<WgpuCtx<'static>> {
+        pollster::block_on(WgpuCtx::new_headless_async(width, height, true))
+    }
+
+    pub async fn new_headless_async(
+        width: u32,
+        height: u32,
+        software_only: bool,
+    ) -> Option<WgpuCtx<'static>> {
+        let instance = wgpu::Instance::default();
+        let attempts: &[bool] = if software_only {
+            &[true]
+        } else {
+            &[false, true]
+        };
+        let mut adapter = None;
+        for &force_fallback_adapter in attempts {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    force_fallback_adapter,
+                    compatible_surface: None,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: adapter.features() & TIMESTAMP_FEATURES,
+                    required_limits: wgpu::Limits::downlevel_webgl2_defaults()
+                        .using_resolution(adapter.limits()),
+                    memory_hints: Performance,
+                },
+                None,
+            )
+            .await
+            .ok()?;
+
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::AutoNoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+        let mut ctx = WgpuCtx::from_device(adapter, device, queue, None, surface_config);
+        ctx.camera.update_window_size(width as f32, height as f32);
+        Some(ctx)
+    }
+
+    fn from_device(
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        surface: Option<wgpu::Surface<'window>>,
+        surface_config: wgpu::SurfaceConfiguration,
+    ) -> WgpuCtx<'window> {
+        let offscreen = surface
+            .is_none()
+            .then(|| create_offscreen_texture(&device, &surface_config));
 
         let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Vertex Buffer"),
-            size: std::mem::size_of_val(&vertices) as u64,
+            size: std::mem::size_of_val(&QUAD_VERTICES) as u64,
             usage: BufferUsages::VERTEX,
             mapped_at_creation: true,
         });
 
         {
             let mut buffer_view = vertex_buffer.slice(..).get_mapped_range_mut();
-            bytemuck::cast_slice_mut::<_, Vertex>(&mut buffer_view).copy_from_slice(&vertices);
+            bytemuck::cast_slice_mut::<_, Vertex>(&mut buffer_view).copy_from_slice(&QUAD_VERTICES);
         }
         vertex_buffer.unmap();
 
@@ -100,6 +329,9 @@ impl<'window> WgpuCtx<'window> {
                         (x as f32 - 4.5) * 0.2, // Center the grid
                         (y as f32 - 4.5) * 0.2,
                     ],
+                    angle: 0.0,
+                    flags: 0,
+                    value: 0.0,
                 });
             }
         }
@@ -138,7 +370,7 @@ impl<'window> WgpuCtx<'window> {
                 count: None,
             }],
         });
-        
+
         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Camera Bind Group"),
             layout: &bind_group_layout,
@@ -155,25 +387,241 @@ impl<'window> WgpuCtx<'window> {
         }
         instance_buffer.unmap();
 
-        let render_pipeline = create_pipeline(&device, surface_config.format, bind_group_layout);
+        let style_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Style Buffer"),
+            size: std::mem::size_of::<StyleUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Colormap entries are sRGB, so the shader gets them back in linear space
+        let colormap_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Colormap"),
+            size: wgpu::Extent3d {
+                width: TABLE_SIZE as u32,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let colormap_view = colormap_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let colormap_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Colormap"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let style_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particle Style Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let style_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Style Bind Group"),
+            layout: &style_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: style_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&colormap_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&colormap_sampler),
+                },
+            ],
+        });
 
-        WgpuCtx {
+        let render_pipeline = create_pipeline(
+            &device,
+            SCENE_FORMAT,
+            &bind_group_layout,
+            &style_bind_group_layout,
+            wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        );
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let additive_pipeline = create_pipeline(
+            &device,
+            SCENE_FORMAT,
+            &bind_group_layout,
+            &style_bind_group_layout,
+            wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            },
+        );
+        let polygon_pipeline = create_polygon_pipeline(&device, SCENE_FORMAT, &bind_group_layout);
+        let heatmap = Heatmap::new(
+            &device,
+            &bind_group_layout,
+            &colormap_view,
+            &colormap_sampler,
+        );
+        let accumulation = AccumulationBuffer::new(&device, &surface_config);
+        let post_chain = PostChain::new(&device);
+        let world_grid = WorldGrid::new(&device);
+        let debug_renderer = DebugRenderer::new(&device, surface_config.format, &bind_group_layout);
+        let polygon_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Polygon Buffer"),
+            size: std::mem::size_of::<PolygonVertex>() as u64 * 3,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let ctx = WgpuCtx {
             surface,
             surface_config,
+            offscreen,
             adapter,
             device,
             queue,
             render_pipeline,
+            additive_pipeline,
             vertex_buffer,
             instance_buffer,
             num_instances,
+            particle_style: ParticleStyle::default(),
+            style_buffer,
+            style_bind_group,
+            colormap_texture,
+            lod: Lod::default(),
+            heatmap,
+            trails: TrailSettings::default(),
+            accumulation,
+            post: PostSettings::default(),
+            post_chain,
+            grid: GridSettings::default(),
+            world_grid,
+            graph_cache: GraphCache::default(),
+            staging_instances: Vec::new(),
+            polygon_pipeline,
+            polygon_buffer,
+            num_polygon_vertices: 0,
+            staging_polygons: Vec::new(),
+            debug: DebugDraw::default(),
+            debug_renderer,
+            uniform_bind_group_layout: bind_group_layout,
             uniform_bind_group,
             uniform_buffer,
             camera,
+        };
+        ctx.write_style();
+        ctx.set_colormap(&Coloring::default().table());
+        ctx
+    }
+
+    /// Uploads particles relative to the current camera position, highlighting
+    /// the `selected` indices. Particles are coloured through the colormap by
+    /// `values` in 0..1 if given, by species otherwise. Call every frame the
+    /// camera may have moved, not only on new snapshots.
+    pub fn update_instances(&mut self, particles: &[Particle], selected: &[usize], values: &[f32]) {
+        let mut instances = std::mem::take(&mut self.staging_instances);
+        instances.clear();
+        instances.extend(particles.iter().map(|particle| InstanceData {
+            position: self.camera.to_view(particle.position),
+            angle: particle.angle,
+            flags: particle.species as u32,
+            value: 0.0,
+        }));
+        if values.len() == instances.len() {
+            for (instance, &value) in instances.iter_mut().zip(values) {
+                instance.flags |= INSTANCE_COLORMAPPED;
+                instance.value = value;
+            }
+        }
+        for &index in selected {
+            if let Some(instance) = instances.get_mut(index) {
+                instance.flags |= INSTANCE_SELECTED;
+            }
         }
+        self.write_instances(&instances);
+        self.staging_instances = instances;
+        self.write_style();
+    }
+
+    /// Replaces the colormap lookup table, `TABLE_SIZE` sRGB entries from 0 to 1.
+    pub fn set_colormap(&self, table: &[[u8; 4]]) {
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.colormap_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&table[..TABLE_SIZE]),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * TABLE_SIZE as u32),
+                rows_per_image: Some(1),
+            },
+            wgpu::Extent3d {
+                width: TABLE_SIZE as u32,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Uploads `particle_style` along with the pixel size at the current zoom,
+    /// the trail fade, the post-processing settings and the grid's view.
+    fn write_style(&self) {
+        let style = StyleUniform {
+            pixel_size: self.camera.world_per_pixel(),
+            shape: self.particle_style.shape as u32,
+            outline_width: self.particle_style.outline_width,
+            glow_radius: self.particle_style.glow_radius,
+        };
+        self.queue
+            .write_buffer(&self.style_buffer, 0, bytemuck::bytes_of(&style));
+        self.heatmap
+            .set_saturation(&self.queue, self.lod.saturation);
+        self.accumulation.set_fade(&self.queue, &self.trails);
+        self.post_chain.set_params(&self.queue, &self.post);
+        self.world_grid
+            .set_view(&self.queue, &self.camera, &self.grid);
     }
 
-    pub fn update_instances(&mut self, instances: &[InstanceData]) {
+    fn write_instances(&mut self, instances: &[InstanceData]) {
         let new_buffer_size = (std::mem::size_of::<InstanceData>() * instances.len()) as u64;
         let current_buffer_size = self.instance_buffer.size();
 
@@ -192,65 +640,319 @@ impl<'window> WgpuCtx<'window> {
         }
 
         // Write the new instance data to the buffer
-        self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
+        self.queue
+            .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
         self.num_instances = instances.len() as u32;
     }
 
-    pub fn draw(&mut self) {
-        let surface_texture = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
-        let texture_view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    /// Uploads world-space triangles relative to the current camera position.
+    pub fn update_polygons(&mut self, world_vertices: &[BodyVertex]) {
+        let mut vertices = std::mem::take(&mut self.staging_polygons);
+        vertices.clear();
+        vertices.extend(world_vertices.iter().map(|vertex| PolygonVertex {
+            position: self.camera.to_view(vertex.position),
+            color: vertex.color,
+        }));
+        self.write_polygons(&vertices);
+        self.staging_polygons = vertices;
+    }
 
+    fn write_polygons(&mut self, vertices: &[PolygonVertex]) {
+        let new_buffer_size = std::mem::size_of_val(vertices) as u64;
+        if new_buffer_size > self.polygon_buffer.size() {
+            self.polygon_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Polygon Buffer"),
+                size: new_buffer_size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        self.queue
+            .write_buffer(&self.polygon_buffer, 0, bytemuck::cast_slice(vertices));
+        self.num_polygon_vertices = vertices.len() as u32;
+    }
+
+    /// Uploads this frame's debug lines relative to the current camera and
+    /// starts an empty batch for the next one.
+    pub fn upload_debug(&mut self) {
+        self.debug_renderer
+            .upload(&self.device, &self.queue, &self.camera, &mut self.debug);
+    }
+
+    /// Draws the uploaded polygon triangles with the camera bind group.
+    pub fn draw_polygons(&self, pass: &mut wgpu::RenderPass<'_>) {
+        if self.num_polygon_vertices == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.polygon_pipeline);
+        pass.set_vertex_buffer(0, self.polygon_buffer.slice(..));
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.draw(0..self.num_polygon_vertices, 0..1);
+    }
+
+    /// Whether particles are currently small enough on screen to be drawn as a heatmap.
+    pub fn heatmap_active(&self) -> bool {
+        self.lod
+            .use_heatmap(PARTICLE_RADIUS, self.camera.world_per_pixel())
+    }
+
+    /// Draws the uploaded particles as anti-aliased discs on instanced quads,
+    /// or through the colormap from a splatted `density` when zoomed far out.
+    pub fn draw_particles(
+        &self,
+        pass: &mut wgpu::RenderPass<'_>,
+        density: Option<&wgpu::TextureView>,
+    ) {
+        if self.num_instances == 0 {
+            return;
+        }
+        if let Some(density) = density {
+            self.heatmap
+                .draw(&self.device, pass, &self.uniform_bind_group, density);
+            return;
+        }
+        let additive = self.trails.enabled && self.trails.blend == TrailBlend::Additive;
+        pass.set_pipeline(if additive {
+            &self.additive_pipeline
+        } else {
+            &self.render_pipeline
+        });
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(1, &self.style_bind_group, &[]);
+        pass.draw(0..QUAD_VERTICES.len() as u32, 0..self.num_instances);
+    }
+
+    /// The texture to draw the next frame into.
+    pub fn acquire_frame(&self) -> Frame {
+        match (&self.surface, &self.offscreen) {
+            (Some(surface), _) => Frame::Surface(
+                surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture"),
+            ),
+            (None, Some(texture)) => Frame::Offscreen(texture.clone()),
+            (None, None) => unreachable!("a context has either a surface or an offscreen target"),
+        }
+    }
+
+    /// The persistent scene target, holding the last frame drawn with history.
+    pub fn scene_history(&self) -> &wgpu::TextureView {
+        self.accumulation.history()
+    }
+
+    /// Adds the nodes that draw the scene: a density splat when the heatmap is
+    /// active, then one pass with the grid and `layers` under the bodies and
+    /// particles. With `keep_history` the scene goes into the persistent target
+    /// so trails carry over, otherwise into a cleared transient one. Returns the
+    /// scene texture.
+    pub fn add_scene_nodes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        keep_history: bool,
+        layers: impl FnOnce(&mut wgpu::RenderPass<'_>) + 'a,
+    ) -> ResourceId {
+        let density = (self.heatmap_active() && self.num_instances > 0).then(|| {
+            self.heatmap.add_splat_node(
+                graph,
+                &self.uniform_bind_group,
+                &self.instance_buffer,
+                self.num_instances,
+            )
+        });
+        let scene = if keep_history {
+            graph.import_texture(self.accumulation.history())
+        } else {
+            graph.transient(SCENE_FORMAT, TargetSize::Output)
+        };
+        let fade = keep_history && self.trails.enabled;
+        let reads: Vec<ResourceId> = density.into_iter().collect();
+        graph.add_node("Scene", &reads, &[scene], move |encoder, resources| {
+            let mut pass = self
+                .accumulation
+                .begin_scene_pass(encoder, resources.view(scene), fade);
+            if self.grid.enabled {
+                self.world_grid.draw(&mut pass);
+            }
+            layers(&mut pass);
+            self.draw_polygons(&mut pass);
+            self.draw_particles(&mut pass, density.map(|density| resources.view(density)));
+        });
+        scene
+    }
+
+    /// Adds the post-processing chain over `scene`, the copy of its result onto
+    /// `output`, in the output format, and the debug lines over that. `scene`
+    /// itself is left as drawn.
+    pub fn add_output_nodes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        scene: ResourceId,
+        output: ResourceId,
+    ) {
+        let result = self
+            .post_chain
+            .add_nodes(graph, &self.device, &self.post, scene);
+        graph.add_node(
+            "Composite",
+            &[result],
+            &[output],
+            move |encoder, resources| {
+                self.accumulation.composite(
+                    &self.device,
+                    encoder,
+                    resources.view(result),
+                    resources.view(output),
+                );
+            },
+        );
+        self.debug_renderer
+            .add_node(graph, &self.uniform_bind_group, output);
+    }
+
+    /// Draws the bodies and particles onto `view`, which is the window's size.
+    fn draw_scene(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
         let view_matrix = self.camera.get_view_matrix();
         self.queue
             .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&view_matrix));
+        self.upload_debug();
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
+        let mut cache = std::mem::take(&mut self.graph_cache);
+        let mut graph = RenderGraph::new(self.surface_config.width, self.surface_config.height);
+        let output = graph.import_texture(view);
+        let scene = self.add_scene_nodes(&mut graph, true, |_| {});
+        self.add_output_nodes(&mut graph, scene, output);
+        graph.execute(&self.device, encoder, &mut cache);
+        self.graph_cache = cache;
+    }
+
+    /// Draws the scene at the current size and reads it back as RGBA.
+    /// Works with or without a window; the window itself is left untouched.
+    pub fn render_to_image(&mut self) -> image::RgbaImage {
+        // Swapchain images can't be copied from on every backend, so windowed
+        // contexts draw into a texture of their own
+        let texture = match &self.offscreen {
+            Some(texture) => texture.clone(),
+            None => create_offscreen_texture(&self.device, &self.surface_config),
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render To Image Encoder"),
             });
-            rpass.set_pipeline(&self.render_pipeline);
-            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            rpass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            rpass.draw(0..3, 0..self.num_instances);
-        }
+        self.draw_scene(&mut encoder, &view);
+        self.queue.submit(Some(encoder.finish()));
+        self.graph_cache.after_submit(&self.device, &self.queue);
+        self.read_texture(&texture)
+    }
+
+    /// Copies a 4-byte-per-pixel texture back to the CPU, dropping the row
+    /// padding the copy needs and swizzling BGRA formats to RGBA.
+    pub fn read_texture(&self, texture: &wgpu::Texture) -> image::RgbaImage {
+        let (width, height) = (texture.width(), texture.height());
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
 
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
         self.queue.submit(Some(encoder.finish()));
-        surface_texture.present();
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Failed to map readback buffer");
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks_exact(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        if matches!(
+            texture.format(),
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(width, height, pixels).expect("Readback size mismatch")
     }
 
     pub fn resize(&mut self, new_size: (u32, u32)) {
         let (width, height) = new_size;
         self.surface_config.width = width.max(1);
         self.surface_config.height = height.max(1);
-        self.surface.configure(&self.device, &self.surface_config);
+        match &self.surface {
+            Some(surface) => surface.configure(&self.device, &self.surface_config),
+            None => {
+                self.offscreen = Some(create_offscreen_texture(&self.device, &self.surface_config))
+            }
+        }
+        // Transient graph textures follow the new size on their own
+        self.accumulation.resize(
+            &self.device,
+            self.surface_config.width,
+            self.surface_config.height,
+        );
         self.camera.update_window_size(width as f32, height as f32);
     }
 }
 
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Target"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
 fn create_pipeline(
     device: &wgpu::Device,
-    swap_chain_format: wgpu::TextureFormat,
-    bind_group_layout: wgpu::BindGroupLayout
+    target_format: wgpu::TextureFormat,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    style_bind_group_layout: &wgpu::BindGroupLayout,
+    blend: wgpu::BlendState,
 ) -> wgpu::RenderPipeline {
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: None,
@@ -259,7 +961,7 @@ fn create_pipeline(
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-         bind_group_layouts: &[&bind_group_layout], 
+        bind_group_layouts: &[bind_group_layout, style_bind_group_layout],
         push_constant_ranges: &[],
     });
 
@@ -282,11 +984,28 @@ fn create_pipeline(
                 wgpu::VertexBufferLayout {
                     array_stride: std::mem::size_of::<InstanceData>() as wgpu::BufferAddress,
                     step_mode: wgpu::VertexStepMode::Instance,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x2,
-                        offset: 0,
-                        shader_location: 1,
-                    }],
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x2,
+                            offset: 0,
+                            shader_location: 1,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32,
+                            offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                            shader_location: 2,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Uint32,
+                            offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                            shader_location: 3,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32,
+                            offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                            shader_location: 4,
+                        },
+                    ],
                 },
             ],
             compilation_options: Default::default(),
@@ -296,7 +1015,57 @@ fn create_pipeline(
             entry_point: Some("fs_main"),
             compilation_options: Default::default(),
             targets: &[Some(wgpu::ColorTargetState {
-                format: swap_chain_format,
+                format: target_format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn create_polygon_pipeline(
+    device: &wgpu::Device,
+    target_format: wgpu::TextureFormat,
+    bind_group_layout: &wgpu::BindGroupLayout,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Polygon Shader"),
+        source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("polygon.wgsl"))),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Polygon Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Polygon Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<PolygonVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x3],
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: target_format,
                 blend: Some(wgpu::BlendState::REPLACE),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
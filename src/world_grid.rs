@@ -0,0 +1,180 @@
+use std::borrow::Cow;
+use wgpu::ShaderSource;
+
+use crate::trails::SCENE_FORMAT;
+use crate::Camera;
+
+// Minor lines are never closer than this on screen, and fade in from here
+const MIN_MINOR_PIXELS: f64 = 8.0;
+const OPAQUE_MINOR_PIXELS: f64 = 32.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GridSettings {
+    pub enabled: bool,
+    pub axes: bool,
+    // Coordinates of the major lines along the axes
+    pub labels: bool,
+    pub color: [f32; 4],
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            axes: true,
+            labels: true,
+            color: [0.5, 0.55, 0.6, 0.5],
+        }
+    }
+}
+
+/// Line spacing at one zoom level, in world units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GridSpacing {
+    pub minor: f64,
+    // Ten minor cells
+    pub major: f64,
+    // Minor lines fade out as they close in on each other
+    pub minor_alpha: f32,
+}
+
+impl GridSpacing {
+    /// The smallest power of ten that keeps minor lines apart at this zoom.
+    pub fn at(world_per_pixel: f64) -> Self {
+        let minor = 10f64.powf((MIN_MINOR_PIXELS * world_per_pixel).log10().ceil());
+        let pixels = minor / world_per_pixel;
+        let minor_alpha = (pixels - MIN_MINOR_PIXELS) / (OPAQUE_MINOR_PIXELS - MIN_MINOR_PIXELS);
+        Self {
+            minor,
+            major: 10.0 * minor,
+            minor_alpha: minor_alpha.clamp(0.0, 1.0) as f32,
+        }
+    }
+
+    /// Decimal places that tell major lines apart.
+    pub fn decimals(&self) -> usize {
+        (-self.major.log10()).ceil().max(0.0) as usize
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridUniform {
+    extent: [f32; 2],
+    offset: [f32; 2],
+    origin: [f32; 2],
+    minor: f32,
+    major: f32,
+    pixel: f32,
+    minor_alpha: f32,
+    axes: u32,
+    _padding: f32,
+    color: [f32; 4],
+}
+
+/// Draws the grid with one screen-covering triangle under the scene, so it
+/// costs no geometry however far it reaches.
+pub struct WorldGrid {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl WorldGrid {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("World Grid Buffer"),
+            size: std::mem::size_of::<GridUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("World Grid Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("World Grid Bind Group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("World Grid Shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("world_grid.wgsl"))),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("World Grid Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("World Grid Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: SCENE_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            uniform_buffer,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    pub fn set_view(&self, queue: &wgpu::Queue, camera: &Camera, settings: &GridSettings) {
+        let pixel = camera.world_per_pixel();
+        let spacing = GridSpacing::at(pixel as f64);
+        // Wrapping by a multiple of both spacings keeps f32 precise far from the origin
+        let period = 10.0 * spacing.major;
+        let uniform = GridUniform {
+            extent: camera.view_extent(),
+            offset: camera.position.map(|v| v.rem_euclid(period) as f32),
+            origin: camera.to_view([0.0, 0.0]),
+            minor: spacing.minor as f32,
+            major: spacing.major as f32,
+            pixel,
+            minor_alpha: spacing.minor_alpha,
+            axes: settings.axes as u32,
+            _padding: 0.0,
+            color: settings.color,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn draw(&self, pass: &mut wgpu::RenderPass<'_>) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,51 @@
+struct GridUniform {
+    // World units from the centre of the view to its edges
+    extent: vec2<f32>,
+    // Camera position, wrapped to keep it small without moving any line
+    offset: vec2<f32>,
+    // World origin relative to the camera
+    origin: vec2<f32>,
+    minor: f32,
+    major: f32,
+    pixel: f32,
+    minor_alpha: f32,
+    axes: u32,
+    _padding: f32,
+    color: vec4<f32>,
+};
+@group(0) @binding(0) var<uniform> grid: GridUniform;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // One triangle covering the screen
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.ndc = corner * 2.0 - 1.0;
+    output.position = vec4<f32>(output.ndc, 0.0, 1.0);
+    return output;
+}
+
+// Coverage of lines every `spacing` world units, about a pixel wide
+fn lines(p: vec2<f32>, spacing: f32) -> f32 {
+    let distance = abs(fract(p / spacing + 0.5) - 0.5) * spacing / grid.pixel;
+    return 1.0 - smoothstep(0.0, 1.0, min(distance.x, distance.y));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let view = in.ndc * grid.extent;
+    let p = view + grid.offset;
+    let coverage = max(lines(p, grid.major), 0.5 * grid.minor_alpha * lines(p, grid.minor));
+    var color = vec4<f32>(grid.color.rgb, grid.color.a * coverage);
+    if grid.axes != 0u {
+        let distance = abs(view - grid.origin) / grid.pixel;
+        color = mix(color, vec4<f32>(0.9, 0.3, 0.3, 1.0), 1.0 - smoothstep(0.5, 1.5, distance.y));
+        color = mix(color, vec4<f32>(0.3, 0.9, 0.3, 1.0), 1.0 - smoothstep(0.5, 1.5, distance.x));
+    }
+    return color;
+}